@@ -5,6 +5,9 @@ fn main() {
     let quic = quinn || quiche;
     let h2 = enabled("CARGO_FEATURE_H2_EDGE");
     let edge = quic || h2;
+    let compression = enabled("CARGO_FEATURE_COMPRESSION_GZIP")
+        || enabled("CARGO_FEATURE_COMPRESSION_BR")
+        || enabled("CARGO_FEATURE_COMPRESSION_ZSTD");
     for name in [
         "any_tunnel",
         "any_edge",
@@ -13,6 +16,7 @@ fn main() {
         "quic_quinn",
         "quic_quiche",
         "h2_any",
+        "compression_any",
     ] {
         println!("cargo:rustc-check-cfg=cfg({name})");
     }
@@ -44,6 +48,9 @@ fn main() {
     if h2 && tunnel {
         println!("cargo:rustc-cfg=h2_any");
     }
+    if compression && tunnel && edge {
+        println!("cargo:rustc-cfg=compression_any");
+    }
 }
 
 fn enabled(name: &str) -> bool {
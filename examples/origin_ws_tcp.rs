@@ -1,16 +1,17 @@
-//! Quick tunnel with websocket and TCP origin handlers that echo raw bytes.
+//! Quick tunnel with websocket and TCP origin handlers that echo.
 //!
 //! Run with: cargo run --example origin_ws_tcp
 //!
-//! Every websocket stream is echoed through a loopback TCP connection and
-//! every TCP stream through an in-process stream pair. Both origins hand
-//! the transport a raw stream; the socket and the virtual origin are built
-//! the same way with `Stream::from_io`.
+//! Websocket messages are echoed with the message-level `WebSocket` API,
+//! which handles the handshake and RFC 6455 framing. Every TCP stream is
+//! echoed through an in-process stream pair handed to the transport with
+//! `Stream::from_io`.
 
+use libcfd::origin::websocket::Message;
 use libcfd::{
     Body, EdgeConnector, EdgeOptions, HttpOrigin, HttpResponder, Origin, QuickTunnelOptions,
-    Request, Response, Stream, StreamOrigin, TcpResponder, Transport, Tunnel, WebSocketConnection,
-    WebSocketResponder, create_quick_tunnel, websocket_accept,
+    Request, Response, Stream, StreamOrigin, TcpResponder, Transport, Tunnel, WebSocketResponder,
+    WebSocketUpgrade, create_quick_tunnel,
 };
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_util::compat::TokioAsyncReadCompatExt;
@@ -33,39 +34,26 @@ impl HttpOrigin for HelloOrigin {
     }
 }
 
-/// Answers the websocket handshake and echoes the raw stream through a
-/// loopback TCP connection to `addr`.
+/// Accepts the websocket handshake and echoes text and binary messages.
 #[derive(Clone)]
-struct EchoWebSocketOrigin {
-    address: std::net::SocketAddr,
-}
+struct EchoWebSocketOrigin;
 
 impl StreamOrigin<WebSocketResponder> for EchoWebSocketOrigin {
     fn connect(&self, request: Request, respond: WebSocketResponder) {
-        let address = self.address;
+        let upgrade = match WebSocketUpgrade::from_request(&request) {
+            Ok(upgrade) => upgrade,
+            Err(error) => return respond.fail(error),
+        };
+        let (connection, mut socket) = upgrade.accept();
+        respond.upgrade(connection);
         tokio::spawn(async move {
-            let sock = match tokio::net::TcpStream::connect(address).await {
-                Ok(sock) => sock,
-                Err(e) => return respond.fail(format!("{e}")),
-            };
-            let mut headers = http::HeaderMap::new();
-            let key = request
-                .headers
-                .get("sec-websocket-key")
-                .and_then(|v| v.to_str().ok())
-                .unwrap_or("");
-            headers.insert(
-                "Sec-WebSocket-Accept",
-                websocket_accept(key).parse().unwrap(),
-            );
-            respond.upgrade(WebSocketConnection {
-                response: Response::new(
-                    http::StatusCode::SWITCHING_PROTOCOLS,
-                    headers,
-                    Body::empty(),
-                ),
-                origin: Stream::from_io(sock.compat()),
-            });
+            while let Some(Ok(message)) = socket.recv().await {
+                if let Message::Text(_) | Message::Binary(_) = message
+                    && socket.send(message).await.is_err()
+                {
+                    break;
+                }
+            }
         });
     }
 }
@@ -104,23 +92,8 @@ async fn main() -> Result<(), libcfd::Error> {
     let tunnel = create_quick_tunnel(&options).await?;
     println!("tunnel created: {}", tunnel.url());
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
-    let address = listener.local_addr()?;
-    tokio::spawn(async move {
-        loop {
-            let (mut sock, _) = match listener.accept().await {
-                Ok(v) => v,
-                Err(_) => break,
-            };
-            tokio::spawn(async move {
-                let (mut r, mut w) = sock.split();
-                let _ = tokio::io::copy(&mut r, &mut w).await;
-            });
-        }
-    });
-
     let origin = Origin::http(HelloOrigin)
-        .with_websocket(EchoWebSocketOrigin { address })
+        .with_websocket(EchoWebSocketOrigin)
         .with_tcp(VirtualEchoOrigin);
 
     let connector = EdgeConnector::new(EdgeOptions {
@@ -4,23 +4,32 @@
 mod backoff;
 mod options;
 mod runtime;
+#[cfg(feature = "quick-tunnel")]
+mod supervisor;
 
 use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::edge::Diagnostics;
 use crate::edge::Error as EdgeError;
+use crate::edge::control;
 use crate::edge::discover_edges;
+use crate::edge::dispatch::Dispatch;
 use crate::edge::event::Event;
 #[cfg(feature = "h2-edge")]
 use crate::edge::h2::H2EdgeConnection;
 #[cfg(quic_any)]
 use crate::edge::quic::QuicConnection;
 use crate::error::{Error, Result};
+use crate::metrics::{QuicStatsSource, Readiness};
 use crate::origin::Origin;
-use crate::tunnel::Tunnel;
+use crate::tunnel::{CredentialProvider, Tunnel, same_credentials};
 
 use options::select_transport;
-pub use options::{EdgeOptions, Transport, default_configuration_json};
+pub use options::{EdgeOptions, ErrorPageRenderer, Transport, default_configuration_json};
+#[cfg(feature = "quick-tunnel")]
+pub use supervisor::{NewTunnelCallback, QuickSupervisorOptions};
 
 use backoff::retry_delay;
 use runtime::{EdgeConnection, EdgeRunParameters, ServeAttempt};
@@ -30,12 +39,23 @@ use runtime::{EdgeConnection, EdgeRunParameters, ServeAttempt};
 #[derive(Debug, Clone)]
 pub struct EdgeConnector {
     options: EdgeOptions,
+    readiness: Readiness,
+    quic_stats: QuicStatsSource,
+    diagnostics: Diagnostics,
 }
 
 impl EdgeConnector {
     /// Creates a connector from the given options.
     pub fn new(options: EdgeOptions) -> Self {
-        Self { options }
+        let readiness = Readiness::new(control::connector_id());
+        let quic_stats = QuicStatsSource::default();
+        let diagnostics = Diagnostics::new(&options, readiness.clone(), quic_stats.clone());
+        Self {
+            options,
+            readiness,
+            quic_stats,
+            diagnostics,
+        }
     }
 
     /// The options this connector was created with.
@@ -43,6 +63,18 @@ impl EdgeConnector {
         &self.options
     }
 
+    /// The readiness of this connector's runs (and its clones'), to query
+    /// or serve as a `/ready` endpoint.
+    pub fn readiness(&self) -> Readiness {
+        self.readiness.clone()
+    }
+
+    /// What this connector's runs (and its clones') recorded for a
+    /// diagnostics bundle.
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics.clone()
+    }
+
     /// Runs the tunnel until `shutdown` resolves or a permanent error
     /// occurs, reconnecting with exponential backoff on connection loss.
     pub async fn run(
@@ -51,6 +83,27 @@ impl EdgeConnector {
         origin: Origin,
         shutdown: impl Future<Output = ()> + Send + 'static,
     ) -> Result<()> {
+        self.run_with_credentials(tunnel, origin, shutdown).await
+    }
+
+    /// Runs a tunnel like [`EdgeConnector::run`], asking `credentials` for
+    /// the tunnel's credentials before every registration.
+    ///
+    /// Rotated credentials take effect on the next reconnect, or right
+    /// away with [`EdgeOptions::credential_refresh`]. A permanent
+    /// registration error only ends the run when the provider has no newer
+    /// credentials to retry with. If the provider fails later on, the last
+    /// credentials it gave are kept; only a failure before the first
+    /// registration ends the run.
+    pub async fn run_with_credentials(
+        &self,
+        credentials: impl CredentialProvider,
+        origin: Origin,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<()> {
+        let credentials: Arc<dyn CredentialProvider> = Arc::new(credentials);
+        let mut current = Arc::new(credentials.credentials().await?);
+        let mut fresh = Some(current.clone());
         let shutdown_flag = Arc::new(Event::new());
         tokio::task::spawn({
             let flag = shutdown_flag.clone();
@@ -59,15 +112,14 @@ impl EdgeConnector {
                 flag.fire();
             }
         });
-        let tunnel = Arc::new(tunnel);
-        let origin = Arc::new(origin);
+        let dispatch = Arc::new(Dispatch::new(origin, &self.options));
         #[cfg(quic_any)]
         let mut quic_failures: u8 = 0;
         #[cfg(not(quic_any))]
         let quic_failures: u8 = 0;
         let mut attempt: u32 = 0;
 
-        loop {
+        'reconnect: loop {
             let transport = select_transport(
                 self.options.transport,
                 quic_failures,
@@ -78,8 +130,10 @@ impl EdgeConnector {
                 .options
                 .region
                 .clone()
-                .or_else(|| tunnel.region_override());
-            let edges = match discover_edges(region.as_deref()).await {
+                .or_else(|| current.region_override());
+            let discovered = discover_edges(region.as_deref()).await;
+            self.diagnostics.discovered(region.as_deref(), &discovered);
+            let edges = match discovered {
                 Ok(edges) => edges,
                 Err(e) => {
                     // Discovery failure is retryable: cloudflared keeps retrying rather than aborting the run.
@@ -87,6 +141,9 @@ impl EdgeConnector {
                     attempt = attempt.saturating_add(1);
                     let delay = retry_delay(attempt, self.options.backoff);
                     tracing::debug!(attempt, ?delay, "retrying edge discovery");
+                    if let Some(metrics) = &dispatch.metrics {
+                        metrics.reconnecting(attempt);
+                    }
                     tokio::select! {
                         _ = shutdown_flag.notified() => return Ok(()),
                         _ = tokio::time::sleep(delay) => {}
@@ -99,36 +156,67 @@ impl EdgeConnector {
             #[cfg(not(quic_any))]
             let _quic_broken = false;
             for edge in &edges {
-                let attempt_result = tokio::select! {
+                let tunnel = match fresh.take() {
+                    Some(tunnel) => tunnel,
+                    None => refresh(credentials.as_ref(), &current).await,
+                };
+                current = tunnel.clone();
+                #[cfg(feature = "management-logs")]
+                dispatch.serving(&current);
+                let connection = tokio::select! {
                     _ = shutdown_flag.notified() => return Ok(()),
-                    result = async {
-                        let connection = match build_connection(
-                            transport,
-                            edge.address,
-                            self.options.ca_cert_pem.as_deref(),
-                            self.options.connect_timeout,
-                        )
-                        .await
-                        {
-                            Ok(connection) => connection,
-                            Err(e) => return ServeAttempt::failed(e),
-                        };
-                        connection
-                            .run(EdgeRunParameters {
-                                edge: edge.address,
-                                tunnel: tunnel.clone(),
-                                origin: origin.clone(),
-                                shutdown: shutdown_flag.clone(),
-                                configuration_json: self.options.configuration_json.clone(),
-                                grace_period: self.options.grace_period,
-                                attempt,
-                                on_remote_configuration: self
-                                    .options
-                                    .on_remote_configuration
-                                    .clone(),
-                            })
-                            .await
-                    } => result,
+                    connection = build_connection(
+                        transport,
+                        edge.address,
+                        self.options.ca_cert_pem.as_deref(),
+                        self.options.connect_timeout,
+                    ) => connection,
+                };
+                let drain = Arc::new(Event::new());
+                let attempt_result = match connection {
+                    Err(e) => ServeAttempt::failed(e),
+                    Ok(connection) => {
+                        let mut serving = connection.run(EdgeRunParameters {
+                            edge: edge.address,
+                            tunnel: tunnel.clone(),
+                            dispatch: dispatch.clone(),
+                            shutdown: shutdown_flag.clone(),
+                            drain: drain.clone(),
+                            configuration_json: self.options.configuration_json.clone(),
+                            grace_period: self.options.grace_period,
+                            attempt,
+                            readiness: self.readiness.clone(),
+                            quic_stats: self.quic_stats.clone(),
+                            on_remote_configuration: self.options.on_remote_configuration.clone(),
+                        });
+                        tokio::select! {
+                            _ = shutdown_flag.notified() => return Ok(()),
+                            result = &mut serving => result,
+                            rotated = rotation(
+                                credentials.clone(),
+                                tunnel.clone(),
+                                self.options.credential_refresh,
+                            ) => {
+                                tracing::info!(address = %edge.address, "reconnecting with rotated tunnel credentials");
+                                // The replaced connection unregisters and drains in the background.
+                                drain.fire();
+                                let diagnostics = self.diagnostics.clone();
+                                let address = edge.address;
+                                tokio::spawn(async move {
+                                    let attempt = serving.await;
+                                    diagnostics.connection_ended(
+                                        address,
+                                        transport,
+                                        attempt.registered_at.is_some(),
+                                        &attempt.result,
+                                    );
+                                });
+                                fresh = Some(rotated);
+                                attempt = 0;
+                                continue 'reconnect;
+                            }
+                        }
+                    }
                 };
                 let ServeAttempt {
                     result,
@@ -137,13 +225,28 @@ impl EdgeConnector {
                 } = attempt_result;
                 #[cfg(not(quic_any))]
                 let _ = quic_timed_out;
+                self.diagnostics.connection_ended(
+                    edge.address,
+                    transport,
+                    registered_at.is_some(),
+                    &result,
+                );
                 match result {
                     Ok(()) => return Ok(()),
                     Err(Error::Edge(EdgeError::DuplicateConnection(_))) => {
                         tracing::warn!(address = %edge.address, "duplicate connection, trying next edge");
                         continue;
                     }
-                    Err(e) if e.is_permanent() => return Err(e),
+                    Err(e) if e.is_permanent() => {
+                        // Credentials revoked by a rotation are only fatal if the provider has nothing newer.
+                        let tunnel = refresh(credentials.as_ref(), &current).await;
+                        if Arc::ptr_eq(&tunnel, &current) {
+                            return Err(e);
+                        }
+                        tracing::warn!(address = %edge.address, "registration rejected, retrying with rotated credentials: {e}");
+                        fresh = Some(tunnel);
+                        continue;
+                    }
                     Err(e) => {
                         tracing::warn!(address = %edge.address, ?transport, "edge connection failed: {e}");
                     }
@@ -173,6 +276,9 @@ impl EdgeConnector {
             attempt = attempt.saturating_add(1);
             let delay = retry_delay(attempt, self.options.backoff);
             tracing::debug!(attempt, ?delay, "reconnecting after edge failure");
+            if let Some(metrics) = &dispatch.metrics {
+                metrics.reconnecting(attempt);
+            }
             tokio::select! {
                 _ = shutdown_flag.notified() => return Ok(()),
                 _ = tokio::time::sleep(delay) => {}
@@ -181,6 +287,41 @@ impl EdgeConnector {
     }
 }
 
+/// The provider's current credentials, or `current` when it fails or has
+/// nothing new.
+async fn refresh(credentials: &dyn CredentialProvider, current: &Arc<Tunnel>) -> Arc<Tunnel> {
+    match credentials.credentials().await {
+        Ok(tunnel) if same_credentials(&tunnel, current) => current.clone(),
+        Ok(tunnel) => {
+            tracing::info!("tunnel credentials changed");
+            Arc::new(tunnel)
+        }
+        Err(e) => {
+            tracing::warn!("credential provider failed, keeping the current credentials: {e}");
+            current.clone()
+        }
+    }
+}
+
+/// Resolves with new credentials once the provider's differ from
+/// `current`, asking every `interval`; never resolves without one.
+async fn rotation(
+    credentials: Arc<dyn CredentialProvider>,
+    current: Arc<Tunnel>,
+    interval: Option<Duration>,
+) -> Arc<Tunnel> {
+    let Some(interval) = interval else {
+        return std::future::pending().await;
+    };
+    loop {
+        tokio::time::sleep(interval).await;
+        let tunnel = refresh(credentials.as_ref(), &current).await;
+        if !Arc::ptr_eq(&tunnel, &current) {
+            return tunnel;
+        }
+    }
+}
+
 /// Establishes a connection for a transport and boxes it behind the
 /// [`EdgeConnection`] abstraction.
 async fn build_connection(
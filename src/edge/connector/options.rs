@@ -4,6 +4,19 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use crate::edge::RemoteConfiguration;
+#[cfg(feature = "management-logs")]
+use crate::management::ManagementLogs;
+use crate::metrics::{AccessLog, MetricsRecorder};
+use crate::origin::{FlushPolicy, OriginError, Response};
+
+/// Renders the error page for a failed request; see
+/// [`EdgeOptions::error_page`].
+///
+/// Called with the request headers and the error, on the task serving the
+/// request, so it should return promptly. `Some` is sent to the visitor in
+/// place of the error's own rendering; `None` falls back to it.
+pub type ErrorPageRenderer =
+    Arc<dyn Fn(&http::HeaderMap, &OriginError) -> Option<Response> + Send + Sync>;
 
 /// The transport used for a tunnel connection.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,6 +36,8 @@ pub enum Transport {
 #[derive(Clone)]
 pub struct EdgeOptions {
     /// Transport selection policy.
+    ///
+    /// QUIC drops response trailers, so serving gRPC takes `Transport::H2`.
     pub transport: Transport,
     /// Edge region override (`--region`); `None` uses the default SRV lookup.
     pub region: Option<String>,
@@ -43,14 +58,40 @@ pub struct EdgeOptions {
     /// QUIC failures before `Transport::Auto` falls back to HTTP/2.
     /// Cloudflared's default retry count is 5.
     pub maximum_quic_failures: u8,
+    /// How often a connected run asks its
+    /// [`CredentialProvider`](crate::CredentialProvider) for credentials
+    /// again. When they changed, it reconnects with them right away and
+    /// lets the old connection drain (a rolling reconnect); `None` leaves
+    /// rotated credentials to the next reconnect.
+    pub credential_refresh: Option<Duration>,
     /// Called with each configuration the edge pushes for a
     /// remotely-managed tunnel (e.g. the hostnames routed to it).
     pub on_remote_configuration: Option<Arc<dyn Fn(RemoteConfiguration) + Send + Sync>>,
+    /// Renders the error page for a failed HTTP or websocket request: the
+    /// handler's [`OriginError`] or a dropped responder. Gets the request
+    /// headers (e.g. `Accept` to pick HTML or JSON); returning `None`
+    /// falls back to the error's own rendering.
+    pub error_page: Option<ErrorPageRenderer>,
+    /// When response chunks are flushed to the edge; a body's own
+    /// [`Body::with_flush_policy`](crate::Body::with_flush_policy) wins.
+    /// Defaults to cloudflared's automatic rule.
+    pub flush_policy: FlushPolicy,
+    /// Receives connection, request and stream events for metrics; see
+    /// [`metrics`](crate::metrics).
+    pub metrics: Option<Arc<dyn MetricsRecorder>>,
+    /// Gets an [`AccessRecord`](crate::AccessRecord) for every HTTP
+    /// request, websocket and TCP stream once it is done.
+    pub access_log: Option<Arc<dyn AccessLog>>,
+    /// Answers the dashboard's management requests (live logs, host
+    /// details) ahead of the origin; see [`management`](crate::management).
+    #[cfg(feature = "management-logs")]
+    pub management: Option<ManagementLogs>,
 }
 
 impl std::fmt::Debug for EdgeOptions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("EdgeOptions")
+        let mut debug = f.debug_struct("EdgeOptions");
+        debug
             .field("transport", &self.transport)
             .field("region", &self.region)
             .field("ca_cert_pem", &self.ca_cert_pem)
@@ -59,11 +100,24 @@ impl std::fmt::Debug for EdgeOptions {
             .field("backoff", &self.backoff)
             .field("grace_period", &self.grace_period)
             .field("maximum_quic_failures", &self.maximum_quic_failures)
+            .field("credential_refresh", &self.credential_refresh)
             .field(
                 "on_remote_configuration",
                 &self.on_remote_configuration.as_ref().map(|_| "<callback>"),
             )
-            .finish()
+            .field(
+                "error_page",
+                &self.error_page.as_ref().map(|_| "<callback>"),
+            )
+            .field("flush_policy", &self.flush_policy)
+            .field("metrics", &self.metrics.as_ref().map(|_| "<recorder>"))
+            .field(
+                "access_log",
+                &self.access_log.as_ref().map(|_| "<callback>"),
+            );
+        #[cfg(feature = "management-logs")]
+        debug.field("management", &self.management);
+        debug.finish()
     }
 }
 
@@ -78,7 +132,14 @@ impl Default for EdgeOptions {
             backoff: Duration::from_secs(1),
             grace_period: Duration::from_secs(30),
             maximum_quic_failures: 5,
+            credential_refresh: None,
             on_remote_configuration: None,
+            error_page: None,
+            flush_policy: FlushPolicy::Auto,
+            metrics: None,
+            access_log: None,
+            #[cfg(feature = "management-logs")]
+            management: None,
         }
     }
 }
@@ -5,14 +5,15 @@ use std::future::Future;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "h2-edge")]
 use tokio::sync::Notify;
 
 use crate::edge::configuration::EdgeConfigurationHandler;
 use crate::edge::control::{self, RegistrationOptions};
-use crate::edge::event::Event;
+use crate::edge::dispatch::{ConnectionInfo, Dispatch};
+use crate::edge::event::{self, Event};
 #[cfg(feature = "h2-edge")]
 use crate::edge::h2::{H2EdgeConnection, H2Shared};
 #[cfg(quic_any)]
@@ -21,7 +22,10 @@ use crate::edge::quic::QuicConnection;
 use crate::edge::serve;
 use crate::error::Error;
 use crate::error::Result;
-use crate::origin::Origin;
+#[cfg(quic_any)]
+use crate::metrics::StatsReader;
+use crate::metrics::{QuicStatsSource, Readiness};
+use crate::origin::EdgeProtocol;
 use crate::tunnel::Tunnel;
 
 /// The outcome of a single connection-and-serve attempt.
@@ -29,7 +33,7 @@ pub(crate) struct ServeAttempt {
     pub result: Result<()>,
     /// When the connection registered successfully, used to reset the
     /// reconnect backoff after a healthy connection period.
-    pub registered_at: Option<std::time::Instant>,
+    pub registered_at: Option<Instant>,
     /// Whether the QUIC connection ended with an idle timeout, which
     /// cloudflared treats as an immediate reason to fall back to HTTP/2.
     pub quic_timed_out: bool,
@@ -45,6 +49,11 @@ impl ServeAttempt {
     }
 }
 
+/// How often a registered QUIC connection's statistics are sampled for
+/// metrics.
+#[cfg(quic_any)]
+const STATS_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
 /// Parameters an established edge connection needs to register, serve, and
 /// shut down.
 pub(crate) struct EdgeRunParameters {
@@ -52,11 +61,17 @@ pub(crate) struct EdgeRunParameters {
     #[cfg_attr(not(quic_any), allow(dead_code))]
     pub edge: SocketAddr,
     pub tunnel: Arc<Tunnel>,
-    pub origin: Arc<Origin>,
+    pub dispatch: Arc<Dispatch>,
     pub shutdown: Arc<Event>,
+    /// Winds down just this connection like a shutdown, e.g. once rotated
+    /// credentials have replaced it.
+    pub drain: Arc<Event>,
     pub configuration_json: Vec<u8>,
     pub grace_period: Duration,
     pub attempt: u32,
+    pub readiness: Readiness,
+    #[cfg_attr(not(quic_any), allow(dead_code))]
+    pub quic_stats: QuicStatsSource,
     pub on_remote_configuration:
         Option<Arc<dyn Fn(crate::edge::RemoteConfiguration) + Send + Sync>>,
 }
@@ -64,7 +79,7 @@ pub(crate) struct EdgeRunParameters {
 /// A transport-agnostic edge connection.
 ///
 /// Implementations register via the libcfd-rpc control stream, dispatch
-/// request streams to the shared [`Origin`], keep the connection alive, and
+/// request streams to the shared [`Dispatch`], keep the connection alive, and
 /// drain in-flight work bounded by the grace period on shutdown. The
 /// [`EdgeConnector`](super::EdgeConnector) drives discovery, connection
 /// establishment and retries over this abstraction.
@@ -90,11 +105,14 @@ async fn run_quic(connection: Box<QuicConnection>, parameters: EdgeRunParameters
     let EdgeRunParameters {
         edge,
         tunnel,
-        origin,
+        dispatch,
         shutdown,
+        drain,
         configuration_json,
         grace_period,
         attempt,
+        readiness,
+        quic_stats,
         on_remote_configuration,
     } = parameters;
     // cloudflared sends the edge address as the QUIC `originLocalIp`.
@@ -103,7 +121,9 @@ async fn run_quic(connection: Box<QuicConnection>, parameters: EdgeRunParameters
         number_previous_attempts: attempt.min(u8::MAX as u32) as u8,
         ..Default::default()
     };
-    let (_details, client) = match tokio::time::timeout(
+    let metrics = dispatch.metrics.clone();
+    let registering = Instant::now();
+    let (details, client) = match tokio::time::timeout(
         control::RPC_TIMEOUT,
         control::register(
             &connection,
@@ -119,32 +139,65 @@ async fn run_quic(connection: Box<QuicConnection>, parameters: EdgeRunParameters
         Err(_) => return ServeAttempt::failed(Error::quic("registration timed out")),
     };
     tracing::info!(
-        tunnel_is_remotely_managed = _details.tunnel_is_remotely_managed,
-        location = %_details.location_name,
+        tunnel_is_remotely_managed = details.tunnel_is_remotely_managed,
+        location = %details.location_name,
         "registered with the edge"
     );
-    let registered_at = Some(std::time::Instant::now());
-
+    let registered_at = Some(Instant::now());
     let connection = Arc::new(*connection);
+    let ready = readiness.connected(EdgeProtocol::Quic);
+    let stats_reader: StatsReader = {
+        let connection = connection.clone();
+        Box::new(move || connection.stats())
+    };
+    let published = quic_stats.publish(stats_reader);
+    if let Some(metrics) = &metrics {
+        metrics.connection_registered(EdgeProtocol::Quic, registering.elapsed());
+    }
+    let connection_info = Arc::new(ConnectionInfo::new(
+        EdgeProtocol::Quic,
+        control::CONNECTION_INDEX,
+    ));
+    connection_info.set_location(&details.location_name);
+
     let configuration_handler = Arc::new(EdgeConfigurationHandler::new(on_remote_configuration));
     let mut serve_handle = tokio::spawn(serve::serve_requests(
         connection.clone(),
-        origin,
+        connection_info,
+        dispatch,
         configuration_handler,
     ));
+    let sampler = metrics.clone().map(|metrics| {
+        let connection = connection.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STATS_SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                metrics.quic_stats(&connection.stats());
+            }
+        })
+    });
 
     let serve_result = tokio::select! {
-        _ = shutdown.notified() => None,
+        _ = event::either(&shutdown, &drain) => None,
         result = &mut serve_handle => Some(result),
     };
+    drop(ready);
+    drop(published);
     let _ = control::unregister(client, grace_period).await;
-    let shutdown_fired = shutdown.is_fired();
+    let shutdown_fired = shutdown.is_fired() || drain.is_fired();
     if shutdown_fired {
         // cloudflared waits out the grace period after unregistration so in-flight requests finish before the connection closes.
         tokio::time::sleep(grace_period).await;
     }
     let quic_timed_out = connection.timed_out();
     connection.close();
+    if let Some(sampler) = sampler {
+        sampler.abort();
+    }
+    if let Some(metrics) = &metrics {
+        metrics.connection_closed(EdgeProtocol::Quic);
+    }
     if !shutdown_fired {
         serve_handle.abort();
     }
@@ -175,11 +228,13 @@ impl EdgeConnection for H2EdgeConnection {
 async fn run_h2(connection: Box<H2EdgeConnection>, parameters: EdgeRunParameters) -> ServeAttempt {
     let EdgeRunParameters {
         tunnel,
-        origin,
+        dispatch,
         shutdown,
+        drain,
         configuration_json,
         grace_period,
         attempt,
+        readiness,
         on_remote_configuration,
         ..
     } = parameters;
@@ -188,19 +243,23 @@ async fn run_h2(connection: Box<H2EdgeConnection>, parameters: EdgeRunParameters
         number_previous_attempts: attempt.min(u8::MAX as u32) as u8,
         ..Default::default()
     };
+    let metrics = dispatch.metrics.clone();
     let registered = Event::new();
     let registered_wait = registered.clone();
     let shared = Arc::new(H2Shared {
         tunnel,
-        origin,
+        dispatch,
+        connection: ConnectionInfo::new(EdgeProtocol::Http2, control::CONNECTION_INDEX),
         registration_options: Arc::new(registration_options),
         configuration_json: Arc::new(configuration_json),
         configuration_handler: Arc::new(EdgeConfigurationHandler::new(on_remote_configuration)),
         shutdown: shutdown.clone(),
+        drain: drain.clone(),
         control_shutdown: Arc::new(Notify::new()),
         registered,
         grace_period,
     });
+    let registering = Instant::now();
     let mut serve_handle = tokio::spawn(connection.serve(shared));
 
     // Registration completes inside serve(); wait for it so the reconnect backoff resets on success.
@@ -210,16 +269,31 @@ async fn run_h2(connection: Box<H2EdgeConnection>, parameters: EdgeRunParameters
     })
     .await
     {
-        Ok(()) => Some(std::time::Instant::now()),
+        Ok(()) => Some(Instant::now()),
         Err(_) => None,
     };
+    if registered_at.is_some()
+        && let Some(metrics) = &metrics
+    {
+        metrics.connection_registered(EdgeProtocol::Http2, registering.elapsed());
+    }
+    let mut ready = registered_at.map(|_| readiness.connected(EdgeProtocol::Http2));
+    let closed = || {
+        if registered_at.is_some()
+            && let Some(metrics) = &metrics
+        {
+            metrics.connection_closed(EdgeProtocol::Http2);
+        }
+    };
 
     let serve_result = tokio::select! {
-        _ = shutdown.notified() => {
+        _ = event::either(&shutdown, &drain) => {
+            ready = None;
             // serve() breaks on shutdown and drains in-flight streams plus the unregister RPC; give it the grace period to finish.
             match tokio::time::timeout(grace_period, &mut serve_handle).await {
                 Ok(Ok(Ok(()))) => None,
                 Ok(Ok(Err(e))) => {
+                    closed();
                     return ServeAttempt {
                         result: Err(e),
                         registered_at,
@@ -227,6 +301,7 @@ async fn run_h2(connection: Box<H2EdgeConnection>, parameters: EdgeRunParameters
                     }
                 }
                 Ok(Err(e)) => {
+                    closed();
                     return ServeAttempt {
                         result: Err(Error::h2(format!("serve task failed: {e}"))),
                         registered_at,
@@ -244,7 +319,7 @@ async fn run_h2(connection: Box<H2EdgeConnection>, parameters: EdgeRunParameters
     let result = match serve_result {
         None => Ok(()),
         Some(Ok(Ok(()))) => {
-            if shutdown.is_fired() {
+            if shutdown.is_fired() || drain.is_fired() {
                 Ok(())
             } else {
                 Err(Error::h2("edge closed the connection"))
@@ -253,6 +328,8 @@ async fn run_h2(connection: Box<H2EdgeConnection>, parameters: EdgeRunParameters
         Some(Ok(Err(e))) => Err(e),
         Some(Err(e)) => Err(Error::h2(format!("serve task failed: {e}"))),
     };
+    drop(ready);
+    closed();
     ServeAttempt {
         result,
         registered_at,
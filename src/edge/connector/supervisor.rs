@@ -0,0 +1,195 @@
+//! Quick tunnel supervision: replacing a quick tunnel the service expired
+//! or revoked with a fresh one instead of ending the run.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+use crate::origin::Origin;
+use crate::tunnel::{QuickTunnel, QuickTunnelOptions, Tunnel, create_quick_tunnel};
+
+use super::EdgeConnector;
+use super::backoff::retry_delay;
+
+/// Called with each quick tunnel a supervised run uses; see
+/// [`QuickSupervisorOptions::on_new_tunnel`].
+///
+/// Called on the task driving the run, before the tunnel connects, so it
+/// should return promptly.
+pub type NewTunnelCallback = Arc<dyn Fn(&QuickTunnel) + Send + Sync>;
+
+/// Options for [`EdgeConnector::run_quick_supervised`].
+#[derive(Clone)]
+pub struct QuickSupervisorOptions {
+    /// How quick tunnels are requested.
+    pub quick_tunnel: QuickTunnelOptions,
+    /// Base delay between failed tunnel creations (exponential backoff).
+    pub backoff: Duration,
+    /// Upper bound on the delay between failed tunnel creations.
+    pub maximum_backoff: Duration,
+    /// The least time to wait after the service rate limited a creation.
+    pub rate_limit_backoff: Duration,
+    /// Called with every quick tunnel the run uses, the first one
+    /// included, before it connects; this is how the new hostname is
+    /// learned after a replacement.
+    pub on_new_tunnel: Option<NewTunnelCallback>,
+}
+
+impl std::fmt::Debug for QuickSupervisorOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuickSupervisorOptions")
+            .field("quick_tunnel", &self.quick_tunnel)
+            .field("backoff", &self.backoff)
+            .field("maximum_backoff", &self.maximum_backoff)
+            .field("rate_limit_backoff", &self.rate_limit_backoff)
+            .field(
+                "on_new_tunnel",
+                &self.on_new_tunnel.as_ref().map(|_| "<callback>"),
+            )
+            .finish()
+    }
+}
+
+impl Default for QuickSupervisorOptions {
+    fn default() -> Self {
+        Self {
+            quick_tunnel: QuickTunnelOptions::default(),
+            backoff: Duration::from_secs(1),
+            maximum_backoff: Duration::from_secs(60),
+            rate_limit_backoff: Duration::from_secs(60),
+            on_new_tunnel: None,
+        }
+    }
+}
+
+impl EdgeConnector {
+    /// Runs a quick tunnel like [`EdgeConnector::run`], but when the edge
+    /// permanently rejects it (the service expired or revoked it) requests
+    /// a fresh one with [`create_quick_tunnel`] and carries on serving
+    /// `origin` under the new hostname.
+    ///
+    /// `tunnel` is used first when given, e.g. one cached from an earlier
+    /// run; otherwise one is created. Creation failures are retried with
+    /// backoff until `shutdown` resolves, waiting at least
+    /// [`QuickSupervisorOptions::rate_limit_backoff`] when the service is
+    /// rate limiting. A tunnel that fails within the grace period of
+    /// connecting also counts towards the backoff, so a service handing
+    /// out unusable tunnels is not hammered.
+    pub async fn run_quick_supervised(
+        &self,
+        tunnel: Option<QuickTunnel>,
+        origin: Origin,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+        options: &QuickSupervisorOptions,
+    ) -> Result<()> {
+        // A watch keeps the signal for runs that start after it fired.
+        let (fire, stopped) = watch::channel(false);
+        tokio::task::spawn(async move {
+            shutdown.await;
+            let _ = fire.send(true);
+        });
+        let until_stopped = move || {
+            let mut stopped = stopped.clone();
+            async move {
+                let _ = stopped.wait_for(|stopped| *stopped).await;
+            }
+        };
+        let mut next = tunnel;
+        let mut failures: u32 = 0;
+
+        loop {
+            let tunnel = match next.take() {
+                Some(tunnel) => tunnel,
+                None => match tokio::select! {
+                    _ = until_stopped() => return Ok(()),
+                    created = create_quick_tunnel(&options.quick_tunnel) => created,
+                } {
+                    Ok(tunnel) => tunnel,
+                    Err(e) => {
+                        let delay = creation_delay(is_rate_limited(&e), failures, options);
+                        failures = failures.saturating_add(1);
+                        tracing::warn!(?delay, "quick tunnel creation failed, retrying: {e}");
+                        tokio::select! {
+                            _ = until_stopped() => return Ok(()),
+                            _ = tokio::time::sleep(delay) => {}
+                        }
+                        continue;
+                    }
+                },
+            };
+            tracing::info!(hostname = %tunnel.hostname, "running quick tunnel");
+            if let Some(on_new_tunnel) = &options.on_new_tunnel {
+                on_new_tunnel(&tunnel);
+            }
+            let started = Instant::now();
+            let result = self
+                .run(Tunnel::quick(tunnel), origin.clone(), until_stopped())
+                .await;
+            match result {
+                Err(e) if e.is_permanent() => {
+                    tracing::warn!("quick tunnel rejected by the edge, requesting a new one: {e}");
+                }
+                result => return result,
+            }
+            if started.elapsed() >= self.options.grace_period {
+                failures = 0;
+            } else {
+                let delay = creation_delay(false, failures, options);
+                failures = failures.saturating_add(1);
+                tokio::select! {
+                    _ = until_stopped() => return Ok(()),
+                    _ = tokio::time::sleep(delay) => {}
+                }
+            }
+        }
+    }
+}
+
+fn is_rate_limited(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Tunnel(crate::tunnel::Error::QuickTunnelRateLimited(_))
+    )
+}
+
+/// The wait before the next tunnel creation after `failures` failed ones.
+fn creation_delay(rate_limited: bool, failures: u32, options: &QuickSupervisorOptions) -> Duration {
+    let delay = retry_delay(failures, options.backoff).min(options.maximum_backoff);
+    if rate_limited {
+        delay.max(options.rate_limit_backoff)
+    } else {
+        delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creation_backoff_is_capped() {
+        let options = QuickSupervisorOptions {
+            maximum_backoff: Duration::from_secs(5),
+            ..QuickSupervisorOptions::default()
+        };
+        for failures in 0..40 {
+            assert!(creation_delay(false, failures, &options) <= Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn rate_limits_wait_at_least_the_rate_limit_backoff() {
+        let options = QuickSupervisorOptions {
+            backoff: Duration::ZERO,
+            rate_limit_backoff: Duration::from_secs(30),
+            ..QuickSupervisorOptions::default()
+        };
+        assert_eq!(creation_delay(false, 3, &options), Duration::ZERO);
+        assert_eq!(creation_delay(true, 3, &options), Duration::from_secs(30));
+        assert!(is_rate_limited(&Error::quick_tunnel_rate_limited("1015")));
+        assert!(!is_rate_limited(&Error::quick_tunnel_api("10000: nope")));
+    }
+}
@@ -20,6 +20,10 @@ pub(crate) const DUPLICATE_CONNECTION_CAUSE: &str = "EDUPCONN";
 /// default `--rpc-timeout` of 5 seconds.
 pub(crate) const RPC_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// The connection index registered with the edge; libcfd runs a single
+/// connection per tunnel.
+pub(crate) const CONNECTION_INDEX: u8 = 0;
+
 /// Default feature list advertised at registration, matching
 /// cloudflared's `features/defaultFeatures`.
 const DEFAULT_FEATURES: &[&str] = &[
@@ -64,6 +68,22 @@ fn build_connection_options(options: &RegistrationOptions) -> ConnectionOptions
     }
 }
 
+/// The connector identifier formatted as a UUID, as cloudflared prints it.
+pub(crate) fn connector_id() -> String {
+    let hex: String = connector_client_identifier()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
 fn connector_client_identifier() -> &'static [u8; 16] {
     static CLIENT_IDENTIFIER: std::sync::OnceLock<[u8; 16]> = std::sync::OnceLock::new();
     CLIENT_IDENTIFIER.get_or_init(|| {
@@ -120,7 +140,7 @@ pub(crate) async fn register_on_stream<S: AsyncStream + Unpin>(
     };
     let options = build_connection_options(options);
     let response = client
-        .register_connection(auth, &tunnel_identifier, 0, &options)
+        .register_connection(auth, &tunnel_identifier, CONNECTION_INDEX, &options)
         .await?;
 
     let details = match response {
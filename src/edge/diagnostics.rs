@@ -0,0 +1,417 @@
+//! Diagnostics bundles, like `cloudflared tunnel diag`.
+//!
+//! A connector's [`Diagnostics`] keeps what the bundle cannot recompute
+//! later: the options it was created with (secrets redacted), the last
+//! edge discovery, a bounded history of connection attempts and, with the
+//! `diagnostics-logs` feature, recent log lines. [`Diagnostics::bundle`]
+//! adds what is read fresh (resolver candidates, the system CA bundle,
+//! readiness and QUIC statistics) and returns text files to attach to a
+//! support ticket.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::edge::connector::{EdgeOptions, Transport};
+use crate::edge::discovery::EdgeAddress;
+use crate::edge::{resolver_address, roots};
+use crate::error::Result;
+use crate::metrics::{QuicStatsSource, Readiness};
+use crate::time::rfc3339;
+
+/// Connection events kept for the bundle.
+const HISTORY_LIMIT: usize = 256;
+/// Log lines kept for the bundle.
+#[cfg(feature = "diagnostics-logs")]
+const LOG_LIMIT: usize = 1000;
+
+/// Configuration keys whose values never leave the process.
+const SECRET_KEYS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "credential",
+    "password",
+    "secret",
+    "token",
+];
+
+/// What a connector's runs recorded for diagnostics bundles.
+///
+/// Get it from [`EdgeConnector::diagnostics`](crate::EdgeConnector::diagnostics);
+/// it is shared by the connector's clones and runs and can be bundled at
+/// any time, including while a run is failing.
+#[derive(Clone)]
+pub struct Diagnostics {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    options: String,
+    readiness: Readiness,
+    quic_stats: QuicStatsSource,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    discovery: Option<String>,
+    events: VecDeque<String>,
+    #[cfg(feature = "diagnostics-logs")]
+    logs: VecDeque<String>,
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.state.lock().unwrap();
+        f.debug_struct("Diagnostics")
+            .field("events", &state.events.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Diagnostics {
+    pub(crate) fn new(
+        options: &EdgeOptions,
+        readiness: Readiness,
+        quic_stats: QuicStatsSource,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                options: redacted_options(options),
+                readiness,
+                quic_stats,
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    /// Records the outcome of an edge discovery, replacing the previous one.
+    pub(crate) fn discovered(&self, region: Option<&str>, outcome: &Result<Vec<EdgeAddress>>) {
+        let mut text = format!(
+            "discovered at {} for region {}\n",
+            rfc3339(SystemTime::now()),
+            region.unwrap_or("(default)")
+        );
+        match outcome {
+            Ok(edges) => {
+                for edge in edges {
+                    let _ = writeln!(text, "{}", edge.address);
+                }
+            }
+            Err(e) => {
+                let _ = writeln!(text, "failed: {e}");
+            }
+        }
+        self.inner.state.lock().unwrap().discovery = Some(text);
+    }
+
+    /// Records how a connection attempt to `edge` ended.
+    pub(crate) fn connection_ended(
+        &self,
+        edge: SocketAddr,
+        transport: Transport,
+        registered: bool,
+        result: &Result<()>,
+    ) {
+        let outcome = match (registered, result) {
+            (true, Ok(())) => "registered, then shut down".to_string(),
+            (true, Err(e)) => format!("registered, then failed: {e}"),
+            (false, Ok(())) => "shut down before registering".to_string(),
+            (false, Err(e)) => format!("failed: {e}"),
+        };
+        self.record(format!("{edge} over {transport:?}: {outcome}"));
+    }
+
+    fn record(&self, event: String) {
+        let line = format!("{} {event}", rfc3339(SystemTime::now()));
+        let mut state = self.inner.state.lock().unwrap();
+        if state.events.len() == HISTORY_LIMIT {
+            state.events.pop_front();
+        }
+        state.events.push_back(line);
+    }
+
+    /// A [`tracing_subscriber::Layer`] keeping the most recent log lines
+    /// for the bundle's `logs.txt`; add it to the application's subscriber.
+    #[cfg(feature = "diagnostics-logs")]
+    pub fn log_layer(&self) -> DiagnosticsLayer {
+        DiagnosticsLayer {
+            diagnostics: self.clone(),
+        }
+    }
+
+    /// Collects a bundle from the recorded history and the current state
+    /// of the host and the connector.
+    pub async fn bundle(&self) -> DiagnosticsBundle {
+        let generated = rfc3339(SystemTime::now());
+        let (discovery, events, logs) = {
+            let state = self.inner.state.lock().unwrap();
+            #[cfg(feature = "diagnostics-logs")]
+            let logs = lines(&state.logs, "no log lines recorded yet");
+            #[cfg(not(feature = "diagnostics-logs"))]
+            let logs = "log capture needs the `diagnostics-logs` feature and \
+                        `Diagnostics::log_layer`\n"
+                .to_string();
+            (
+                state
+                    .discovery
+                    .clone()
+                    .unwrap_or_else(|| "no edge discovery yet\n".to_string()),
+                lines(&state.events, "no connection attempts yet"),
+                logs,
+            )
+        };
+        let readiness = &self.inner.readiness;
+        let system = format!(
+            "libcfd {}\nos: {} {}\nconnector id: {}\ngenerated: {generated}\n",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            readiness.connector_id(),
+        );
+        let resolvers: String = resolver_address()
+            .await
+            .iter()
+            .map(|address| format!("{address}\n"))
+            .collect();
+        let tls = match roots::system_ca() {
+            Some((path, bytes)) => format!("system CA bundle: {path} ({} bytes)\n", bytes.len()),
+            None => "system CA bundle: none found; bundled Cloudflare roots only\n".to_string(),
+        };
+        let quic = match self.inner.quic_stats.latest() {
+            Some(stats) => format!("{stats:#?}\n"),
+            None => "no QUIC connection registered\n".to_string(),
+        };
+        DiagnosticsBundle {
+            files: vec![
+                ("system.txt", system),
+                ("options.txt", self.inner.options.clone()),
+                ("discovery.txt", discovery),
+                ("resolvers.txt", resolvers),
+                ("connections.txt", events),
+                ("readiness.json", readiness.to_json()),
+                ("quic-stats.txt", quic),
+                ("tls.txt", tls),
+                ("logs.txt", logs),
+            ],
+        }
+    }
+}
+
+/// The files of a diagnostics bundle, by name.
+#[derive(Debug, Clone)]
+pub struct DiagnosticsBundle {
+    files: Vec<(&'static str, String)>,
+}
+
+impl DiagnosticsBundle {
+    /// The files as `(name, contents)` pairs.
+    pub fn files(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.files
+            .iter()
+            .map(|(name, contents)| (*name, contents.as_str()))
+    }
+
+    /// The contents of the file called `name`.
+    pub fn file(&self, name: &str) -> Option<&str> {
+        self.files()
+            .find(|(file, _)| *file == name)
+            .map(|(_, contents)| contents)
+    }
+
+    /// Writes every file into `directory`, creating it if needed.
+    pub async fn write_to(&self, directory: impl AsRef<Path>) -> io::Result<()> {
+        let directory = directory.as_ref();
+        tokio::fs::create_dir_all(directory).await?;
+        for (name, contents) in self.files() {
+            tokio::fs::write(directory.join(name), contents).await?;
+        }
+        Ok(())
+    }
+}
+
+fn lines(lines: &VecDeque<String>, empty: &str) -> String {
+    if lines.is_empty() {
+        return format!("{empty}\n");
+    }
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// Renders the options a connector was created with, leaving out anything
+/// that could be a secret.
+fn redacted_options(options: &EdgeOptions) -> String {
+    let set = |present: bool| if present { "set" } else { "not set" };
+    let mut out = String::new();
+    let _ = writeln!(out, "transport: {:?}", options.transport);
+    let _ = writeln!(
+        out,
+        "region: {}",
+        options.region.as_deref().unwrap_or("(default)")
+    );
+    let _ = writeln!(
+        out,
+        "ca_cert_pem: {}",
+        options
+            .ca_cert_pem
+            .as_ref()
+            .map_or("not set".to_string(), |pem| format!("{} bytes", pem.len()))
+    );
+    let _ = writeln!(out, "connect_timeout: {:?}", options.connect_timeout);
+    let _ = writeln!(out, "backoff: {:?}", options.backoff);
+    let _ = writeln!(out, "grace_period: {:?}", options.grace_period);
+    let _ = writeln!(
+        out,
+        "maximum_quic_failures: {}",
+        options.maximum_quic_failures
+    );
+    let _ = writeln!(out, "credential_refresh: {:?}", options.credential_refresh);
+    let _ = writeln!(out, "flush_policy: {:?}", options.flush_policy);
+    let _ = writeln!(
+        out,
+        "on_remote_configuration: {}",
+        set(options.on_remote_configuration.is_some())
+    );
+    let _ = writeln!(out, "error_page: {}", set(options.error_page.is_some()));
+    let _ = writeln!(out, "metrics: {}", set(options.metrics.is_some()));
+    let _ = writeln!(out, "access_log: {}", set(options.access_log.is_some()));
+    #[cfg(feature = "management-logs")]
+    let _ = writeln!(out, "management: {}", set(options.management.is_some()));
+    let configuration =
+        match serde_json::from_slice::<serde_json::Value>(&options.configuration_json) {
+            Ok(mut value) => {
+                redact(&mut value);
+                serde_json::to_string_pretty(&value).unwrap_or_default()
+            }
+            Err(_) => format!("<{} bytes, not JSON>", options.configuration_json.len()),
+        };
+    let _ = writeln!(out, "configuration_json: {configuration}");
+    out
+}
+
+/// Replaces the values of secret-looking keys, at any depth.
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+                    *value = serde_json::Value::String("REDACTED".into());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// A [`tracing_subscriber::Layer`] feeding a connector's
+/// [`Diagnostics`] with recent log lines.
+#[cfg(feature = "diagnostics-logs")]
+#[derive(Debug, Clone)]
+pub struct DiagnosticsLayer {
+    diagnostics: Diagnostics,
+}
+
+#[cfg(feature = "diagnostics-logs")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for DiagnosticsLayer {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _context: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let metadata = event.metadata();
+        let mut line = LogLine(format!(
+            "{} {} {}:",
+            rfc3339(SystemTime::now()),
+            metadata.level(),
+            metadata.target()
+        ));
+        event.record(&mut line);
+        let mut state = self.diagnostics.inner.state.lock().unwrap();
+        if state.logs.len() == LOG_LIMIT {
+            state.logs.pop_front();
+        }
+        state.logs.push_back(line.0);
+    }
+}
+
+/// Appends an event's message and fields to one log line.
+#[cfg(feature = "diagnostics-logs")]
+struct LogLine(String);
+
+#[cfg(feature = "diagnostics-logs")]
+impl tracing::field::Visit for LogLine {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn options_leave_out_secrets() {
+        let options = EdgeOptions {
+            configuration_json: br#"{"ingress":[{"service":"http://localhost:8080","originRequest":{"access":{"token":"abc"},"httpHostHeader":"app"}}],"warp-routing":{"secret":"s"}}"#.to_vec(),
+            ca_cert_pem: Some(b"-----BEGIN CERTIFICATE-----".to_vec()),
+            ..EdgeOptions::default()
+        };
+        let text = redacted_options(&options);
+        assert!(!text.contains("abc"));
+        assert!(!text.contains("\"s\""));
+        assert!(text.contains("\"token\": \"REDACTED\""));
+        assert!(text.contains("http://localhost:8080"));
+        assert!(text.contains("ca_cert_pem: 27 bytes"));
+    }
+
+    #[tokio::test]
+    async fn bundles_recorded_history() {
+        let diagnostics = Diagnostics::new(
+            &EdgeOptions::default(),
+            Readiness::new("0011".into()),
+            QuicStatsSource::default(),
+        );
+        let edge: SocketAddr = "198.41.192.7:7844".parse().unwrap();
+        diagnostics.discovered(None, &Ok(vec![EdgeAddress { address: edge }]));
+        diagnostics.connection_ended(
+            edge,
+            EdgeOptions::default().transport,
+            false,
+            &Err(Error::edge_discovery("unreachable")),
+        );
+        let bundle = diagnostics.bundle().await;
+        assert!(
+            bundle
+                .file("discovery.txt")
+                .unwrap()
+                .contains("198.41.192.7:7844")
+        );
+        let connections = bundle.file("connections.txt").unwrap();
+        assert!(connections.contains("198.41.192.7:7844 over"));
+        assert!(connections.contains("failed: "));
+        assert!(bundle.file("resolvers.txt").unwrap().contains("1.1.1.1:53"));
+        assert!(
+            bundle
+                .file("readiness.json")
+                .unwrap()
+                .contains("\"status\":503")
+        );
+        assert_eq!(
+            bundle.file("quic-stats.txt"),
+            Some("no QUIC connection registered\n")
+        );
+    }
+}
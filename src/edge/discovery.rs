@@ -89,7 +89,9 @@ async fn srv_lookup(service: &str, proto: &str, name: &str) -> io::Result<Vec<(S
     Ok(Vec::new())
 }
 
-async fn resolver_address() -> Vec<SocketAddr> {
+/// The DNS servers SRV lookups try, in order: `/etc/resolv.conf`, then
+/// 1.1.1.1.
+pub(crate) async fn resolver_address() -> Vec<SocketAddr> {
     let mut candidates = Vec::new();
     if let Ok(contents) = tokio::fs::read_to_string("/etc/resolv.conf").await {
         for line in contents.lines() {
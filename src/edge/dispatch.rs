@@ -0,0 +1,261 @@
+//! What a tunnel run dispatches edge streams to: the origin handlers plus
+//! the connector-wide hooks both transports apply around them.
+
+use std::sync::OnceLock;
+#[cfg(quic_any)]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::edge::connector::{EdgeOptions, ErrorPageRenderer};
+#[cfg(feature = "management-logs")]
+use crate::management::ServedTunnel;
+use crate::metrics::{Recorder, RequestKind, RequestMetric, SharedAccessLog};
+use crate::origin::{
+    EdgeProtocol, FlushPolicy, Origin, OriginError, RequestContext, Response, StreamSummary,
+};
+
+/// The origin handlers and serving hooks shared by every connection of a
+/// run.
+pub(crate) struct Dispatch {
+    pub origin: Origin,
+    pub error_page: Option<ErrorPageRenderer>,
+    pub flush_policy: FlushPolicy,
+    pub metrics: Option<Recorder>,
+    pub access_log: Option<SharedAccessLog>,
+    #[cfg(feature = "management-logs")]
+    served_tunnel: ServedTunnel,
+}
+
+impl Dispatch {
+    pub(crate) fn new(origin: Origin, options: &EdgeOptions) -> Self {
+        #[cfg(feature = "management-logs")]
+        let served_tunnel = ServedTunnel::default();
+        #[cfg(feature = "management-logs")]
+        let origin = match &options.management {
+            Some(management) => management.route(origin, served_tunnel.clone()),
+            None => origin,
+        };
+        Self {
+            origin,
+            error_page: options.error_page.clone(),
+            flush_policy: options.flush_policy,
+            metrics: options.metrics.clone(),
+            access_log: options.access_log.clone(),
+            #[cfg(feature = "management-logs")]
+            served_tunnel,
+        }
+    }
+
+    /// Records the tunnel the run registers next, which management
+    /// requests must be authorized for.
+    #[cfg(feature = "management-logs")]
+    pub(crate) fn serving(&self, tunnel: &crate::tunnel::Tunnel) {
+        *self.served_tunnel.lock().unwrap() = tunnel.tunnel_identifier().to_string();
+    }
+
+    /// Starts reporting a request the edge just sent.
+    pub(crate) fn request_metric(&self, kind: RequestKind) -> RequestMetric {
+        RequestMetric::start(self.metrics.as_ref(), kind).logging(self.access_log.as_ref())
+    }
+
+    /// Reports a finished websocket or TCP stream to the origin's hook and
+    /// the metrics recorder.
+    pub(crate) fn stream_completed(&self, summary: &StreamSummary) {
+        self.origin.stream_completed(summary);
+        if let Some(metrics) = &self.metrics {
+            metrics.stream_finished(summary);
+        }
+    }
+
+    /// Whether every chunk of `response` is flushed to the edge as soon as
+    /// the origin produces it.
+    pub(crate) fn flushes(&self, response: &Response) -> bool {
+        response
+            .body
+            .flush_policy()
+            .unwrap_or(self.flush_policy)
+            .flushes(&response.headers)
+    }
+
+    /// The request headers the error-page renderer needs, captured before
+    /// the request moves into the handler; empty when no renderer is set.
+    pub(crate) fn error_page_headers(&self, headers: &http::HeaderMap) -> http::HeaderMap {
+        if self.error_page.is_some() {
+            headers.clone()
+        } else {
+            http::HeaderMap::new()
+        }
+    }
+
+    /// Turns a failed origin outcome into the response the visitor sees.
+    ///
+    /// The renderer gets the first chance; otherwise the error renders
+    /// itself. `Err` carries the message of a bare `502`, which the
+    /// transports report the way cloudflared does.
+    pub(crate) fn error_response(
+        &self,
+        request_headers: &http::HeaderMap,
+        error: OriginError,
+    ) -> std::result::Result<Response, String> {
+        if let Some(render) = &self.error_page
+            && let Some(response) = render(request_headers, &error)
+        {
+            return Ok(response);
+        }
+        error.into_response()
+    }
+}
+
+/// One edge connection as its request streams see it.
+pub(crate) struct ConnectionInfo {
+    protocol: EdgeProtocol,
+    index: u8,
+    /// The registered edge location, set once registration reports it.
+    location: OnceLock<String>,
+    /// Whether a response on the connection has lost its trailers.
+    #[cfg(quic_any)]
+    trailers_dropped: AtomicBool,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(protocol: EdgeProtocol, index: u8) -> Self {
+        Self {
+            protocol,
+            index,
+            location: OnceLock::new(),
+            #[cfg(quic_any)]
+            trailers_dropped: AtomicBool::new(false),
+        }
+    }
+
+    /// Records that a response lost its trailers; `true` the first time on
+    /// this connection, so the loss is reported loudly once.
+    #[cfg(quic_any)]
+    pub(crate) fn dropped_trailers(&self) -> bool {
+        !self.trailers_dropped.swap(true, Ordering::Relaxed)
+    }
+
+    /// Records the location the edge reported at registration.
+    pub(crate) fn set_location(&self, location: &str) {
+        if !location.is_empty() {
+            let _ = self.location.set(location.to_string());
+        }
+    }
+
+    /// The context attached to a request arriving on `stream_id`.
+    pub(crate) fn request_context(
+        &self,
+        stream_id: u64,
+        headers: &http::HeaderMap,
+    ) -> RequestContext {
+        RequestContext::new(
+            self.protocol,
+            self.index,
+            self.location.get().cloned(),
+            stream_id,
+            crate::edge::trace::trace_id(headers),
+            headers,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::origin::{Body, HttpResponder, Request};
+
+    fn dispatch(error_page: Option<ErrorPageRenderer>) -> Dispatch {
+        let origin = Origin::http(|_request: Request, respond: HttpResponder| drop(respond));
+        let options = EdgeOptions {
+            error_page,
+            ..EdgeOptions::default()
+        };
+        Dispatch::new(origin, &options)
+    }
+
+    #[test]
+    fn bare_failure_without_renderer_stays_an_edge_error() {
+        let dispatch = dispatch(None);
+        let outcome = dispatch.error_response(&http::HeaderMap::new(), "origin down".into());
+        assert_eq!(outcome.unwrap_err(), "origin down");
+    }
+
+    #[test]
+    fn renderer_brands_bare_failures() {
+        let renderer: ErrorPageRenderer = Arc::new(|headers, error| {
+            let json = headers
+                .get(http::header::ACCEPT)
+                .is_some_and(|accept| accept == "application/json");
+            let body = if json {
+                format!(r#"{{"error":"{}"}}"#, error.message())
+            } else {
+                format!("<h1>{}</h1>", error.status())
+            };
+            Some(Response::new(
+                error.status(),
+                http::HeaderMap::new(),
+                Body::from_bytes(body.into_bytes()),
+            ))
+        });
+        let dispatch = dispatch(Some(renderer));
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::ACCEPT, "application/json".parse().unwrap());
+        let response = dispatch
+            .error_response(&headers, OriginError::no_response())
+            .unwrap();
+        assert_eq!(response.status, http::StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            dispatch.error_page_headers(&headers)[http::header::ACCEPT],
+            "application/json"
+        );
+    }
+
+    #[test]
+    fn request_context_picks_up_the_registered_location() {
+        let connection = ConnectionInfo::new(EdgeProtocol::Quic, 0);
+        assert!(
+            connection
+                .request_context(4, &http::HeaderMap::new())
+                .colo()
+                .is_none()
+        );
+        connection.set_location("lax08");
+        let context = connection.request_context(8, &http::HeaderMap::new());
+        assert_eq!(context.colo(), Some("lax08"));
+        assert_eq!(context.stream_id(), 8);
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert("cf-trace-id", "a1b2c3:ff:0:1".parse().unwrap());
+        let context = connection.request_context(9, &headers);
+        assert_eq!(context.trace_id(), Some("00000000000000000000000000a1b2c3"));
+    }
+
+    #[cfg(quic_any)]
+    #[test]
+    fn dropped_trailers_are_reported_once_per_connection() {
+        let connection = ConnectionInfo::new(EdgeProtocol::Quic, 0);
+        assert!(connection.dropped_trailers());
+        assert!(!connection.dropped_trailers());
+        assert!(ConnectionInfo::new(EdgeProtocol::Quic, 0).dropped_trailers());
+    }
+
+    #[test]
+    fn body_flush_policy_overrides_the_connector() {
+        let dispatch = dispatch(None);
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::CONTENT_LENGTH, "5".parse().unwrap());
+        let response = Response::new(
+            http::StatusCode::OK,
+            headers.clone(),
+            Body::from_bytes(b"hello".to_vec()),
+        );
+        assert!(!dispatch.flushes(&response));
+        let response = Response::new(
+            http::StatusCode::OK,
+            headers,
+            Body::from_bytes(b"hello".to_vec()).with_flush_policy(FlushPolicy::Always),
+        );
+        assert!(dispatch.flushes(&response));
+    }
+}
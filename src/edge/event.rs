@@ -43,6 +43,15 @@ impl Event {
     }
 }
 
+/// Resolves once either event fires, e.g. a run's shutdown or a single
+/// connection's drain.
+pub(crate) async fn either(first: &Event, second: &Event) {
+    tokio::select! {
+        _ = first.notified() => {}
+        _ = second.notified() => {}
+    }
+}
+
 impl Clone for Event {
     fn clone(&self) -> Self {
         Self {
@@ -8,6 +8,7 @@
 
 use base64::Engine as _;
 
+use crate::edge::trace::TRACING_HEADER;
 use crate::origin::Response;
 
 /// The header carrying base64-serialized origin user headers.
@@ -23,8 +24,6 @@ pub(crate) const WEBSOCKET_UPGRADE: &str = "websocket";
 pub(crate) const CONTROL_STREAM_UPGRADE: &str = "control-stream";
 pub(crate) const CONFIGURATION_UPDATE: &str = "update-configuration";
 
-const TRACING_INTERNAL_HEADER: &str = "cf-int-cloudflared-tracing";
-
 /// Serializes HTTP/1 headers as `[base64(name):base64(value);]`, exactly like
 /// cloudflared's `SerializeHeaders`.
 pub(crate) fn serialize_headers(headers: &[(String, String)]) -> String {
@@ -84,7 +83,7 @@ pub(crate) fn encode_response_headers(response: &Response) -> http::HeaderMap {
         if lower == "content-length" {
             headers.append(name.clone(), http::HeaderValue::from_str(&value).unwrap());
         }
-        if lower == TRACING_INTERNAL_HEADER {
+        if lower == TRACING_HEADER {
             headers.insert(
                 "Cf-Int-Cloudflared-Tracing",
                 http::HeaderValue::from_str(&value).unwrap(),
@@ -21,9 +21,9 @@ use tokio::net::TcpStream;
 
 use crate::edge::configuration::EdgeConfigurationHandler;
 use crate::edge::control::{self, RegistrationOptions};
-use crate::edge::event::Event;
+use crate::edge::dispatch::{ConnectionInfo, Dispatch};
+use crate::edge::event::{self, Event};
 use crate::error::{Error, Result};
-use crate::origin::Origin;
 use crate::tunnel::Tunnel;
 
 pub(crate) use crate::origin::websocket_accept;
@@ -40,11 +40,14 @@ const EDGE_H2_SNI: &str = "h2.cftunnel.com";
 /// State shared between the HTTP/2 connection task and per-stream tasks.
 pub(crate) struct H2Shared {
     pub tunnel: Arc<Tunnel>,
-    pub origin: Arc<Origin>,
+    pub dispatch: Arc<Dispatch>,
+    pub connection: ConnectionInfo,
     pub registration_options: Arc<RegistrationOptions>,
     pub configuration_json: Arc<Vec<u8>>,
     pub configuration_handler: Arc<EdgeConfigurationHandler>,
     pub shutdown: Arc<Event>,
+    /// Fires when only this connection should wind down, like a shutdown.
+    pub drain: Arc<Event>,
     pub control_shutdown: Arc<tokio::sync::Notify>,
     /// Fires once registration completes on the control stream.
     pub registered: Event,
@@ -162,7 +165,7 @@ impl H2EdgeConnection {
                     stream_tasks.abort_all();
                     return Err(Error::h2("registration timed out"));
                 }
-                _ = shared.shutdown.notified() => {
+                _ = event::either(&shared.shutdown, &shared.drain) => {
                     shared.control_shutdown.notify_waiters();
                     break;
                 }
@@ -38,7 +38,10 @@ pub(crate) async fn handle_control_stream(
     )
     .await;
     let client = match result {
-        Ok((_details, client)) => client,
+        Ok((details, client)) => {
+            shared.connection.set_location(&details.location_name);
+            client
+        }
         Err(e) => {
             let _ = registration_tx.send(Err(e));
             return Ok(());
@@ -12,17 +12,22 @@ use std::task::{Context, Poll};
 
 use bytes::Bytes;
 use futures_io::{AsyncRead, AsyncWrite};
+use futures_util::Stream;
 use h2::RecvStream;
 
+use crate::origin::TrailerSender;
+
 /// Reads the request body of an HTTP/2 stream.
 ///
 /// Releases flow-control capacity as chunks are consumed so the edge can
-/// keep sending.
+/// keep sending. With a [`TrailerSender`] attached, the request trailers are
+/// read after the last data frame and handed to the body.
 pub(crate) struct ReceiveStreamReader {
     receive: RecvStream,
     chunk: Option<Bytes>,
     position: usize,
     eof: bool,
+    trailers: Option<TrailerSender>,
 }
 
 impl ReceiveStreamReader {
@@ -32,6 +37,73 @@ impl ReceiveStreamReader {
             chunk: None,
             position: 0,
             eof: false,
+            trailers: None,
+        }
+    }
+
+    /// Forwards the request trailers to `trailers` once the data ends.
+    pub(crate) fn with_trailers(mut self, trailers: TrailerSender) -> Self {
+        self.trailers = Some(trailers);
+        self
+    }
+
+    /// Reads the trailers after the last data frame, when a sender waits
+    /// for them.
+    fn poll_trailers(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.trailers.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+        match Pin::new(&mut self.receive).poll_trailers(cx) {
+            Poll::Ready(Ok(Some(trailers))) => {
+                if let Some(sender) = self.trailers.take() {
+                    sender.send(trailers);
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Ok(None)) => {
+                self.trailers = None;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(e)) => {
+                self.trailers = None;
+                Poll::Ready(Err(io::Error::other(e)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Yields the received data frames as they are, without copying. Capacity
+/// for a frame is released when it is handed on.
+impl Stream for ReceiveStreamReader {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.eof {
+                return match self.poll_trailers(cx) {
+                    Poll::Ready(Ok(())) => Poll::Ready(None),
+                    Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+            if let Some(chunk) = self.chunk.take() {
+                let rest = chunk.slice(self.position..);
+                self.position = 0;
+                if let Err(e) = self.receive.flow_control().release_capacity(chunk.len()) {
+                    return Poll::Ready(Some(Err(io::Error::other(e))));
+                }
+                if !rest.is_empty() {
+                    return Poll::Ready(Some(Ok(rest)));
+                }
+                continue;
+            }
+            match Pin::new(&mut self.receive).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.chunk = Some(chunk),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(io::Error::other(e)))),
+                Poll::Ready(None) => self.eof = true,
+                Poll::Pending => return Poll::Pending,
+            }
         }
     }
 }
@@ -44,7 +116,7 @@ impl AsyncRead for ReceiveStreamReader {
     ) -> Poll<io::Result<usize>> {
         loop {
             if self.eof {
-                return Poll::Ready(Ok(0));
+                return self.poll_trailers(cx).map_ok(|()| 0);
             }
             if let Some(chunk) = self.chunk.take() {
                 if self.position < chunk.len() {
@@ -72,10 +144,7 @@ impl AsyncRead for ReceiveStreamReader {
                 Poll::Ready(Some(Err(e))) => {
                     return Poll::Ready(Err(io::Error::other(e)));
                 }
-                Poll::Ready(None) => {
-                    self.eof = true;
-                    return Poll::Ready(Ok(0));
-                }
+                Poll::Ready(None) => self.eof = true,
                 Poll::Pending => return Poll::Pending,
             }
         }
@@ -115,8 +184,43 @@ impl AsyncWrite for SendStreamWriter {
         if buffer.is_empty() {
             return Poll::Ready(Ok(0));
         }
+        let n = match self.poll_send_capacity(cx, buffer.len()) {
+            Poll::Ready(Ok(n)) => n,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        match self
+            .send
+            .send_data(Bytes::copy_from_slice(&buffer[..n]), false)
+        {
+            Ok(()) => Poll::Ready(Ok(n)),
+            Err(e) => Poll::Ready(Err(io::Error::other(e))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.closed {
+            self.closed = true;
+            let _ = self.send.send_data(Bytes::new(), true);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl SendStreamWriter {
+    /// Waits for send capacity and returns how many of `wanted` bytes may be
+    /// sent now.
+    fn poll_send_capacity(
+        &mut self,
+        cx: &mut Context<'_>,
+        wanted: usize,
+    ) -> Poll<io::Result<usize>> {
         if self.send.capacity() == 0 {
-            self.send.reserve_capacity(buffer.len());
+            self.send.reserve_capacity(wanted);
             match Pin::new(&mut self.send).poll_capacity(cx) {
                 Poll::Ready(Some(Ok(_))) => {}
                 Poll::Ready(None) => {
@@ -131,26 +235,34 @@ impl AsyncWrite for SendStreamWriter {
                 Poll::Pending => return Poll::Pending,
             }
         }
-        let n = std::cmp::min(buffer.len(), self.send.capacity());
-        match self
-            .send
-            .send_data(Bytes::copy_from_slice(&buffer[..n]), false)
-        {
-            Ok(()) => Poll::Ready(Ok(n)),
-            Err(e) => Poll::Ready(Err(io::Error::other(e))),
-        }
+        Poll::Ready(Ok(std::cmp::min(wanted, self.send.capacity())))
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Poll::Ready(Ok(()))
+    /// Sends a chunk as data frames, splitting it to the granted capacity
+    /// rather than copying it.
+    pub(crate) async fn send_chunk(&mut self, mut chunk: Bytes) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "stream already closed",
+            ));
+        }
+        while !chunk.is_empty() {
+            let n = std::future::poll_fn(|cx| self.poll_send_capacity(cx, chunk.len())).await?;
+            self.send
+                .send_data(chunk.split_to(n), false)
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
     }
 
-    fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        if !self.closed {
-            self.closed = true;
-            let _ = self.send.send_data(Bytes::new(), true);
+    /// Ends the stream with a trailers frame instead of an empty data frame.
+    pub(crate) fn close_with_trailers(&mut self, trailers: http::HeaderMap) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
         }
-        Poll::Ready(Ok(()))
+        self.closed = true;
+        self.send.send_trailers(trailers).map_err(io::Error::other)
     }
 }
 
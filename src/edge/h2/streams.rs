@@ -1,15 +1,20 @@
 //! Per-stream request handling for the HTTP/2 edge connection.
 
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use bytes::Bytes;
 use futures_util::io::{AsyncReadExt, AsyncWriteExt};
 use h2::RecvStream;
 use h2::server::SendResponse;
+use tracing::Instrument as _;
 
+use crate::edge::trace::RequestTrace;
 use crate::error::{Error, Result};
+use crate::metrics::RequestKind;
 use crate::origin::{
-    Body, HttpResponder, Request, Response, TcpResponder, WebSocketResponder, pump, wait_outcome,
+    Body, CancellationToken, HttpResponder, LimitBreach, Request, Response, ResponseWrites,
+    StreamKind, StreamSummary, TcpResponder, WebSocketResponder, pump, wait_outcome_unless,
 };
 
 use libcfd_rpc::CloudflaredHandler;
@@ -37,25 +42,78 @@ pub(crate) async fn handle_stream(
 
 async fn handle_h2_http(
     request: http::Request<RecvStream>,
-    respond: SendResponse<Bytes>,
+    mut respond: SendResponse<Bytes>,
     shared: Arc<H2Shared>,
 ) -> Result<()> {
+    let mut metric = shared.dispatch.request_metric(RequestKind::Http);
     let (parts, body) = request.into_parts();
     let mut headers = parts.headers;
     headers.remove(INTERNAL_UPGRADE_HEADER);
-    let request = Request::new(
-        parts.method,
-        parts.uri,
-        headers,
-        Body::from_reader(ReceiveStreamReader::new(body)),
-    );
-    let (responder, receiver) = HttpResponder::channel();
-    shared.origin.http.handle(request, responder);
-    let response = match wait_outcome(receiver).await {
+    let mut trace = RequestTrace::from_headers(&headers);
+    let error_page_headers = shared.dispatch.error_page_headers(&headers);
+    let context = shared
+        .connection
+        .request_context(stream_id(&body), &headers);
+    let mut request = Request::new(parts.method, parts.uri, headers, Body::empty());
+    request.extensions.insert(context);
+    metric.request(&request);
+    let method = request.method.clone();
+    let cancellation = attach_cancellation(&mut request);
+    let mut guard = cancellation.drop_guard();
+    let limits = &shared.dispatch.origin.limits;
+    let breach = LimitBreach::default();
+    let outcome = match limits.check_headers(&request.headers) {
+        Err(error) => Err(error),
+        Ok(()) => {
+            request.body = Body::from_stream_with_trailers(|trailers| {
+                let chunks = ReceiveStreamReader::new(body).with_trailers(trailers);
+                limits.limit_body(metric.count_received(chunks), &breach)
+            });
+            let (responder, receiver) = HttpResponder::channel();
+            let origin = &shared.dispatch.origin.http;
+            let origin_started = SystemTime::now();
+            let span = trace
+                .as_ref()
+                .map_or_else(tracing::Span::none, RequestTrace::span);
+            let outcome = async {
+                origin.handle(request, responder);
+                wait_outcome_unless(receiver, reset(&mut respond), &cancellation).await
+            }
+            .instrument(span)
+            .await;
+            let Some(outcome) = outcome else {
+                tracing::debug!("edge reset the request stream");
+                return Ok(());
+            };
+            if let Some(trace) = &mut trace {
+                trace.record("origin", origin_started);
+            }
+            breach.error().map_or(outcome, Err)
+        }
+    };
+    let mut response = match outcome {
         Ok(response) => response,
-        Err(message) => return write_h2_error(respond, &message).await,
+        Err(error) => {
+            guard.disarm();
+            metric.origin_failed();
+            match shared.dispatch.error_response(&error_page_headers, error) {
+                Ok(response) => response,
+                Err(message) => {
+                    metric.status(http::StatusCode::BAD_GATEWAY);
+                    return write_h2_error(respond, &message).await;
+                }
+            }
+        }
     };
-    write_h2_response(respond, response).await
+    metric.status(response.status);
+    if let Some(trace) = trace {
+        trace.finish(&method, response.status, &mut response.headers);
+    }
+    let flush = shared.dispatch.flushes(&response);
+    let sent = write_h2_response(respond, response, flush).await?;
+    metric.responded(sent);
+    guard.disarm();
+    Ok(())
 }
 
 async fn handle_h2_websocket(
@@ -63,26 +121,79 @@ async fn handle_h2_websocket(
     mut respond: SendResponse<Bytes>,
     shared: Arc<H2Shared>,
 ) -> Result<()> {
-    let Some(websocket) = &shared.origin.websocket else {
+    let mut metric = shared.dispatch.request_metric(RequestKind::WebSocket);
+    let Some(websocket) = &shared.dispatch.origin.websocket else {
+        metric.origin_failed();
+        metric.status(http::StatusCode::BAD_GATEWAY);
         return write_h2_error(respond, "no websocket origin handler").await;
     };
     let (parts, body) = request.into_parts();
     let mut headers = parts.headers;
     headers.remove(INTERNAL_UPGRADE_HEADER);
-    let request = Request::new(parts.method, parts.uri, headers, Body::empty());
-    let (responder, receiver) = WebSocketResponder::channel();
-    websocket.connect(request, responder);
-    let connection = match wait_outcome(receiver).await {
+    let error_page_headers = shared.dispatch.error_page_headers(&headers);
+    let context = shared
+        .connection
+        .request_context(stream_id(&body), &headers);
+    let summary = StreamSummary::new(StreamKind::WebSocket, Some(context.clone()));
+    let mut request = Request::new(parts.method, parts.uri, headers, Body::empty());
+    request.extensions.insert(context);
+    metric.request(&request);
+    let cancellation = attach_cancellation(&mut request);
+    let mut guard = cancellation.drop_guard();
+    let outcome = match shared
+        .dispatch
+        .origin
+        .limits
+        .check_headers(&request.headers)
+    {
+        Err(error) => Err(error),
+        Ok(()) => {
+            let (responder, receiver) = WebSocketResponder::channel();
+            websocket.connect(request, responder);
+            let Some(outcome) =
+                wait_outcome_unless(receiver, reset(&mut respond), &cancellation).await
+            else {
+                tracing::debug!("edge reset the websocket stream");
+                return Ok(());
+            };
+            outcome
+        }
+    };
+    let connection = match outcome {
         Ok(connection) => connection,
-        Err(message) => return write_h2_error(respond, &message).await,
+        Err(error) => {
+            guard.disarm();
+            metric.origin_failed();
+            return match shared.dispatch.error_response(&error_page_headers, error) {
+                Ok(response) => {
+                    metric.status(response.status);
+                    let flush = shared.dispatch.flushes(&response);
+                    write_h2_response(respond, response, flush).await?;
+                    Ok(())
+                }
+                Err(message) => {
+                    metric.status(http::StatusCode::BAD_GATEWAY);
+                    write_h2_error(respond, &message).await
+                }
+            };
+        }
     };
+    metric.status(connection.response.status);
     let send = write_h2_headers(&mut respond, &connection.response)?;
-    pump(
+    // h2 resets a stream whose handles drop before it ends, so a stream the
+    // pump tore down on a timeout needs no explicit reset.
+    let summary = pump(
         connection.origin,
         ReceiveStreamReader::new(body),
         SendStreamWriter::new(send),
+        &shared.dispatch.origin.streams,
+        summary,
     )
-    .await
+    .await;
+    metric.streamed(&summary);
+    shared.dispatch.stream_completed(&summary);
+    guard.disarm();
+    Ok(())
 }
 
 async fn handle_h2_tcp(
@@ -90,17 +201,38 @@ async fn handle_h2_tcp(
     mut respond: SendResponse<Bytes>,
     shared: Arc<H2Shared>,
 ) -> Result<()> {
-    let Some(tcp) = &shared.origin.tcp else {
+    let mut metric = shared.dispatch.request_metric(RequestKind::Tcp);
+    let Some(tcp) = &shared.dispatch.origin.tcp else {
+        metric.origin_failed();
+        metric.status(http::StatusCode::BAD_GATEWAY);
         return write_h2_error(respond, "no tcp origin handler").await;
     };
     let (parts, body) = request.into_parts();
     let host = request_host(&parts);
-    let request = Request::tcp(&host);
+    let mut request = Request::tcp(&host);
+    let context = shared
+        .connection
+        .request_context(stream_id(&body), &parts.headers);
+    let summary = StreamSummary::new(StreamKind::Tcp, Some(context.clone()));
+    request.extensions.insert(context);
+    metric.request(&request);
+    let cancellation = attach_cancellation(&mut request);
+    let mut guard = cancellation.drop_guard();
     let (responder, receiver) = TcpResponder::channel();
     tcp.connect(request, responder);
-    let origin_stream = match wait_outcome(receiver).await {
+    let Some(outcome) = wait_outcome_unless(receiver, reset(&mut respond), &cancellation).await
+    else {
+        tracing::debug!("edge reset the tcp stream");
+        return Ok(());
+    };
+    let origin_stream = match outcome {
         Ok(origin_stream) => origin_stream,
-        Err(message) => return write_h2_error(respond, &message).await,
+        Err(error) => {
+            guard.disarm();
+            metric.origin_failed();
+            metric.status(http::StatusCode::BAD_GATEWAY);
+            return write_h2_error(respond, error.message()).await;
+        }
     };
     let mut ack_headers = http::HeaderMap::new();
     if let Some(key) = parts.headers.get("sec-websocket-key")
@@ -119,12 +251,30 @@ async fn handle_h2_tcp(
         Body::empty(),
     );
     let send = write_h2_headers(&mut respond, &ack)?;
-    pump(
+    let summary = pump(
         origin_stream,
         ReceiveStreamReader::new(body),
         SendStreamWriter::new(send),
+        &shared.dispatch.origin.streams,
+        summary,
     )
-    .await
+    .await;
+    metric.streamed(&summary);
+    shared.dispatch.stream_completed(&summary);
+    guard.disarm();
+    Ok(())
+}
+
+/// Attaches a fresh cancellation token to a request and returns it.
+fn attach_cancellation(request: &mut Request) -> CancellationToken {
+    let cancellation = CancellationToken::new();
+    request.extensions.insert(cancellation.clone());
+    cancellation
+}
+
+/// Resolves once the edge resets the stream or the connection fails.
+async fn reset(respond: &mut SendResponse<Bytes>) {
+    let _ = std::future::poll_fn(|cx| respond.poll_reset(cx)).await;
 }
 
 async fn handle_h2_configuration(
@@ -168,9 +318,15 @@ async fn handle_h2_configuration(
     Ok(())
 }
 
-/// Writes an HTTP/2 response and streams the response body.
-async fn write_h2_response(mut respond: SendResponse<Bytes>, response: Response) -> Result<()> {
-    if response.body.size_hint() == Some(0) {
+/// Writes an HTTP/2 response and streams the response body, chunk by chunk
+/// when `flush` is set and coalescing ready chunks otherwise; returns the
+/// body bytes sent.
+async fn write_h2_response(
+    mut respond: SendResponse<Bytes>,
+    response: Response,
+    flush: bool,
+) -> Result<u64> {
+    if response.body.size_hint() == Some(0) && !response.body.has_trailers() {
         let empty = response;
         let mut http_response = http::Response::builder()
             .status(remap_status(empty.status))
@@ -178,14 +334,24 @@ async fn write_h2_response(mut respond: SendResponse<Bytes>, response: Response)
             .unwrap();
         *http_response.headers_mut() = encode_response_headers(&empty);
         respond.send_response(http_response, true)?;
-        return Ok(());
+        return Ok(0);
     }
     let send = write_h2_headers(&mut respond, &response)?;
     let mut writer = SendStreamWriter::new(send);
     let mut body = response.body;
-    futures_util::io::copy(&mut body, &mut writer).await?;
-    writer.close().await?;
-    Ok(())
+    let mut writes = ResponseWrites::new(&mut body, flush);
+    let mut sent = 0;
+    while let Some(chunk) = writes.next().await {
+        let chunk = chunk?;
+        sent += chunk.len() as u64;
+        writer.send_chunk(chunk).await?;
+    }
+    // Like cloudflared, trailers travel as a real HTTP/2 trailers frame.
+    match body.trailers().await {
+        Some(trailers) => writer.close_with_trailers(trailers)?,
+        None => writer.close().await?,
+    }
+    Ok(sent)
 }
 
 /// Writes the response headers for a streaming exchange (websocket, TCP,
@@ -213,7 +379,8 @@ async fn write_h2_error(respond: SendResponse<Bytes>, message: &str) -> Result<(
         headers,
         Body::from_bytes(message.as_bytes().to_vec()),
     );
-    write_h2_response(respond, response).await
+    write_h2_response(respond, response, false).await?;
+    Ok(())
 }
 
 /// HTTP/2 has no 101; cloudflared remaps it to 200.
@@ -225,6 +392,10 @@ fn remap_status(status: http::StatusCode) -> http::StatusCode {
     }
 }
 
+fn stream_id(body: &RecvStream) -> u64 {
+    u64::from(body.stream_id().as_u32())
+}
+
 fn request_host(parts: &http::request::Parts) -> String {
     if let Some(host) = parts.headers.get(http::header::HOST) {
         return host.to_str().unwrap_or("").to_string();
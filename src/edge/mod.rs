@@ -7,7 +7,9 @@
 pub(crate) mod configuration;
 mod connector;
 pub(crate) mod control;
+mod diagnostics;
 mod discovery;
+pub(crate) mod dispatch;
 mod error;
 pub use error::Error;
 pub(crate) mod event;
@@ -18,8 +20,16 @@ pub(crate) mod quic;
 mod roots;
 #[cfg(quic_any)]
 pub(crate) mod serve;
+pub(crate) mod trace;
 
-pub(crate) use discovery::discover_edges;
+pub(crate) use discovery::{discover_edges, resolver_address};
 
 pub use configuration::RemoteConfiguration;
-pub use connector::{EdgeConnector, EdgeOptions, Transport, default_configuration_json};
+pub use connector::{
+    EdgeConnector, EdgeOptions, ErrorPageRenderer, Transport, default_configuration_json,
+};
+#[cfg(feature = "quick-tunnel")]
+pub use connector::{NewTunnelCallback, QuickSupervisorOptions};
+#[cfg(feature = "diagnostics-logs")]
+pub use diagnostics::DiagnosticsLayer;
+pub use diagnostics::{Diagnostics, DiagnosticsBundle};
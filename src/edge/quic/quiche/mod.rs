@@ -13,6 +13,7 @@ use tokio::net::UdpSocket;
 use tokio::sync::{Notify, watch};
 
 use crate::error::{Error, Result};
+use crate::metrics::QuicStats;
 
 use super::{EDGE_ALPN, EDGE_SNI};
 
@@ -32,6 +33,8 @@ pub(crate) struct Inner {
     /// is served by the RPC client, not the request path.
     pub(crate) accepted: HashSet<u64>,
     pub(crate) established: bool,
+    /// Streams opened so far, the control stream included.
+    pub(crate) streams_opened: u64,
     pub(crate) closed: bool,
     pub(crate) timed_out: bool,
     pub(crate) close_reason: Option<String>,
@@ -82,6 +85,7 @@ impl QuicConnection {
             write_wakers: HashMap::new(),
             accepted: HashSet::from([0]),
             established: false,
+            streams_opened: 0,
             closed: false,
             timed_out: false,
             close_reason: None,
@@ -127,7 +131,13 @@ impl QuicConnection {
 
     /// Opens the control stream (the first client stream, id 0).
     pub(crate) async fn open_control_stream(&self) -> Result<QuicStream> {
-        Ok(QuicStream::new(self.inner.clone(), self.notify.clone(), 0))
+        self.inner.lock().unwrap().streams_opened += 1;
+        Ok(QuicStream::new(
+            self.inner.clone(),
+            self.notify.clone(),
+            self.sequence_tx.subscribe(),
+            0,
+        ))
     }
 
     /// Accepts the next data stream opened by the edge, or `None` once the
@@ -140,14 +150,20 @@ impl QuicConnection {
                 if g.closed {
                     return Ok(None);
                 }
-                g.connection
+                let identifier = g
+                    .connection
                     .readable()
-                    .find(|identifier| g.accepted.insert(*identifier))
+                    .find(|identifier| g.accepted.insert(*identifier));
+                if identifier.is_some() {
+                    g.streams_opened += 1;
+                }
+                identifier
             };
             if let Some(identifier) = identifier {
                 return Ok(Some(QuicStream::new(
                     self.inner.clone(),
                     self.notify.clone(),
+                    self.sequence_tx.subscribe(),
                     identifier,
                 )));
             }
@@ -173,6 +189,29 @@ impl QuicConnection {
         self.inner.lock().unwrap().timed_out
     }
 
+    /// A snapshot of the connection's transport statistics.
+    pub(crate) fn stats(&self) -> QuicStats {
+        let g = self.inner.lock().unwrap();
+        let stats = g.connection.stats();
+        let (rtt, congestion_window) = g
+            .connection
+            .path_stats()
+            .next()
+            .map_or((Duration::ZERO, 0), |path| (path.rtt, path.cwnd as u64));
+        QuicStats {
+            rtt,
+            congestion_window,
+            bytes_sent: stats.sent_bytes,
+            bytes_received: stats.recv_bytes,
+            bytes_lost: stats.lost_bytes,
+            packets_sent: stats.sent as u64,
+            packets_lost: stats.lost as u64,
+            streams_opened: g.streams_opened,
+            datagrams_sent: stats.dgram_sent as u64,
+            datagrams_received: stats.dgram_recv as u64,
+        }
+    }
+
     /// Gracefully closes the connection.
     pub(crate) fn close(&self) {
         let mut g = self.inner.lock().unwrap();
@@ -15,8 +15,9 @@ use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 
-use futures_util::io::{AsyncRead, AsyncWrite};
-use tokio::sync::Notify;
+use bytes::{Bytes, BytesMut};
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{Notify, watch};
 
 use super::Inner;
 
@@ -24,6 +25,8 @@ use super::Inner;
 pub(crate) struct QuicStream {
     inner: Arc<Mutex<Inner>>,
     notify: Arc<Notify>,
+    /// Bumped by the driver after every loop, to re-check stream state.
+    sequence: watch::Receiver<u64>,
     stream_identifier: u64,
     read_eof: bool,
 }
@@ -32,11 +35,13 @@ impl QuicStream {
     pub(crate) fn new(
         inner: Arc<Mutex<Inner>>,
         notify: Arc<Notify>,
+        sequence: watch::Receiver<u64>,
         stream_identifier: u64,
     ) -> Self {
         Self {
             inner,
             notify,
+            sequence,
             stream_identifier,
             read_eof: false,
         }
@@ -79,8 +84,53 @@ impl QuicStream {
                 .stream_shutdown(self.stream_identifier, quiche::Shutdown::Read, 0);
         }
     }
+
+    /// Resolves once the peer stops the write side (STOP_SENDING) or the
+    /// connection closes.
+    pub(crate) async fn stopped(&self) {
+        let mut sequence = self.sequence.clone();
+        loop {
+            {
+                let g = self.inner.lock().unwrap();
+                if g.closed {
+                    return;
+                }
+                if let Err(quiche::Error::StreamStopped(_) | quiche::Error::InvalidStreamState(_)) =
+                    g.connection.stream_writable(self.stream_identifier, 0)
+                {
+                    return;
+                }
+            }
+            if sequence.changed().await.is_err() {
+                return;
+            }
+        }
+    }
 }
 
+impl QuicStream {
+    /// Reads the next chunk of stream data; `None` at the end of the stream.
+    /// quiche copies out of its own buffers, so the chunk is freshly
+    /// allocated.
+    pub(crate) async fn read_chunk(&mut self) -> io::Result<Option<Bytes>> {
+        let mut chunk = BytesMut::zeroed(READ_CHUNK_SIZE);
+        let n = self.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        chunk.truncate(n);
+        Ok(Some(chunk.freeze()))
+    }
+
+    /// Writes a whole chunk.
+    pub(crate) async fn write_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        self.write_all(&chunk).await
+    }
+}
+
+/// Largest chunk `read_chunk` returns.
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
 fn closed_error() -> io::Error {
     io::Error::new(io::ErrorKind::ConnectionReset, "quic connection closed")
 }
@@ -202,7 +252,11 @@ impl AsyncWrite for QuicStream {
         }
     }
 
+    /// Wakes the driver so buffered stream data goes out now rather than on
+    /// its next periodic kick. `notify_one` keeps a permit when the driver
+    /// is busy, so the wakeup cannot be missed.
     fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.notify.notify_one();
         Poll::Ready(Ok(()))
     }
 
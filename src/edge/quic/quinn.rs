@@ -7,18 +7,22 @@
 //! halves behind an `Arc` so multiple handles can read and write the same
 //! edge stream, mirroring how the quiche backend references streams by id.
 
+use std::future::{Future, poll_fn};
 use std::io;
 use std::net::SocketAddr;
-use std::pin::Pin;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::Duration;
 
+use bytes::Bytes;
 use futures_io::{AsyncRead, AsyncWrite};
 use quinn::{Connection as QuinnConnection, Endpoint, IdleTimeout, TransportConfig, VarInt};
 
 use crate::edge::roots;
 use crate::error::{Error, Result};
+use crate::metrics::QuicStats;
 
 use super::{EDGE_ALPN, EDGE_SNI};
 
@@ -33,6 +37,8 @@ const MAXIMUM_INCOMING_STREAMS: u64 = 1024;
 /// A QUIC connection to the edge.
 pub(crate) struct QuicConnection {
     connection: QuinnConnection,
+    /// Streams opened so far; quinn's statistics count frames, not streams.
+    streams_opened: AtomicU64,
 }
 
 impl QuicConnection {
@@ -56,7 +62,10 @@ impl QuicConnection {
         let connection = connecting
             .await
             .map_err(|e| Error::quic(format!("handshake failed: {e}")))?;
-        Ok(QuicConnection { connection })
+        Ok(QuicConnection {
+            connection,
+            streams_opened: AtomicU64::new(0),
+        })
     }
 
     /// Opens the control stream (the first client stream, id 0).
@@ -66,13 +75,14 @@ impl QuicConnection {
             .open_bi()
             .await
             .map_err(|e| Error::quic(format!("open control stream failed: {e}")))?;
+        self.streams_opened.fetch_add(1, Ordering::Relaxed);
         Ok(QuicStream::new(Some(send), Some(recv)))
     }
 
     /// Accepts the next data stream opened by the edge, or `None` once the
     /// connection closes.
     pub(crate) async fn accept_stream(&self) -> Result<Option<QuicStream>> {
-        tokio::select! {
+        let stream = tokio::select! {
             result = self.connection.accept_bi() => {
                 accept_outcome(result).map(|streams| {
                     streams.map(|(send, recv)| QuicStream::new(Some(send), Some(recv)))
@@ -81,7 +91,11 @@ impl QuicConnection {
             result = self.connection.accept_uni() => {
                 accept_outcome(result).map(|stream| stream.map(|recv| QuicStream::new(None, Some(recv))))
             }
+        };
+        if let Ok(Some(_)) = &stream {
+            self.streams_opened.fetch_add(1, Ordering::Relaxed);
         }
+        stream
     }
 
     /// The reason the connection closed, if it has.
@@ -97,6 +111,23 @@ impl QuicConnection {
         )
     }
 
+    /// A snapshot of the connection's transport statistics.
+    pub(crate) fn stats(&self) -> QuicStats {
+        let stats = self.connection.stats();
+        QuicStats {
+            rtt: stats.path.rtt,
+            congestion_window: stats.path.cwnd,
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            bytes_lost: stats.path.lost_bytes,
+            packets_sent: stats.path.sent_packets,
+            packets_lost: stats.path.lost_packets,
+            streams_opened: self.streams_opened.load(Ordering::Relaxed),
+            datagrams_sent: stats.frame_tx.datagram,
+            datagrams_received: stats.frame_rx.datagram,
+        }
+    }
+
     /// Gracefully closes the connection.
     pub(crate) fn close(&self) {
         self.connection.close(VarInt::from_u32(0), b"");
@@ -219,6 +250,65 @@ impl QuicStream {
             let _ = recv.stop(VarInt::from_u32(0));
         }
     }
+
+    /// Resolves once the peer stops the write side (STOP_SENDING) or the
+    /// connection is lost. Never resolves for a stream without a write side.
+    pub(crate) async fn stopped(&self) {
+        let stopped = self
+            .parts
+            .lock()
+            .unwrap()
+            .send
+            .as_ref()
+            .map(|send| send.stopped());
+        match stopped {
+            Some(stopped) => {
+                let _ = stopped.await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Reads the next chunk quinn has buffered, without copying it; `None`
+    /// at the end of the stream.
+    pub(crate) async fn read_chunk(&mut self) -> io::Result<Option<Bytes>> {
+        // Reach the receive half under the lock for one poll at a time, so
+        // `stop_read` and `id` still find it while a read is pending.
+        let chunk = poll_fn(|cx| {
+            let mut parts = self.parts.lock().unwrap();
+            match parts.recv.as_mut() {
+                Some(recv) => pin!(recv.read_chunk(usize::MAX, true))
+                    .poll(cx)
+                    .map_err(io::Error::from),
+                None => Poll::Ready(Ok(None)),
+            }
+        })
+        .await?;
+        Ok(chunk.map(|chunk| chunk.bytes))
+    }
+
+    /// Writes a whole chunk, handing the buffer to quinn without copying.
+    pub(crate) async fn write_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        let mut chunks = [chunk];
+        while !chunks[0].is_empty() {
+            // quinn's `write_chunks` writes nothing unless it resolves, so a
+            // fresh one per poll under the lock loses no data.
+            poll_fn(|cx| {
+                let mut parts = self.parts.lock().unwrap();
+                match parts.send.as_mut() {
+                    Some(send) => pin!(send.write_chunks(&mut chunks))
+                        .poll(cx)
+                        .map_err(io::Error::from),
+                    None => Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "write side is closed",
+                    ))),
+                }
+            })
+            .await?;
+        }
+        Ok(())
+    }
 }
 
 impl AsyncRead for QuicStream {
@@ -14,16 +14,9 @@ const SYSTEM_CA_PATHS: &[&str] = &[
 /// roots, and any user-supplied CA, in that order.
 pub(crate) fn root_pems(ca_cert_pem: Option<&[u8]>) -> Vec<Vec<u8>> {
     let mut pems = Vec::new();
-    let mut found_system = false;
-    for path in SYSTEM_CA_PATHS {
-        if let Ok(bytes) = std::fs::read(path) {
-            pems.push(bytes);
-            found_system = true;
-            break;
-        }
-    }
-    if !found_system {
-        tracing::warn!("no system CA bundle found; using bundled roots only");
+    match system_ca() {
+        Some((_, bytes)) => pems.push(bytes),
+        None => tracing::warn!("no system CA bundle found; using bundled roots only"),
     }
     pems.push(include_bytes!("quic/cloudflare_origin_ca.pem").to_vec());
     if let Some(custom) = ca_cert_pem {
@@ -31,3 +24,10 @@ pub(crate) fn root_pems(ca_cert_pem: Option<&[u8]>) -> Vec<Vec<u8>> {
     }
     pems
 }
+
+/// The first readable system CA bundle and its path.
+pub(crate) fn system_ca() -> Option<(&'static str, Vec<u8>)> {
+    SYSTEM_CA_PATHS
+        .iter()
+        .find_map(|path| std::fs::read(path).ok().map(|bytes| (*path, bytes)))
+}
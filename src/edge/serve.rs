@@ -1,21 +1,27 @@
 //! Serving incoming edge streams to the origin handlers.
 
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use bytes::Bytes;
 use futures_util::io::{AsyncReadExt, AsyncWriteExt};
 use libcfd_rpc::quic::{
     ConnectRequest, ConnectResponse, ConnectionType, DATA_STREAM_PROTOCOL_SIGNATURE, HTTP_HOST_KEY,
     HTTP_METHOD_KEY, HTTP_STATUS_KEY, PROTOCOL_V1, RPC_STREAM_PROTOCOL_SIGNATURE,
     read_connect_request, write_connect_response,
 };
+use tracing::Instrument as _;
 
 use crate::edge::configuration::EdgeConfigurationHandler;
+use crate::edge::dispatch::{ConnectionInfo, Dispatch};
 use crate::edge::quic::{QuicConnection, QuicStream};
+use crate::edge::trace::RequestTrace;
 use crate::error::{Error, Result};
+use crate::metrics::RequestKind;
 use crate::origin::{
-    Body, HttpResponder, Origin, Request, Response, TcpResponder, WebSocketResponder, pump,
-    wait_outcome,
+    Body, CancellationToken, HttpResponder, LimitBreach, OriginError, OriginLimits, Request,
+    Response, ResponseWrites, StreamKind, StreamSummary, TcpResponder, WebSocketResponder, pump,
+    wait_outcome_unless,
 };
 
 const HEADER_KEY_PREFIX: &str = "HttpHeader:";
@@ -29,7 +35,8 @@ const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
 /// stays bounded.
 pub(crate) async fn serve_requests(
     connection: Arc<QuicConnection>,
-    origin: Arc<Origin>,
+    connection_info: Arc<ConnectionInfo>,
+    dispatch: Arc<Dispatch>,
     configuration_handler: Arc<EdgeConfigurationHandler>,
 ) -> Result<()> {
     let mut tasks = tokio::task::JoinSet::new();
@@ -51,11 +58,12 @@ pub(crate) async fn serve_requests(
                 match accepted {
                     Ok(Some(stream)) => {
                         let stream_id = stream.id();
-                        let o = origin.clone();
+                        let d = dispatch.clone();
+                        let ci = connection_info.clone();
                         let ch = configuration_handler.clone();
                         let c = connection.clone();
                         tasks.spawn(async move {
-                            let result = serve_stream(o.as_ref(), ch.as_ref(), stream).await;
+                            let result = serve_stream(d.as_ref(), ci.as_ref(), ch.as_ref(), stream).await;
                             c.release(stream_id);
                             result
                         });
@@ -75,7 +83,8 @@ pub(crate) async fn serve_requests(
 }
 
 async fn serve_stream(
-    origin: &Origin,
+    dispatch: &Dispatch,
+    connection_info: &ConnectionInfo,
     configuration_handler: &EdgeConfigurationHandler,
     mut stream: QuicStream,
 ) -> Result<()> {
@@ -107,77 +116,152 @@ async fn serve_stream(
         "edge requested a connection"
     );
     match connect.connection_type {
-        ConnectionType::Http => handle_quic_http(origin, connect, stream).await,
-        ConnectionType::Websocket => handle_quic_websocket(origin, connect, stream).await,
-        ConnectionType::Tcp => handle_quic_tcp(origin, connect, stream).await,
+        ConnectionType::Http => handle_quic_http(dispatch, connection_info, connect, stream).await,
+        ConnectionType::Websocket => {
+            handle_quic_websocket(dispatch, connection_info, connect, stream).await
+        }
+        ConnectionType::Tcp => handle_quic_tcp(dispatch, connection_info, connect, stream).await,
     }
 }
 
 async fn handle_quic_http(
-    origin: &Origin,
+    dispatch: &Dispatch,
+    connection_info: &ConnectionInfo,
     connect: ConnectRequest,
     stream: QuicStream,
 ) -> Result<()> {
-    let request = build_request(&connect)?;
-    let request = Request::new(
-        request.method,
-        request.uri,
-        request.headers,
-        Body::from_reader(stream.clone()),
-    );
+    let mut metric = dispatch.request_metric(RequestKind::Http);
+    let limits = &dispatch.origin.limits;
+    let (mut request, rejected) = build_request(&connect, limits)?;
+    let mut trace = RequestTrace::from_headers(&request.headers);
+    let method = request.method.clone();
+    let context = connection_info.request_context(stream.id(), &request.headers);
+    request.extensions.insert(context);
+    metric.request(&request);
+    let error_page_headers = dispatch.error_page_headers(&request.headers);
+    let cancellation = attach_cancellation(&mut request);
+    let mut guard = cancellation.drop_guard();
 
-    let (responder, receiver) = HttpResponder::channel();
-    origin.http.handle(request, responder);
-    let response = match wait_outcome(receiver).await {
-        Ok(response) => response,
-        Err(message) => return write_stream_error(&stream, &message).await,
+    let breach = LimitBreach::default();
+    let outcome = match rejected {
+        Some(error) => {
+            stream.stop_read();
+            Err(error)
+        }
+        None => {
+            let chunks = metric.count_received(request_chunks(stream.clone()));
+            request.body = Body::from_stream(limits.limit_body(chunks, &breach));
+            let (responder, receiver) = HttpResponder::channel();
+            let origin_started = SystemTime::now();
+            let span = trace
+                .as_ref()
+                .map_or_else(tracing::Span::none, RequestTrace::span);
+            let outcome = async {
+                dispatch.origin.http.handle(request, responder);
+                wait_outcome_unless(receiver, stream.stopped(), &cancellation).await
+            }
+            .instrument(span)
+            .await;
+            let Some(outcome) = outcome else {
+                return abandoned(&stream);
+            };
+            if let Some(trace) = &mut trace {
+                trace.record("origin", origin_started);
+            }
+            match breach.error() {
+                Some(error) => {
+                    stream.stop_read();
+                    Err(error)
+                }
+                None => outcome,
+            }
+        }
     };
-
-    let mut response_stream = stream.clone();
-    let metadata = encode_response_metadata(&response);
-    let connect_response = ConnectResponse {
-        error: String::new(),
-        metadata,
+    let mut response = match outcome {
+        Ok(response) => response,
+        Err(error) => {
+            guard.disarm();
+            metric.origin_failed();
+            match dispatch.error_response(&error_page_headers, error) {
+                Ok(response) => response,
+                Err(message) => {
+                    metric.status(http::StatusCode::BAD_GATEWAY);
+                    return write_stream_error(&stream, &message).await;
+                }
+            }
+        }
     };
-    if let Err(e) = write_response_preamble(&mut response_stream, &connect_response).await {
-        stream.cancel_write();
-        return Err(e);
+    metric.status(response.status);
+    if let Some(trace) = trace {
+        trace.finish(&method, response.status, &mut response.headers);
     }
 
-    let mut body = response.body;
-    let copied = futures_util::io::copy(&mut body, &mut response_stream).await;
-    match copied {
-        Ok(_) => {}
-        Err(e) => {
-            stream.cancel_write();
-            return Err(Error::edge_io(e));
-        }
-    }
+    let sent = write_response(&stream, response, dispatch, connection_info).await?;
+    metric.responded(sent);
     tracing::trace!(stream = stream.id(), "response sent");
+    guard.disarm();
 
     // Drain unconsumed request body bytes so the edge's flow-control credit is not held forever.
-    drain_unread(stream);
+    drain_unread(stream.clone());
 
-    response_stream.finish();
+    stream.finish();
     Ok(())
 }
 
 async fn handle_quic_websocket(
-    origin: &Origin,
+    dispatch: &Dispatch,
+    connection_info: &ConnectionInfo,
     connect: ConnectRequest,
     stream: QuicStream,
 ) -> Result<()> {
-    let Some(websocket) = &origin.websocket else {
+    let mut metric = dispatch.request_metric(RequestKind::WebSocket);
+    let Some(websocket) = &dispatch.origin.websocket else {
+        metric.origin_failed();
+        metric.status(http::StatusCode::BAD_GATEWAY);
         return write_stream_error(&stream, "no websocket origin handler").await;
     };
-    let request = build_request(&connect)?;
-    let (responder, receiver) = WebSocketResponder::channel();
-    websocket.connect(request, responder);
-    let connection = match wait_outcome(receiver).await {
+    let (mut request, rejected) = build_request(&connect, &dispatch.origin.limits)?;
+    let context = connection_info.request_context(stream.id(), &request.headers);
+    let summary = StreamSummary::new(StreamKind::WebSocket, Some(context.clone()));
+    request.extensions.insert(context);
+    metric.request(&request);
+    let error_page_headers = dispatch.error_page_headers(&request.headers);
+    let cancellation = attach_cancellation(&mut request);
+    let mut guard = cancellation.drop_guard();
+    let outcome = match rejected {
+        Some(error) => Err(error),
+        None => {
+            let (responder, receiver) = WebSocketResponder::channel();
+            websocket.connect(request, responder);
+            let Some(outcome) =
+                wait_outcome_unless(receiver, stream.stopped(), &cancellation).await
+            else {
+                return abandoned(&stream);
+            };
+            outcome
+        }
+    };
+    let connection = match outcome {
         Ok(connection) => connection,
-        Err(message) => return write_stream_error(&stream, &message).await,
+        Err(error) => {
+            guard.disarm();
+            metric.origin_failed();
+            return match dispatch.error_response(&error_page_headers, error) {
+                Ok(response) => {
+                    metric.status(response.status);
+                    write_response(&stream, response, dispatch, connection_info).await?;
+                    stream.finish();
+                    Ok(())
+                }
+                Err(message) => {
+                    metric.status(http::StatusCode::BAD_GATEWAY);
+                    write_stream_error(&stream, &message).await
+                }
+            };
+        }
     };
 
+    metric.status(connection.response.status);
     let mut response_stream = stream.clone();
     let metadata = encode_response_metadata(&connection.response);
     let connect_response = ConnectResponse {
@@ -186,29 +270,69 @@ async fn handle_quic_websocket(
     };
     write_response_preamble(&mut response_stream, &connect_response).await?;
 
-    pump(connection.origin, stream.clone(), stream.clone()).await
+    let summary = pump(
+        connection.origin,
+        stream.clone(),
+        stream.clone(),
+        &dispatch.origin.streams,
+        summary,
+    )
+    .await;
+    metric.streamed(&summary);
+    finish_pumped(&stream, dispatch, &summary);
+    guard.disarm();
+    Ok(())
 }
 
 async fn handle_quic_tcp(
-    origin: &Origin,
+    dispatch: &Dispatch,
+    connection_info: &ConnectionInfo,
     connect: ConnectRequest,
     stream: QuicStream,
 ) -> Result<()> {
-    let Some(tcp) = &origin.tcp else {
+    let mut metric = dispatch.request_metric(RequestKind::Tcp);
+    let Some(tcp) = &dispatch.origin.tcp else {
+        metric.origin_failed();
+        metric.status(http::StatusCode::BAD_GATEWAY);
         return write_stream_error(&stream, "no tcp origin handler").await;
     };
-    let request = Request::tcp(&connect.destination);
+    let mut request = Request::tcp(&connect.destination);
+    let context = connection_info.request_context(stream.id(), &request.headers);
+    let summary = StreamSummary::new(StreamKind::Tcp, Some(context.clone()));
+    request.extensions.insert(context);
+    metric.request(&request);
+    let cancellation = attach_cancellation(&mut request);
+    let mut guard = cancellation.drop_guard();
     let (responder, receiver) = TcpResponder::channel();
     tcp.connect(request, responder);
-    let origin_stream = match wait_outcome(receiver).await {
+    let Some(outcome) = wait_outcome_unless(receiver, stream.stopped(), &cancellation).await else {
+        return abandoned(&stream);
+    };
+    let origin_stream = match outcome {
         Ok(origin_stream) => origin_stream,
-        Err(message) => return write_stream_error(&stream, &message).await,
+        Err(error) => {
+            guard.disarm();
+            metric.origin_failed();
+            metric.status(http::StatusCode::BAD_GATEWAY);
+            return write_stream_error(&stream, error.message()).await;
+        }
     };
 
     let mut response_stream = stream.clone();
     write_response_preamble(&mut response_stream, &ConnectResponse::default()).await?;
 
-    pump(origin_stream, stream.clone(), stream.clone()).await
+    let summary = pump(
+        origin_stream,
+        stream.clone(),
+        stream.clone(),
+        &dispatch.origin.streams,
+        summary,
+    )
+    .await;
+    metric.streamed(&summary);
+    finish_pumped(&stream, dispatch, &summary);
+    guard.disarm();
+    Ok(())
 }
 
 /// Handles edge-initiated RPC streams: the edge bootstraps the connector's
@@ -224,6 +348,31 @@ async fn handle_rpc_stream(
         .map_err(Error::from)
 }
 
+/// Attaches a fresh cancellation token to a request and returns it.
+fn attach_cancellation(request: &mut Request) -> CancellationToken {
+    let cancellation = CancellationToken::new();
+    request.extensions.insert(cancellation.clone());
+    cancellation
+}
+
+/// Resets a stream the pump tore down on a timeout, then reports it.
+fn finish_pumped(stream: &QuicStream, dispatch: &Dispatch, summary: &StreamSummary) {
+    if summary.timed_out() {
+        stream.stop_read();
+        stream.cancel_write();
+    }
+    dispatch.stream_completed(summary);
+}
+
+/// The edge stopped the stream before the handler answered; release the
+/// request side and leave the handler to notice its cancellation.
+fn abandoned(stream: &QuicStream) -> Result<()> {
+    tracing::debug!(stream = stream.id(), "edge abandoned the request");
+    stream.stop_read();
+    stream.cancel_write();
+    Ok(())
+}
+
 async fn write_stream_error(stream: &QuicStream, message: &str) -> Result<()> {
     let mut response_stream = stream.clone();
     let connect_response = ConnectResponse {
@@ -235,6 +384,81 @@ async fn write_stream_error(stream: &QuicStream, message: &str) -> Result<()> {
     Ok(())
 }
 
+/// Writes a complete response (preamble plus body) without finishing the
+/// stream and returns the body bytes sent; a failed write cancels the send
+/// side.
+async fn write_response(
+    stream: &QuicStream,
+    response: Response,
+    dispatch: &Dispatch,
+    connection_info: &ConnectionInfo,
+) -> Result<u64> {
+    let flush = dispatch.flushes(&response);
+    let mut response_stream = stream.clone();
+    let connect_response = ConnectResponse {
+        error: String::new(),
+        metadata: encode_response_metadata(&response),
+    };
+    if let Err(e) = write_response_preamble(&mut response_stream, &connect_response).await {
+        stream.cancel_write();
+        return Err(e);
+    }
+    let mut body = response.body;
+    let mut writes = ResponseWrites::new(&mut body, flush);
+    if writes.flushes() {
+        // Streaming responses show their headers before the first chunk.
+        response_stream.flush().await?;
+    }
+    let mut sent = 0;
+    while let Some(chunk) = writes.next().await {
+        let written = match chunk {
+            Ok(chunk) => {
+                sent += chunk.len() as u64;
+                write_chunk(&mut response_stream, chunk, writes.flushes()).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            stream.cancel_write();
+            return Err(Error::edge_io(e));
+        }
+    }
+    if body.has_trailers() {
+        // The QUIC data-stream protocol has no trailer frame, so a gRPC
+        // status never reaches the client; warn once per connection.
+        if connection_info.dropped_trailers() {
+            tracing::warn!(
+                stream = stream.id(),
+                "response trailers dropped over QUIC; gRPC origins need the HTTP/2 transport"
+            );
+        } else {
+            tracing::debug!(stream = stream.id(), "response trailers dropped over QUIC");
+        }
+    }
+    Ok(sent)
+}
+
+async fn write_chunk(stream: &mut QuicStream, chunk: Bytes, flush: bool) -> std::io::Result<()> {
+    stream.write_chunk(chunk).await?;
+    if flush {
+        stream.flush().await?;
+    }
+    Ok(())
+}
+
+/// The request body of an HTTP stream, read chunk by chunk.
+fn request_chunks(
+    stream: QuicStream,
+) -> impl futures_util::Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+    futures_util::stream::unfold(stream, |mut stream| async move {
+        match stream.read_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), stream)),
+            Ok(None) => None,
+            Err(e) => Some((Err(e), stream)),
+        }
+    })
+}
+
 async fn write_response_preamble(
     stream: &mut QuicStream,
     response: &ConnectResponse,
@@ -245,13 +469,40 @@ async fn write_response_preamble(
     Ok(())
 }
 
-fn build_request(connect: &ConnectRequest) -> Result<Request> {
-    let mut method = http::Method::GET;
+/// Builds the request a connect request describes. A head that breaks
+/// the origin's header limits is rejected before any header is copied: the
+/// request comes back without headers, along with the breach to answer.
+fn build_request(
+    connect: &ConnectRequest,
+    limits: &OriginLimits,
+) -> Result<(Request, Option<OriginError>)> {
+    let method = connect
+        .metadata
+        .iter()
+        .find(|(key, _)| key == HTTP_METHOD_KEY)
+        .and_then(|(_, val)| http::Method::from_bytes(val.as_bytes()).ok())
+        .unwrap_or(http::Method::GET);
+    let uri = http::Uri::try_from(connect.destination.as_str()).map_err(|e| {
+        Error::quic(format!(
+            "invalid request destination {:?}: {e}",
+            connect.destination
+        ))
+    })?;
+    let fields = connect.metadata.iter().filter_map(|(key, val)| {
+        let name = match key.strip_prefix(HEADER_KEY_PREFIX) {
+            Some(name) => name,
+            None if key == HTTP_HOST_KEY => http::header::HOST.as_str(),
+            None => return None,
+        };
+        Some((name, val.as_bytes()))
+    });
+    if let Err(error) = limits.check_header_fields(fields) {
+        let request = Request::new(method, uri, http::HeaderMap::new(), Body::empty());
+        return Ok((request, Some(error)));
+    }
     let mut headers = http::HeaderMap::new();
     for (key, val) in &connect.metadata {
-        if key == HTTP_METHOD_KEY {
-            method = http::Method::from_bytes(val.as_bytes()).unwrap_or(http::Method::GET);
-        } else if key == HTTP_HOST_KEY
+        if key == HTTP_HOST_KEY
             && let Ok(hv) = http::HeaderValue::from_str(val)
         {
             headers.insert(http::header::HOST, hv);
@@ -264,13 +515,7 @@ fn build_request(connect: &ConnectRequest) -> Result<Request> {
             headers.append(n, v);
         }
     }
-    let uri = http::Uri::try_from(connect.destination.as_str()).map_err(|e| {
-        Error::quic(format!(
-            "invalid request destination {:?}: {e}",
-            connect.destination
-        ))
-    })?;
-    Ok(Request::new(method, uri, headers, Body::empty()))
+    Ok((Request::new(method, uri, headers, Body::empty()), None))
 }
 
 fn encode_response_metadata(response: &Response) -> Vec<(String, String)> {
@@ -345,13 +590,29 @@ mod tests {
 
     #[test]
     fn classifies_http_request() {
-        let request = build_request(&http_request()).unwrap();
+        let (request, rejected) = build_request(&http_request(), &OriginLimits::default()).unwrap();
+        assert!(rejected.is_none());
         assert_eq!(request.method, http::Method::POST);
         assert_eq!(request.uri, "http://example.com/path");
         assert_eq!(request.headers[http::header::HOST], "example.com");
         assert_eq!(request.headers["content-type"], "text/plain");
     }
 
+    #[test]
+    fn rejects_oversized_heads_before_copying_headers() {
+        let limits = OriginLimits {
+            max_header_count: Some(1),
+            ..OriginLimits::default()
+        };
+        let (request, rejected) = build_request(&http_request(), &limits).unwrap();
+        assert_eq!(
+            rejected.unwrap().status(),
+            http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+        assert_eq!(request.method, http::Method::POST);
+        assert!(request.headers.is_empty());
+    }
+
     #[test]
     fn classifies_tcp_request() {
         let connect = ConnectRequest {
@@ -0,0 +1,301 @@
+//! Edge trace propagation, mirroring cloudflared's `tracing` package.
+//!
+//! The edge marks a traced request with a `Cf-Trace-Id` header in the
+//! Jaeger `trace-id:span-id:parent-id:flags` form. For those requests the
+//! transports time the connector hop (the whole proxying, and the wait for
+//! the origin handler inside it) and return the spans in the
+//! `Cf-Int-Cloudflared-Tracing` response header as a base64 OTLP
+//! `ExportTraceServiceRequest`, parented to the edge's span, which is how
+//! the hop shows up in the dashboard's trace view. Untraced requests cost
+//! nothing.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine as _;
+
+/// The request header carrying the edge's trace context.
+pub(crate) const TRACE_CONTEXT_HEADER: &str = "cf-trace-id";
+/// The response header returning the connector's spans to the edge.
+pub(crate) const TRACING_HEADER: &str = "cf-int-cloudflared-tracing";
+
+/// Encoded spans larger than this are dropped rather than sent.
+const MAX_ENCODED_SPANS: usize = 8 * 1024;
+
+/// OTLP span kinds.
+const SPAN_KIND_INTERNAL: u64 = 1;
+const SPAN_KIND_SERVER: u64 = 2;
+
+/// The connector spans of one traced request.
+pub(crate) struct RequestTrace {
+    trace_id: [u8; 16],
+    /// The edge span the connector hop hangs off.
+    parent_id: [u8; 8],
+    root_id: [u8; 8],
+    started: SystemTime,
+    spans: Vec<Span>,
+}
+
+struct Span {
+    id: [u8; 8],
+    name: &'static str,
+    started: SystemTime,
+    ended: SystemTime,
+}
+
+impl RequestTrace {
+    /// Starts the connector hop if the edge traces this request.
+    pub(crate) fn from_headers(headers: &http::HeaderMap) -> Option<Self> {
+        let (trace_id, parent_id) = trace_context(headers)?;
+        Some(Self {
+            trace_id,
+            parent_id,
+            root_id: span_id(),
+            started: SystemTime::now(),
+            spans: Vec::new(),
+        })
+    }
+
+    /// The trace id in hex, as `tracing` spans and logs show it.
+    pub(crate) fn trace_id(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    /// A `tracing` span for the request, linked to the edge trace.
+    pub(crate) fn span(&self) -> tracing::Span {
+        tracing::info_span!(
+            "edge_request",
+            trace_id = %self.trace_id(),
+            span_id = %hex(&self.root_id),
+        )
+    }
+
+    /// Records a child of the connector hop that ran from `started` until
+    /// now.
+    pub(crate) fn record(&mut self, name: &'static str, started: SystemTime) {
+        self.spans.push(Span {
+            id: span_id(),
+            name,
+            started,
+            ended: SystemTime::now(),
+        });
+    }
+
+    /// Ends the connector hop and adds its spans to the response headers.
+    pub(crate) fn finish(
+        self,
+        method: &http::Method,
+        status: http::StatusCode,
+        headers: &mut http::HeaderMap,
+    ) {
+        let encoded = self.encode(method, status);
+        if encoded.len() > MAX_ENCODED_SPANS {
+            tracing::debug!(bytes = encoded.len(), "trace spans too large to return");
+            return;
+        }
+        if let Ok(value) = http::HeaderValue::from_str(&encoded) {
+            headers.insert(TRACING_HEADER, value);
+        }
+    }
+
+    fn encode(&self, method: &http::Method, status: http::StatusCode) -> String {
+        let ended = SystemTime::now();
+        let mut spans = Vec::new();
+        let mut root = Proto::default();
+        root.bytes(1, &self.trace_id);
+        root.bytes(2, &self.root_id);
+        root.bytes(4, &self.parent_id);
+        root.string(5, "proxy");
+        root.varint(6, SPAN_KIND_SERVER);
+        root.fixed64(7, unix_nanos(self.started));
+        root.fixed64(8, unix_nanos(ended));
+        root.message(9, attribute_string("http.method", method.as_str()));
+        root.message(
+            9,
+            attribute_int("http.status_code", i64::from(status.as_u16())),
+        );
+        spans.push(root);
+        for span in &self.spans {
+            let mut child = Proto::default();
+            child.bytes(1, &self.trace_id);
+            child.bytes(2, &span.id);
+            child.bytes(4, &self.root_id);
+            child.string(5, span.name);
+            child.varint(6, SPAN_KIND_INTERNAL);
+            child.fixed64(7, unix_nanos(span.started));
+            child.fixed64(8, unix_nanos(span.ended));
+            spans.push(child);
+        }
+
+        let mut scope = Proto::default();
+        scope.string(1, "libcfd");
+        let mut scope_spans = Proto::default();
+        scope_spans.message(1, scope);
+        for span in spans {
+            scope_spans.message(2, span);
+        }
+        // The dashboard groups connector spans under cloudflared's service name.
+        let mut resource = Proto::default();
+        resource.message(1, attribute_string("service.name", "cloudflared"));
+        resource.message(
+            1,
+            attribute_string("service.version", env!("CARGO_PKG_VERSION")),
+        );
+        let mut resource_spans = Proto::default();
+        resource_spans.message(1, resource);
+        resource_spans.message(2, scope_spans);
+        let mut request = Proto::default();
+        request.message(1, resource_spans);
+        base64::engine::general_purpose::STANDARD.encode(request.0)
+    }
+}
+
+/// A protobuf message being written, field by field.
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint((u64::from(field) << 3) | u64::from(wire_type));
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn varint(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        self.raw_varint(value);
+    }
+
+    fn fixed64(&mut self, field: u32, value: u64) {
+        self.key(field, 1);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, 2);
+        self.raw_varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, message: Proto) {
+        self.bytes(field, &message.0);
+    }
+}
+
+/// An OTLP `KeyValue` with a string value.
+fn attribute_string(key: &str, value: &str) -> Proto {
+    let mut any = Proto::default();
+    any.string(1, value);
+    let mut attribute = Proto::default();
+    attribute.string(1, key);
+    attribute.message(2, any);
+    attribute
+}
+
+/// An OTLP `KeyValue` with an integer value.
+fn attribute_int(key: &str, value: i64) -> Proto {
+    let mut any = Proto::default();
+    any.varint(3, value as u64);
+    let mut attribute = Proto::default();
+    attribute.string(1, key);
+    attribute.message(2, any);
+    attribute
+}
+
+/// The trace id of a request the edge traces, in hex as
+/// [`RequestTrace::trace_id`] shows it.
+pub(crate) fn trace_id(headers: &http::HeaderMap) -> Option<String> {
+    trace_context(headers).map(|(trace_id, _)| hex(&trace_id))
+}
+
+/// The trace id and parent span id of the edge's trace context, when the
+/// request is traced.
+fn trace_context(headers: &http::HeaderMap) -> Option<([u8; 16], [u8; 8])> {
+    let value = headers.get(TRACE_CONTEXT_HEADER)?.to_str().ok()?;
+    let mut parts = value.trim().split(':');
+    let trace_id = parse_hex::<16>(parts.next()?)?;
+    let parent_id = parse_hex::<8>(parts.next()?)?;
+    (trace_id != [0; 16]).then_some((trace_id, parent_id))
+}
+
+/// Parses up to `N * 2` hex digits, right-aligned as Jaeger ids are.
+fn parse_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.is_empty() || text.len() > N * 2 {
+        return None;
+    }
+    let padded = format!("{text:0>width$}", width = N * 2);
+    let mut out = [0u8; N];
+    for (index, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(padded.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn span_id() -> [u8; 8] {
+    let mut id = [0u8; 8];
+    let _ = getrandom::fill(&mut id);
+    id
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traced(value: &str) -> Option<RequestTrace> {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(TRACE_CONTEXT_HEADER, value.parse().unwrap());
+        RequestTrace::from_headers(&headers)
+    }
+
+    #[test]
+    fn parses_the_edge_trace_context() {
+        let trace = traced("a1b2c3:00000000000000ff:0:1").unwrap();
+        assert_eq!(trace.trace_id(), "00000000000000000000000000a1b2c3");
+        assert_eq!(trace.parent_id, [0, 0, 0, 0, 0, 0, 0, 0xff]);
+        assert!(traced("0:1:0:1").is_none());
+        assert!(traced("not-hex:1:0:1").is_none());
+        assert!(RequestTrace::from_headers(&http::HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn returns_spans_parented_to_the_edge() {
+        let mut trace = traced("0123456789abcdef0123456789abcdef:1122334455667788:0:1").unwrap();
+        trace.record("origin", SystemTime::now());
+        let root_id = trace.root_id;
+        let mut headers = http::HeaderMap::new();
+        trace.finish(&http::Method::GET, http::StatusCode::OK, &mut headers);
+        let encoded = headers.get(TRACING_HEADER).unwrap().to_str().unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+        // The root span names the edge span as its parent (field 4)...
+        assert!(contains(&[
+            0x22, 8, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88
+        ]));
+        // ...and the origin span names the root.
+        let mut child_parent = vec![0x22, 8];
+        child_parent.extend_from_slice(&root_id);
+        assert!(contains(&child_parent));
+        assert!(contains(b"origin"));
+        assert!(contains(b"http.status_code"));
+    }
+}
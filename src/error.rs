@@ -7,7 +7,7 @@ use thiserror::Error;
 /// to handle a specific failure.
 #[derive(Debug, Error)]
 pub enum Error {
-    /// A tunnel identity or the quick tunnel HTTP API failed.
+    /// A tunnel identity or the quick tunnel or Cloudflare API failed.
     #[cfg(any_tunnel)]
     #[error(transparent)]
     Tunnel(#[from] crate::tunnel::Error),
@@ -66,6 +66,7 @@ impl From<quiche::Error> for Error {
     }
 }
 
+#[cfg(any(any_tunnel, edge_conn, feature = "axum-origin"))]
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
 impl Error {
@@ -87,6 +88,11 @@ impl Error {
         Self::Tunnel(crate::tunnel::Error::QuickTunnelApi(message.into()))
     }
 
+    #[cfg(feature = "quick-tunnel")]
+    pub(crate) fn quick_tunnel_rate_limited(message: impl Into<String>) -> Self {
+        Self::Tunnel(crate::tunnel::Error::QuickTunnelRateLimited(message.into()))
+    }
+
     #[cfg(feature = "quick-tunnel")]
     pub(crate) fn quick_tunnel_response(message: impl Into<String>) -> Self {
         Self::Tunnel(crate::tunnel::Error::QuickTunnelResponse(message.into()))
@@ -102,6 +108,52 @@ impl Error {
         Self::Tunnel(crate::tunnel::Error::NamedTunnelCredentials(message.into()))
     }
 
+    #[cfg(feature = "tunnel-api")]
+    pub(crate) fn tunnel_api(status: u16, code: Option<i64>, message: impl Into<String>) -> Self {
+        Self::Tunnel(crate::tunnel::Error::TunnelApi {
+            status,
+            code,
+            message: message.into(),
+        })
+    }
+
+    #[cfg(feature = "tunnel-api")]
+    pub(crate) fn tunnel_api_response(message: impl Into<String>) -> Self {
+        Self::Tunnel(crate::tunnel::Error::TunnelApiResponse(message.into()))
+    }
+
+    #[cfg(feature = "tunnel-api")]
+    pub(crate) fn tunnel_api_request(error: std::io::Error) -> Self {
+        Self::Tunnel(crate::tunnel::Error::TunnelApiRequest(error))
+    }
+
+    #[cfg(feature = "tunnel-api")]
+    pub(crate) fn route_conflict(message: impl Into<String>) -> Self {
+        Self::Tunnel(crate::tunnel::Error::RouteConflict(message.into()))
+    }
+
+    #[cfg(feature = "tunnel-api")]
+    pub(crate) fn invalid_configuration(message: impl Into<String>) -> Self {
+        Self::Tunnel(crate::tunnel::Error::InvalidConfiguration(message.into()))
+    }
+
+    #[cfg(feature = "tunnel-api")]
+    pub(crate) fn configuration_version(expected: i32, current: i32) -> Self {
+        Self::Tunnel(crate::tunnel::Error::ConfigurationVersion { expected, current })
+    }
+
+    #[cfg(feature = "tunnel-api")]
+    pub(crate) fn origin_certificate(message: impl Into<String>) -> Self {
+        Self::Tunnel(crate::tunnel::Error::OriginCertificate(message.into()))
+    }
+
+    #[cfg(any_tunnel)]
+    pub(crate) fn credential_provider(
+        error: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        Self::Tunnel(crate::tunnel::Error::CredentialProvider(error.into()))
+    }
+
     #[cfg(any_tunnel)]
     pub(crate) fn invalid_tunnel_identifier(message: impl Into<String>) -> Self {
         Self::Tunnel(crate::tunnel::Error::InvalidTunnelIdentifier(
@@ -155,6 +207,7 @@ mod tests {
         #[cfg(feature = "quick-tunnel")]
         errors.extend([
             Error::Tunnel(crate::tunnel::Error::QuickTunnelApi("rate limited".into())),
+            Error::quick_tunnel_rate_limited("1015: rate limited"),
             Error::Tunnel(crate::tunnel::Error::QuickTunnelResponse("bad json".into())),
             Error::Tunnel(crate::tunnel::Error::QuickTunnelRequest(
                 std::io::Error::other("dns"),
@@ -164,10 +217,23 @@ mod tests {
         errors.push(Error::Tunnel(crate::tunnel::Error::NamedTunnelCredentials(
             "missing file".into(),
         )));
+        #[cfg(feature = "tunnel-api")]
+        errors.extend([
+            Error::tunnel_api(409, Some(1013), "tunnel name already in use"),
+            Error::tunnel_api_response("bad json"),
+            Error::tunnel_api_request(std::io::Error::other("dns")),
+            Error::route_conflict("record already exists"),
+            Error::invalid_configuration("no ingress rules"),
+            Error::configuration_version(3, 4),
+            Error::origin_certificate("no ARGO TUNNEL TOKEN block"),
+        ]);
         #[cfg(any_tunnel)]
-        errors.push(Error::Tunnel(
-            crate::tunnel::Error::InvalidTunnelIdentifier("not a uuid".into()),
-        ));
+        errors.extend([
+            Error::Tunnel(crate::tunnel::Error::InvalidTunnelIdentifier(
+                "not a uuid".into(),
+            )),
+            Error::credential_provider("secret manager unavailable"),
+        ]);
         #[cfg(edge_conn)]
         errors.extend([
             Error::Edge(crate::edge::Error::EdgeDiscovery("no srv records".into())),
@@ -17,7 +17,33 @@
 //! (quick or [`NamedTunnel`] loaded from a credentials file), an [`Origin`]
 //! with HTTP, websocket and TCP handlers, and a [`Transport`] selection
 //! (QUIC, HTTP/2, or auto with QUIC-to-HTTP/2 fallback). On connection loss
-//! it reconnects with exponential backoff.//!
+//! it reconnects with exponential backoff.
+//! [`EdgeConnector::run_quick_supervised`] also replaces a quick tunnel the
+//! service expired or revoked, reporting each new hostname, and
+//! [`EdgeConnector::run_with_credentials`] takes a [`CredentialProvider`]
+//! so rotated credentials apply without restarting the run.
+//!
+//! # Trailers and gRPC
+//!
+//! A [`Body`] may end with trailers, so gRPC services (`grpc-status`) can sit
+//! behind a tunnel. Over HTTP/2 they are exchanged as trailers frames in both
+//! directions. The QUIC data-stream protocol has no trailers: response
+//! trailers are dropped, with a warning once per connection, so gRPC needs
+//! the HTTP/2 transport (`Transport::H2`).
+//!
+//! # Streaming responses
+//!
+//! Response chunks are flushed to the edge as the origin produces them for
+//! server-sent events, gRPC and responses without a known length, following
+//! cloudflared; other responses may coalesce chunks that are ready together.
+//! [`FlushPolicy`] overrides this per connector or per [`Body`].
+//!
+//! # Cancellation
+//!
+//! When the visitor goes away, the edge resets the stream; the token from
+//! [`Request::cancellation`] fires then, or when the tunnel connection drops,
+//! so handlers doing expensive work can stop early.
+//!
 //! # Feature gates
 //!
 //! - `quick-tunnel`: the quick tunnel HTTP API client and [`QuickTunnel`]
@@ -29,11 +55,29 @@
 //!   quiche wins when both are enabled;
 //! - `h2-edge`: the HTTP/2 edge transport.
 //!
-//! All four are enabled by default. Transports can be disabled to slim the
-//! dependency tree; the [`Transport`] selection only offers enabled
-//! transports. A transport feature without a tunnel feature still compiles
-//! (the tunnel-agnostic types remain), but no [`EdgeConnector`] entry point
-//! is available for that combination.
+//! All four are enabled by default. The opt-in `compression-gzip`,
+//! `compression-br` and `compression-zstd` features (or `compression` for
+//! all three) provide `CompressionOrigin`, which compresses the responses
+//! of a wrapped [`HttpOrigin`]. `websocket-proxy-tls` lets
+//! `WebSocketProxyOrigin` forward upgrades to `wss://` backends; plain
+//! `ws://` backends need no extra feature.
+//! `prometheus` adds `PrometheusRecorder`, a [`MetricsRecorder`] rendering
+//! cloudflared's tunnel metrics in the Prometheus text format.
+//! `management-logs` adds `ManagementLogs`, the dashboard's management
+//! service with live log streaming from a `tracing` layer.
+//! `tunnel-api` adds `TunnelApi`, a Cloudflare API client that creates,
+//! lists and deletes named tunnels with an API token or a `cloudflared
+//! tunnel login` certificate (`OriginCertificate`), routes DNS
+//! hostnames, load balancers and private networks to them, and uploads
+//! remotely-managed ingress configuration.
+//! `diagnostics-logs` adds recent log lines to the bundles from
+//! [`EdgeConnector::diagnostics`], captured by `DiagnosticsLayer`.
+//!
+//! Transports can be disabled to slim the dependency tree; the
+//! [`Transport`] selection only offers enabled transports. A transport
+//! feature without a tunnel feature still compiles (the tunnel-agnostic
+//! types remain), but no [`EdgeConnector`] entry point is available for
+//! that combination.
 //!
 //! The QUIC transport implements RFC 9000 (version 1). cloudflared also
 //! offers QUIC version 2; quiche 0.29 does not support it yet, so libcfd
@@ -54,29 +98,61 @@
 #[cfg(edge_conn)]
 pub mod edge;
 mod error;
+#[cfg(all(feature = "management-logs", edge_conn))]
+pub mod management;
+pub mod metrics;
 pub mod origin;
 #[cfg(all(feature = "quick-tunnel", quic_any))]
 mod run;
+#[cfg(edge_conn)]
+mod time;
 #[cfg(any_tunnel)]
 pub mod tunnel;
 
+#[cfg(all(feature = "diagnostics-logs", edge_conn))]
+pub use edge::DiagnosticsLayer;
 #[cfg(edge_conn)]
 pub use edge::{
-    EdgeConnector, EdgeOptions, RemoteConfiguration, Transport, default_configuration_json,
+    Diagnostics, DiagnosticsBundle, EdgeConnector, EdgeOptions, ErrorPageRenderer,
+    RemoteConfiguration, Transport, default_configuration_json,
 };
+#[cfg(all(feature = "quick-tunnel", edge_conn))]
+pub use edge::{NewTunnelCallback, QuickSupervisorOptions};
 pub use error::Error;
+#[cfg(all(feature = "management-logs", edge_conn))]
+pub use management::ManagementLogs;
+#[cfg(feature = "prometheus")]
+pub use metrics::PrometheusRecorder;
+#[cfg(edge_conn)]
+pub use metrics::Readiness;
+pub use metrics::{
+    AccessLog, AccessOutcome, AccessRecord, MetricsRecorder, QuicStats, RequestKind,
+};
+#[cfg(edge_conn)]
+pub use origin::WebSocketProxyOrigin;
 #[cfg(feature = "axum-origin")]
 pub use origin::axum::AxumOrigin;
+#[cfg(compression_any)]
+pub use origin::compression::CompressionOrigin;
 pub use origin::{
-    Body, HttpOrigin, HttpResponder, Origin, ReadHalf, Request, Response, Stream, StreamOrigin,
-    StreamResponder, TcpResponder, WebSocketConnection, WebSocketResponder, WriteHalf,
-    websocket_accept,
+    Body, CancellationToken, EdgeProtocol, FlushPolicy, HttpOrigin, HttpResponder, Origin,
+    OriginError, OriginLimits, ReadHalf, Request, RequestContext, Response, Stream, StreamEnd,
+    StreamKind, StreamOptions, StreamOrigin, StreamResponder, StreamSide, StreamSummary,
+    TcpResponder, TrailerSender, WebSocket, WebSocketConnection, WebSocketKeepalive,
+    WebSocketResponder, WebSocketUpgrade, WriteHalf, websocket_accept,
 };
 #[cfg(all(feature = "quick-tunnel", quic_any))]
 pub use run::{RunOptions, run_quick_tunnel};
-#[cfg(feature = "named-tunnel")]
-pub use tunnel::NamedTunnel;
+#[cfg(feature = "tunnel-api")]
+pub use tunnel::{
+    ConfigSource, CreatedTunnel, DnsRoute, IngressConfiguration, IngressRule, IpRoute,
+    IpRouteFilter, LoadBalancerRoute, OriginCertificate, OriginRequest, RouteChange, TunnelApi,
+    TunnelApiOptions, TunnelConfiguration, TunnelConnection, TunnelFilter, TunnelInfo,
+    VirtualNetwork, WarpRouting,
+};
 #[cfg(any_tunnel)]
-pub use tunnel::Tunnel;
+pub use tunnel::{CredentialProvider, FnCredentials, Tunnel};
+#[cfg(feature = "named-tunnel")]
+pub use tunnel::{EnvCredentials, FileCredentials, NamedTunnel};
 #[cfg(feature = "quick-tunnel")]
 pub use tunnel::{QuickTunnel, QuickTunnelOptions, create_quick_tunnel};
@@ -0,0 +1,186 @@
+//! The `tracing` layer feeding management log sessions.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::time::rfc3339;
+
+/// The severity of a streamed log line, in cloudflared's names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<&Level> for LogLevel {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+/// What a log line is about, in cloudflared's names. Events name theirs
+/// with an `event` field (`event = "http"`); the rest are `cloudflared`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogEvent {
+    Cloudflared,
+    Http,
+    Tcp,
+    Udp,
+}
+
+impl LogEvent {
+    fn parse(value: &str) -> Self {
+        match value {
+            "http" => LogEvent::Http,
+            "tcp" => LogEvent::Tcp,
+            "udp" => LogEvent::Udp,
+            _ => LogEvent::Cloudflared,
+        }
+    }
+}
+
+/// One log line as the dashboard receives it.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LogEntry {
+    pub time: String,
+    pub level: LogLevel,
+    pub message: String,
+    pub event: LogEvent,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub fields: Map<String, Value>,
+}
+
+/// A [`tracing_subscriber::Layer`] that hands events to live management
+/// log sessions.
+///
+/// Get one from [`ManagementLogs::layer`](crate::ManagementLogs::layer) and
+/// add it to the subscriber whose events the dashboard should see. Events
+/// are only formatted while a session is streaming.
+#[derive(Clone)]
+pub struct ManagementLayer {
+    pub(crate) entries: broadcast::Sender<Arc<LogEntry>>,
+}
+
+impl fmt::Debug for ManagementLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManagementLayer")
+            .field("sessions", &self.entries.receiver_count())
+            .finish()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ManagementLayer {
+    fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+        if self.entries.receiver_count() == 0 || is_own(event.metadata().target()) {
+            return;
+        }
+        let mut visitor = Fields::default();
+        event.record(&mut visitor);
+        let entry = LogEntry {
+            time: rfc3339(SystemTime::now()),
+            level: LogLevel::from(event.metadata().level()),
+            message: visitor.message,
+            event: visitor.event,
+            fields: visitor.fields,
+        };
+        let _ = self.entries.send(Arc::new(entry));
+    }
+}
+
+/// Whether an event with `target` comes from the management service
+/// itself; streaming those would feed a session its own logging.
+fn is_own(target: &str) -> bool {
+    target
+        .strip_prefix(super::TARGET)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// Collects an event's message, `event` kind and remaining fields.
+struct Fields {
+    message: String,
+    event: LogEvent,
+    fields: Map<String, Value>,
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Self {
+            message: String::new(),
+            event: LogEvent::Cloudflared,
+            fields: Map::new(),
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            "event" => self.event = LogEvent::parse(value),
+            name => {
+                self.fields.insert(name.to_string(), value.into());
+            }
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn layer_records_events_only_while_watched() {
+        let (entries, _) = broadcast::channel(8);
+        let layer = ManagementLayer {
+            entries: entries.clone(),
+        };
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "app", "nobody is watching");
+            let mut watcher = entries.subscribe();
+            tracing::warn!(target: "app", event = "http", status = 502u64, "origin failed");
+            let entry = watcher.try_recv().unwrap();
+            assert_eq!(entry.level, LogLevel::Warn);
+            assert_eq!(entry.event, LogEvent::Http);
+            assert_eq!(entry.message, "origin failed");
+            assert_eq!(entry.fields["status"], 502);
+            tracing::trace!(target: "libcfd::management::session", "fell behind");
+            assert!(watcher.try_recv().is_err());
+        });
+    }
+}
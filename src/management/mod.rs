@@ -0,0 +1,459 @@
+//! The management service the Cloudflare dashboard reaches through a
+//! tunnel, as cloudflared advertises with the `management_logs` feature.
+//!
+//! The edge forwards dashboard requests for [`MANAGEMENT_HOSTNAME`] over
+//! the tunnel like any other traffic. With
+//! [`EdgeOptions::management`](crate::EdgeOptions) set, those requests are
+//! answered ahead of the origin:
+//!
+//! - `GET /ping`: an empty `200`;
+//! - `GET /host_details`: the connector id and host name;
+//! - `GET /logs` (websocket): live log streaming, with filters by level and
+//!   event type, from the events [`ManagementLayer`] sees.
+//!
+//! Every request must carry the dashboard's `access_token` query
+//! parameter. The edge authenticates the dashboard before forwarding, so,
+//! like cloudflared, libcfd reads the token's claims without verifying its
+//! signature, but only accepts tokens issued for the tunnel being served;
+//! the actor claim keeps one live log session per actor.
+
+mod layer;
+mod session;
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use base64::Engine as _;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+
+use self::layer::LogEntry;
+pub use self::layer::ManagementLayer;
+use crate::edge::control;
+use crate::origin::websocket::CloseFrame;
+use crate::origin::{
+    Body, CancellationToken, HttpOrigin, HttpResponder, Origin, OriginError, Request, Response,
+    StreamOrigin, WebSocketResponder, WebSocketUpgrade,
+};
+
+/// The hostname the edge sends management requests to.
+pub const MANAGEMENT_HOSTNAME: &str = "management.argotunnel.com";
+
+/// Log lines buffered per session before a slow one starts skipping.
+const SESSION_BUFFER: usize = 1024;
+
+/// The target of this module's own events, which [`ManagementLayer`]
+/// leaves out so a session does not stream its own logging.
+const TARGET: &str = module_path!();
+
+/// The id of the tunnel a run currently serves, which management tokens
+/// must be issued for; empty until the run registers.
+pub(crate) type ServedTunnel = Arc<Mutex<String>>;
+
+/// The management service of a connector and the log feed behind it.
+///
+/// Clones share the feed and the session. Add [`layer`](Self::layer) to the
+/// application's `tracing` subscriber and set the service on
+/// [`EdgeOptions::management`](crate::EdgeOptions).
+#[derive(Clone)]
+pub struct ManagementLogs {
+    shared: Arc<Shared>,
+    label: Option<String>,
+}
+
+struct Shared {
+    entries: broadcast::Sender<Arc<LogEntry>>,
+    session: Mutex<Option<ActiveSession>>,
+    next_session: AtomicU64,
+}
+
+/// The live log session, one at a time as in cloudflared.
+struct ActiveSession {
+    id: u64,
+    actor: String,
+    stop: CancellationToken,
+}
+
+impl Default for ManagementLogs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ManagementLogs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManagementLogs")
+            .field("label", &self.label)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ManagementLogs {
+    /// A management service reporting the system host name.
+    pub fn new() -> Self {
+        let (entries, _) = broadcast::channel(SESSION_BUFFER);
+        Self {
+            shared: Arc::new(Shared {
+                entries,
+                session: Mutex::new(None),
+                next_session: AtomicU64::new(0),
+            }),
+            label: None,
+        }
+    }
+
+    /// Reports `label` as the host name instead (cloudflared's `--label`),
+    /// shown as `custom:<label>` in the dashboard.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// The `tracing` layer whose events the log sessions stream.
+    pub fn layer(&self) -> ManagementLayer {
+        ManagementLayer {
+            entries: self.shared.entries.clone(),
+        }
+    }
+
+    /// Wraps `origin` so management requests for the `served` tunnel are
+    /// answered before it.
+    pub(crate) fn route(&self, origin: Origin, served: ServedTunnel) -> Origin {
+        Origin {
+            http: Arc::new(ManagementHttp {
+                management: self.clone(),
+                served: served.clone(),
+                inner: origin.http,
+            }),
+            websocket: Some(Arc::new(ManagementWebSocket {
+                management: self.clone(),
+                served,
+                inner: origin.websocket,
+            })),
+            ..origin
+        }
+    }
+
+    fn answer(&self, request: &Request, tunnel_id: &str) -> Result<Response, OriginError> {
+        access_claims(request, tunnel_id)?;
+        match request.uri.path() {
+            "/ping" => Ok(Response::new(
+                http::StatusCode::OK,
+                http::HeaderMap::new(),
+                Body::empty(),
+            )),
+            "/host_details" => {
+                let mut details = serde_json::json!({ "connector_id": control::connector_id() });
+                if let Some(hostname) = self.hostname() {
+                    details["hostname"] = hostname.into();
+                }
+                let mut headers = http::HeaderMap::new();
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    http::HeaderValue::from_static("application/json"),
+                );
+                Ok(Response::new(
+                    http::StatusCode::OK,
+                    headers,
+                    Body::from_bytes(details.to_string().into_bytes()),
+                ))
+            }
+            "/logs" => Err(failure(
+                http::StatusCode::BAD_REQUEST,
+                1003,
+                "websocket upgrade required",
+            )),
+            _ => Err(failure(http::StatusCode::NOT_FOUND, 1004, "not found")),
+        }
+    }
+
+    fn connect(&self, request: Request, tunnel_id: &str, respond: WebSocketResponder) {
+        let claims = match access_claims(&request, tunnel_id) {
+            Ok(claims) => claims,
+            Err(error) => return respond.fail(error),
+        };
+        if request.uri.path() != "/logs" {
+            return respond.fail(failure(http::StatusCode::NOT_FOUND, 1004, "not found"));
+        }
+        let upgrade = match WebSocketUpgrade::from_request(&request) {
+            Ok(upgrade) => upgrade,
+            Err(error) => return respond.fail(error),
+        };
+        let (connection, socket) = upgrade.accept();
+        respond.upgrade(connection);
+
+        let shared = self.shared.clone();
+        let stop = CancellationToken::new();
+        let id = shared.next_session.fetch_add(1, Ordering::Relaxed);
+        let admitted = {
+            let mut current = shared.session.lock().unwrap();
+            if current
+                .as_ref()
+                .is_some_and(|active| active.actor != claims.actor.id)
+            {
+                false
+            } else {
+                // The same actor reconnecting replaces its old session.
+                let replaced = current.replace(ActiveSession {
+                    id,
+                    actor: claims.actor.id,
+                    stop: stop.clone(),
+                });
+                if let Some(replaced) = replaced {
+                    replaced.stop.cancel();
+                }
+                true
+            }
+        };
+        tokio::spawn(async move {
+            if !admitted {
+                let frame = CloseFrame::new(session::CLOSE_SESSION_LIMIT, "limit exceeded");
+                let _ = socket.close(Some(frame)).await;
+                return;
+            }
+            tracing::debug!("management log session started");
+            session::run(socket, shared.entries.clone(), stop).await;
+            let mut current = shared.session.lock().unwrap();
+            if current.as_ref().is_some_and(|active| active.id == id) {
+                *current = None;
+            }
+        });
+    }
+
+    fn hostname(&self) -> Option<String> {
+        if let Some(label) = &self.label {
+            return Some(format!("custom:{label}"));
+        }
+        std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    }
+}
+
+/// Answers management HTTP requests and hands the rest to the origin.
+struct ManagementHttp {
+    management: ManagementLogs,
+    served: ServedTunnel,
+    inner: Arc<dyn HttpOrigin>,
+}
+
+impl HttpOrigin for ManagementHttp {
+    fn handle(&self, request: Request, respond: HttpResponder) {
+        if !is_management(&request) {
+            return self.inner.handle(request, respond);
+        }
+        let tunnel_id = self.served.lock().unwrap().clone();
+        let outcome = self
+            .management
+            .answer(&request, &tunnel_id)
+            .or_else(OriginError::into_response);
+        match outcome {
+            Ok(mut response) => {
+                allow_dashboard(&request, &mut response.headers);
+                respond.send(response);
+            }
+            Err(message) => respond.fail(message),
+        }
+    }
+}
+
+/// Accepts management log sessions and hands other upgrades to the
+/// origin's websocket handler, if any.
+struct ManagementWebSocket {
+    management: ManagementLogs,
+    served: ServedTunnel,
+    inner: Option<Arc<dyn StreamOrigin<WebSocketResponder>>>,
+}
+
+impl StreamOrigin<WebSocketResponder> for ManagementWebSocket {
+    fn connect(&self, request: Request, respond: WebSocketResponder) {
+        if is_management(&request) {
+            let tunnel_id = self.served.lock().unwrap().clone();
+            return self.management.connect(request, &tunnel_id, respond);
+        }
+        match &self.inner {
+            Some(inner) => inner.connect(request, respond),
+            None => respond.fail(OriginError::bad_gateway("no websocket origin handler")),
+        }
+    }
+}
+
+fn is_management(request: &Request) -> bool {
+    let host = request.uri.host().or_else(|| {
+        request
+            .headers
+            .get(http::header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .map(|host| host.split(':').next().unwrap_or(host))
+    });
+    host.is_some_and(|host| host.eq_ignore_ascii_case(MANAGEMENT_HOSTNAME))
+}
+
+/// Lets the dashboard's pages on `*.cloudflare.com` read the responses.
+fn allow_dashboard(request: &Request, headers: &mut http::HeaderMap) {
+    if let Some(origin) = request.headers.get(http::header::ORIGIN)
+        && let Ok(value) = origin.to_str()
+        && value.starts_with("https://")
+        && (value.ends_with(".cloudflare.com") || value == "https://cloudflare.com")
+    {
+        headers.insert(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        headers.insert(http::header::VARY, http::HeaderValue::from_static("Origin"));
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessClaims {
+    tun: TunnelClaim,
+    actor: ActorClaim,
+}
+
+#[derive(Debug, Deserialize)]
+struct TunnelClaim {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActorClaim {
+    id: String,
+}
+
+/// Reads the claims of the request's `access_token`, or the `400` that
+/// rejects it when it is missing or not issued for `tunnel_id`.
+fn access_claims(request: &Request, tunnel_id: &str) -> Result<AccessClaims, OriginError> {
+    let token = request
+        .uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("access_token="))
+        .filter(|token| !token.is_empty());
+    let Some(token) = token else {
+        return Err(failure(
+            http::StatusCode::BAD_REQUEST,
+            1001,
+            "missing access_token query parameter",
+        ));
+    };
+    parse_token(token, tunnel_id).ok_or_else(|| {
+        failure(
+            http::StatusCode::BAD_REQUEST,
+            1002,
+            "invalid access_token query parameter",
+        )
+    })
+}
+
+/// Decodes the claims of a JWT without verifying its signature, keeping
+/// them only when the token names the tunnel `tunnel_id`.
+fn parse_token(token: &str, tunnel_id: &str) -> Option<AccessClaims> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: AccessClaims = serde_json::from_slice(&bytes).ok()?;
+    (!claims.tun.id.is_empty() && claims.tun.id.eq_ignore_ascii_case(tunnel_id)).then_some(claims)
+}
+
+/// A rejection carrying cloudflared's management error body.
+fn failure(status: http::StatusCode, code: u32, message: &str) -> OriginError {
+    let body = serde_json::json!({
+        "success": false,
+        "errors": [{ "code": code, "message": message }],
+    });
+    OriginError::new(status, format!("management request rejected: {message}"))
+        .with_body("application/json", body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::io::AsyncReadExt;
+
+    use super::*;
+    use crate::origin::wait_outcome;
+
+    fn token(claims: &str) -> String {
+        let encode = |part: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(part);
+        format!(
+            "{}.{}.signature",
+            encode(r#"{"alg":"ES256"}"#),
+            encode(claims)
+        )
+    }
+
+    fn request(uri: &str) -> Request {
+        Request::new(
+            http::Method::GET,
+            uri.parse().unwrap(),
+            http::HeaderMap::new(),
+            Body::empty(),
+        )
+    }
+
+    fn origin(management: &ManagementLogs) -> Origin {
+        let served = ServedTunnel::default();
+        *served.lock().unwrap() = "t1".to_string();
+        management.route(
+            Origin::http(|_request: Request, respond: HttpResponder| {
+                respond.send(Response::new(
+                    http::StatusCode::IM_A_TEAPOT,
+                    http::HeaderMap::new(),
+                    Body::empty(),
+                ))
+            }),
+            served,
+        )
+    }
+
+    #[tokio::test]
+    async fn answers_management_requests_ahead_of_the_origin() {
+        let management = ManagementLogs::new().with_label("edge-box");
+        let origin = origin(&management);
+        let valid = token(r#"{"tun":{"id":"t1","account_tag":"a"},"actor":{"id":"u1"}}"#);
+
+        let (respond, receiver) = HttpResponder::channel();
+        origin
+            .http
+            .handle(request("https://example.com/ping"), respond);
+        let response = wait_outcome(receiver).await.unwrap();
+        assert_eq!(response.status, http::StatusCode::IM_A_TEAPOT);
+
+        let uri = format!("https://{MANAGEMENT_HOSTNAME}/host_details?access_token={valid}");
+        let (respond, receiver) = HttpResponder::channel();
+        origin.http.handle(request(&uri), respond);
+        let mut response = wait_outcome(receiver).await.unwrap();
+        assert_eq!(response.status, http::StatusCode::OK);
+        let mut body = Vec::new();
+        response.body.read_to_end(&mut body).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["hostname"], "custom:edge-box");
+        assert_eq!(body["connector_id"], control::connector_id());
+
+        let uri = format!("https://{MANAGEMENT_HOSTNAME}/ping");
+        let (respond, receiver) = HttpResponder::channel();
+        origin.http.handle(request(&uri), respond);
+        let response = wait_outcome(receiver).await.unwrap();
+        assert_eq!(response.status, http::StatusCode::BAD_REQUEST);
+
+        let other = token(r#"{"tun":{"id":"t2","account_tag":"a"},"actor":{"id":"u1"}}"#);
+        let uri = format!("https://{MANAGEMENT_HOSTNAME}/ping?access_token={other}");
+        let (respond, receiver) = HttpResponder::channel();
+        origin.http.handle(request(&uri), respond);
+        let response = wait_outcome(receiver).await.unwrap();
+        assert_eq!(response.status, http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn rejects_tokens_for_other_tunnels() {
+        assert!(parse_token("not-a-jwt", "t1").is_none());
+        assert!(parse_token(&token(r#"{"tun":{"id":""},"actor":{"id":"u1"}}"#), "").is_none());
+        let valid = token(r#"{"tun":{"id":"t1"},"actor":{"id":"u1"}}"#);
+        assert!(parse_token(&valid, "t2").is_none());
+        assert!(parse_token(&valid, "").is_none());
+        let claims = parse_token(&valid, "t1").unwrap();
+        assert_eq!(claims.actor.id, "u1");
+    }
+}
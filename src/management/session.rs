@@ -0,0 +1,199 @@
+//! One dashboard log session over a websocket.
+//!
+//! The client starts streaming with a `start_streaming` event carrying
+//! optional filters and may stop with `stop_streaming`; libcfd answers with
+//! `logs` events batching every line available at the time.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+use super::layer::{LogEntry, LogEvent, LogLevel};
+use crate::origin::websocket::{CloseFrame, Message};
+use crate::origin::{CancellationToken, WebSocket};
+
+/// Close code for a malformed or unexpected client event.
+pub(crate) const CLOSE_INVALID_COMMAND: u16 = 4001;
+/// Close code when another actor already holds the session.
+pub(crate) const CLOSE_SESSION_LIMIT: u16 = 4002;
+/// Close code for a session that never started streaming.
+const CLOSE_IDLE_LIMIT: u16 = 4003;
+
+/// How long a connected session may go without streaming.
+const IDLE_LIMIT: Duration = Duration::from_secs(5 * 60);
+
+/// The most lines sent in one `logs` event.
+const BATCH_LIMIT: usize = 256;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientEvent {
+    StartStreaming {
+        #[serde(default)]
+        filters: Option<Filters>,
+    },
+    StopStreaming,
+}
+
+/// Which lines a session wants.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct Filters {
+    /// Event kinds to include; empty includes all.
+    #[serde(default)]
+    events: Vec<LogEvent>,
+    /// The lowest level to include.
+    #[serde(default)]
+    level: Option<LogLevel>,
+    /// The fraction of lines to keep, in `(0, 1]`; zero keeps all.
+    #[serde(default)]
+    sampling: f64,
+}
+
+impl Filters {
+    fn accepts(&self, entry: &LogEntry) -> bool {
+        if !self.events.is_empty() && !self.events.contains(&entry.event) {
+            return false;
+        }
+        if self.level.is_some_and(|level| entry.level < level) {
+            return false;
+        }
+        if self.sampling > 0.0 && self.sampling < 1.0 {
+            let mut bytes = [0u8; 4];
+            let _ = getrandom::fill(&mut bytes);
+            let draw = f64::from(u32::from_le_bytes(bytes)) / f64::from(u32::MAX);
+            return draw < self.sampling;
+        }
+        true
+    }
+}
+
+/// Runs a session until the client leaves, misbehaves, idles, or `stop`
+/// fires because the same actor opened a newer session.
+pub(crate) async fn run(
+    mut socket: WebSocket,
+    entries: broadcast::Sender<Arc<LogEntry>>,
+    stop: CancellationToken,
+) {
+    let mut streaming: Option<(Filters, broadcast::Receiver<Arc<LogEntry>>)> = None;
+    let idle = tokio::time::sleep(IDLE_LIMIT);
+    tokio::pin!(idle);
+    loop {
+        let idling = streaming.is_none();
+        tokio::select! {
+            _ = stop.cancelled() => {
+                let _ = socket.close(Some(CloseFrame::new(1000, "session replaced"))).await;
+                return;
+            }
+            _ = &mut idle, if idling => {
+                let frame = CloseFrame::new(CLOSE_IDLE_LIMIT, "session was idle for too long");
+                let _ = socket.close(Some(frame)).await;
+                return;
+            }
+            entry = async {
+                match &mut streaming {
+                    Some((_, receiver)) => receiver.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => match entry {
+                Ok(entry) => {
+                    let Some((filters, receiver)) = &mut streaming else {
+                        continue;
+                    };
+                    let mut batch: Vec<Arc<LogEntry>> = Vec::new();
+                    if filters.accepts(&entry) {
+                        batch.push(entry);
+                    }
+                    while batch.len() < BATCH_LIMIT {
+                        match receiver.try_recv() {
+                            Ok(entry) if filters.accepts(&entry) => batch.push(entry),
+                            Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                            Err(_) => break,
+                        }
+                    }
+                    if !batch.is_empty()
+                        && socket.send(Message::Text(logs_event(&batch))).await.is_err()
+                    {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::trace!(skipped, "management log session fell behind");
+                }
+                Err(RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ClientEvent::StartStreaming { filters }) => {
+                        if streaming.is_none() {
+                            streaming = Some((filters.unwrap_or_default(), entries.subscribe()));
+                        }
+                    }
+                    Ok(ClientEvent::StopStreaming) => {
+                        streaming = None;
+                        idle.as_mut().reset(tokio::time::Instant::now() + IDLE_LIMIT);
+                    }
+                    Err(_) => {
+                        let frame = CloseFrame::new(
+                            CLOSE_INVALID_COMMAND,
+                            "expected start_streaming or stop_streaming",
+                        );
+                        let _ = socket.close(Some(frame)).await;
+                        return;
+                    }
+                },
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => return,
+            },
+        }
+    }
+}
+
+fn logs_event(batch: &[Arc<LogEntry>]) -> String {
+    #[derive(serde::Serialize)]
+    struct LogsEvent<'a> {
+        r#type: &'static str,
+        logs: Vec<&'a LogEntry>,
+    }
+    let event = LogsEvent {
+        r#type: "logs",
+        logs: batch.iter().map(|entry| &**entry).collect(),
+    };
+    serde_json::to_string(&event).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: LogLevel, event: LogEvent) -> LogEntry {
+        LogEntry {
+            time: "1970-01-01T00:00:00.000Z".into(),
+            level,
+            message: "m".into(),
+            event,
+            fields: serde_json::Map::new(),
+        }
+    }
+
+    #[test]
+    fn filters_by_event_and_level() {
+        let filters: Filters =
+            serde_json::from_str(r#"{"events":["http"],"level":"warn"}"#).unwrap();
+        assert!(filters.accepts(&entry(LogLevel::Error, LogEvent::Http)));
+        assert!(!filters.accepts(&entry(LogLevel::Info, LogEvent::Http)));
+        assert!(!filters.accepts(&entry(LogLevel::Error, LogEvent::Tcp)));
+        assert!(Filters::default().accepts(&entry(LogLevel::Debug, LogEvent::Cloudflared)));
+    }
+
+    #[test]
+    fn batches_logs_like_cloudflared() {
+        let batch = [Arc::new(entry(LogLevel::Info, LogEvent::Cloudflared))];
+        assert_eq!(
+            logs_event(&batch),
+            r#"{"type":"logs","logs":[{"time":"1970-01-01T00:00:00.000Z","level":"info","message":"m","event":"cloudflared"}]}"#
+        );
+    }
+}
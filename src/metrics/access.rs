@@ -0,0 +1,69 @@
+//! One record per proxied request and stream, for access logging.
+
+use std::time::Duration;
+
+use super::RequestKind;
+
+/// How a request or stream ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessOutcome {
+    /// The origin answered and the answer reached the edge.
+    Success,
+    /// The origin failed, or no handler was set for the request kind; the
+    /// edge got an error response.
+    OriginFailure,
+    /// The edge went away (reset the stream or dropped the connection)
+    /// before the exchange completed.
+    EdgeReset,
+}
+
+/// What went through the tunnel for one HTTP request, websocket or TCP
+/// stream.
+#[derive(Debug, Clone)]
+pub struct AccessRecord {
+    /// The kind of request.
+    pub kind: RequestKind,
+    /// The request method; `None` for TCP streams.
+    pub method: Option<http::Method>,
+    /// The `Host` the visitor asked for, or the TCP destination.
+    pub host: Option<String>,
+    /// The request path and query; `None` for TCP streams.
+    pub path: Option<String>,
+    /// The status sent to the edge, as for
+    /// [`MetricsRecorder::request_finished`](super::MetricsRecorder::request_finished).
+    pub status: Option<u16>,
+    /// Body or stream bytes received from the edge.
+    pub bytes_in: u64,
+    /// Body or stream bytes sent to the edge.
+    pub bytes_out: u64,
+    /// From the request's arrival until its response or stream finished.
+    pub duration: Duration,
+    /// The index of the tunnel connection that carried it.
+    pub connection_index: u8,
+    /// The edge location of that connection, once known.
+    pub colo: Option<String>,
+    /// The `Cf-Ray` id of the request.
+    pub ray_id: Option<String>,
+    /// How the exchange ended.
+    pub outcome: AccessOutcome,
+}
+
+/// Receives an [`AccessRecord`] once every request and stream is done.
+///
+/// Set one on [`EdgeOptions::access_log`](crate::EdgeOptions). It is
+/// called on the transport's task after the exchange ends, so it should
+/// hand the record off (to a channel, a buffered writer, `tracing`) rather
+/// than block. Closures taking `&AccessRecord` implement it.
+pub trait AccessLog: Send + Sync {
+    /// Logs one finished request or stream.
+    fn log(&self, record: &AccessRecord);
+}
+
+impl<F> AccessLog for F
+where
+    F: Fn(&AccessRecord) + Send + Sync + 'static,
+{
+    fn log(&self, record: &AccessRecord) {
+        (self)(record)
+    }
+}
@@ -0,0 +1,132 @@
+//! A minimal HTTP/1.1 listener for the local scrape and probe endpoints.
+//!
+//! Each connection gets one `GET` answered from a handler keyed by path and
+//! is then closed, which is all Prometheus scrapers and orchestrator probes
+//! need.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The largest request head the listener reads.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a handler answers for one path.
+pub(crate) struct Reply {
+    pub status: http::StatusCode,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Reply {
+    pub(crate) fn new(
+        status: http::StatusCode,
+        content_type: &'static str,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    pub(crate) fn not_found() -> Self {
+        Self::new(
+            http::StatusCode::NOT_FOUND,
+            "text/plain; charset=utf-8",
+            "not found\n",
+        )
+    }
+}
+
+/// Answers `GET` requests on `address` with `handler`, given the request
+/// path without its query, until the future is dropped. Fails only if the
+/// listener cannot be bound.
+pub(crate) async fn serve<F>(address: SocketAddr, handler: F) -> io::Result<()>
+where
+    F: Fn(&str) -> Reply + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(address).await?;
+    serve_listener(listener, handler).await
+}
+
+/// Answers `GET` requests on an already bound `listener` with `handler`,
+/// until the future is dropped.
+pub(crate) async fn serve_listener<F>(listener: TcpListener, handler: F) -> io::Result<()>
+where
+    F: Fn(&str) -> Reply + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    loop {
+        match listener.accept().await {
+            Ok((socket, _)) => {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = answer(socket, &*handler).await {
+                        tracing::debug!("local endpoint request failed: {e}");
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::debug!("local endpoint accept failed: {e}");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+async fn answer(mut socket: TcpStream, handler: &(dyn Fn(&str) -> Reply + Sync)) -> io::Result<()> {
+    let mut head = Vec::new();
+    let read = async {
+        let mut chunk = [0u8; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            if head.len() > MAX_REQUEST_HEAD {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "request head too large",
+                ));
+            }
+            let n = socket.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            head.extend_from_slice(&chunk[..n]);
+        }
+        Ok(())
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, read)
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+    let line = head.split(|byte| *byte == b'\r').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(line);
+    let mut parts = line.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let reply = if method == "GET" {
+        handler(path)
+    } else {
+        Reply::new(
+            http::StatusCode::METHOD_NOT_ALLOWED,
+            "text/plain; charset=utf-8",
+            "method not allowed\n",
+        )
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status,
+        reply.content_type,
+        reply.body.len(),
+        reply.body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
@@ -0,0 +1,398 @@
+//! Metrics for tunnel connections and the traffic they carry.
+//!
+//! libcfd reports what happens during a run to a [`MetricsRecorder`] set on
+//! [`EdgeOptions::metrics`](crate::EdgeOptions): connection registrations
+//! and reconnects from the connector, every request and stream from both
+//! transports, bytes proxied by websocket and TCP streams, and periodic
+//! [`QuicStats`] samples of the QUIC connection. The recorder decides what
+//! to keep; every method has an empty default, so an implementation only
+//! overrides the events it cares about.
+//!
+//! With the `prometheus` feature, [`PrometheusRecorder`] keeps the counters
+//! cloudflared exposes on `/metrics` and renders them in the Prometheus
+//! text format, optionally serving them on a local listener.
+//!
+//! An [`AccessLog`] set on [`EdgeOptions::access_log`](crate::EdgeOptions)
+//! gets one [`AccessRecord`] per request and stream instead of counters.
+//!
+//! [`Readiness`] answers whether a connector currently has a connection
+//! registered, for orchestrator probes, like cloudflared's `/ready`.
+
+mod access;
+#[cfg(any(feature = "prometheus", edge_conn))]
+mod listener;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod quic;
+#[cfg(edge_conn)]
+mod readiness;
+
+pub use access::{AccessLog, AccessOutcome, AccessRecord};
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusRecorder;
+pub use quic::QuicStats;
+#[cfg(edge_conn)]
+pub(crate) use quic::QuicStatsSource;
+#[cfg(quic_any)]
+pub(crate) use quic::StatsReader;
+#[cfg(edge_conn)]
+pub use readiness::Readiness;
+
+use std::time::Duration;
+
+#[cfg(edge_conn)]
+use std::sync::Arc;
+#[cfg(edge_conn)]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(edge_conn)]
+use std::time::Instant;
+
+#[cfg(edge_conn)]
+use bytes::Bytes;
+
+#[cfg(edge_conn)]
+use crate::origin::Request;
+use crate::origin::{EdgeProtocol, StreamSummary};
+
+/// The kind of request the edge sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestKind {
+    /// An HTTP request.
+    Http,
+    /// A websocket upgrade.
+    WebSocket,
+    /// A raw TCP stream.
+    Tcp,
+}
+
+impl RequestKind {
+    /// The lowercase name used in metric labels.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RequestKind::Http => "http",
+            RequestKind::WebSocket => "websocket",
+            RequestKind::Tcp => "tcp",
+        }
+    }
+}
+
+/// Receives the events of a tunnel run.
+///
+/// Methods are called inline on the connector's and transports' tasks, so
+/// they should only update counters and return.
+pub trait MetricsRecorder: Send + Sync {
+    /// A connection registered with the edge; `latency` is the time the
+    /// registration took.
+    fn connection_registered(&self, protocol: EdgeProtocol, latency: Duration) {
+        let _ = (protocol, latency);
+    }
+
+    /// A registered connection ended.
+    fn connection_closed(&self, protocol: EdgeProtocol) {
+        let _ = protocol;
+    }
+
+    /// A connection failed and the connector is retrying; `attempt` counts
+    /// the failures since the last healthy connection.
+    fn reconnecting(&self, attempt: u32) {
+        let _ = attempt;
+    }
+
+    /// The edge sent a request or opened a stream.
+    fn request_started(&self, kind: RequestKind) {
+        let _ = kind;
+    }
+
+    /// A request or stream finished. `status` is the HTTP status sent back
+    /// to the edge (`502` when the origin failed without a response):
+    /// `None` for connected TCP streams and for requests the edge abandoned
+    /// before the origin answered.
+    fn request_finished(&self, kind: RequestKind, status: Option<u16>, duration: Duration) {
+        let _ = (kind, status, duration);
+    }
+
+    /// A websocket or TCP stream finished pumping.
+    fn stream_finished(&self, summary: &StreamSummary) {
+        let _ = summary;
+    }
+
+    /// A periodic sample of a registered QUIC connection's transport
+    /// state.
+    fn quic_stats(&self, stats: &QuicStats) {
+        let _ = stats;
+    }
+}
+
+/// A shared recorder, as stored on [`EdgeOptions`](crate::EdgeOptions).
+#[cfg(edge_conn)]
+pub(crate) type Recorder = Arc<dyn MetricsRecorder>;
+
+/// A shared access log, as stored on [`EdgeOptions`](crate::EdgeOptions).
+#[cfg(edge_conn)]
+pub(crate) type SharedAccessLog = Arc<dyn AccessLog>;
+
+/// Reports one request from its arrival until it is dropped, whichever
+/// way the handler returns: to the recorder, and to the access log if one
+/// is set.
+#[cfg(edge_conn)]
+pub(crate) struct RequestMetric {
+    recorder: Option<Recorder>,
+    kind: RequestKind,
+    started: Instant,
+    status: Option<u16>,
+    access: Option<Access>,
+}
+
+/// What the access log needs beyond the metrics.
+#[cfg(edge_conn)]
+struct Access {
+    log: SharedAccessLog,
+    record: AccessRecord,
+    /// Request body bytes, counted as the handler reads them.
+    received: Arc<AtomicU64>,
+    origin_failed: bool,
+    completed: bool,
+}
+
+#[cfg(edge_conn)]
+impl RequestMetric {
+    pub(crate) fn start(recorder: Option<&Recorder>, kind: RequestKind) -> Self {
+        if let Some(recorder) = recorder {
+            recorder.request_started(kind);
+        }
+        Self {
+            recorder: recorder.cloned(),
+            kind,
+            started: Instant::now(),
+            status: None,
+            access: None,
+        }
+    }
+
+    /// Also reports the request to `log`, if set.
+    pub(crate) fn logging(mut self, log: Option<&SharedAccessLog>) -> Self {
+        self.access = log.map(|log| Access {
+            log: log.clone(),
+            record: AccessRecord {
+                kind: self.kind,
+                method: None,
+                host: None,
+                path: None,
+                status: None,
+                bytes_in: 0,
+                bytes_out: 0,
+                duration: Duration::ZERO,
+                connection_index: 0,
+                colo: None,
+                ray_id: None,
+                outcome: AccessOutcome::EdgeReset,
+            },
+            received: Arc::default(),
+            origin_failed: false,
+            completed: false,
+        });
+        self
+    }
+
+    /// Records what was requested, once the transport has attached the
+    /// request's context.
+    pub(crate) fn request(&mut self, request: &Request) {
+        let Some(access) = &mut self.access else {
+            return;
+        };
+        let record = &mut access.record;
+        let authority = request
+            .uri
+            .authority()
+            .map(|authority| authority.to_string());
+        if self.kind == RequestKind::Tcp {
+            record.host = authority;
+        } else {
+            record.method = Some(request.method.clone());
+            record.host = request
+                .headers
+                .get(http::header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .map(str::to_string)
+                .or(authority);
+            record.path = request
+                .uri
+                .path_and_query()
+                .map(|path| path.as_str().to_string());
+        }
+        if let Some(context) = request.context() {
+            record.connection_index = context.connection_index();
+            record.colo = context.colo().map(str::to_string);
+            record.ray_id = context.ray_id().map(str::to_string);
+        }
+    }
+
+    /// Counts the request body bytes the handler reads from `chunks`.
+    pub(crate) fn count_received<S>(
+        &self,
+        chunks: S,
+    ) -> impl futures_util::Stream<Item = std::io::Result<Bytes>> + use<S>
+    where
+        S: futures_util::Stream<Item = std::io::Result<Bytes>>,
+    {
+        let received = self.access.as_ref().map(|access| access.received.clone());
+        futures_util::StreamExt::inspect(chunks, move |chunk| {
+            if let (Some(received), Ok(chunk)) = (&received, chunk) {
+                received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// Records the status sent to the edge.
+    pub(crate) fn status(&mut self, status: http::StatusCode) {
+        self.status = Some(status.as_u16());
+    }
+
+    /// Records that the origin failed (or no handler was set), whatever the
+    /// edge is sent instead.
+    pub(crate) fn origin_failed(&mut self) {
+        if let Some(access) = &mut self.access {
+            access.origin_failed = true;
+        }
+    }
+
+    /// Records a response whose `sent` body bytes reached the edge.
+    pub(crate) fn responded(&mut self, sent: u64) {
+        if let Some(access) = &mut self.access {
+            access.record.bytes_out = sent;
+            access.completed = true;
+        }
+    }
+
+    /// Records a websocket or TCP stream that finished pumping.
+    pub(crate) fn streamed(&mut self, summary: &StreamSummary) {
+        if let Some(access) = &mut self.access {
+            access
+                .received
+                .store(summary.bytes_from_edge, Ordering::Relaxed);
+            access.record.bytes_out = summary.bytes_to_edge;
+            access.completed = true;
+        }
+    }
+}
+
+#[cfg(edge_conn)]
+impl Drop for RequestMetric {
+    fn drop(&mut self) {
+        let duration = self.started.elapsed();
+        if let Some(recorder) = &self.recorder {
+            recorder.request_finished(self.kind, self.status, duration);
+        }
+        if let Some(access) = &mut self.access {
+            let record = &mut access.record;
+            record.status = self.status;
+            record.bytes_in = access.received.load(Ordering::Relaxed);
+            record.duration = duration;
+            record.outcome = if access.origin_failed {
+                AccessOutcome::OriginFailure
+            } else if access.completed {
+                AccessOutcome::Success
+            } else {
+                AccessOutcome::EdgeReset
+            };
+            access.log.log(record);
+        }
+    }
+}
+
+#[cfg(all(test, edge_conn))]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl MetricsRecorder for Events {
+        fn request_started(&self, kind: RequestKind) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("start {}", kind.as_str()));
+        }
+
+        fn request_finished(&self, kind: RequestKind, status: Option<u16>, _duration: Duration) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("finish {} {status:?}", kind.as_str()));
+        }
+    }
+
+    #[test]
+    fn request_metric_reports_on_drop() {
+        let events = Arc::new(Events::default());
+        let recorder: Recorder = events.clone();
+        {
+            let mut metric = RequestMetric::start(Some(&recorder), RequestKind::Http);
+            metric.status(http::StatusCode::NOT_FOUND);
+        }
+        drop(RequestMetric::start(Some(&recorder), RequestKind::Tcp));
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [
+                "start http",
+                "finish http Some(404)",
+                "start tcp",
+                "finish tcp None"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn request_metric_writes_the_access_record() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let log: SharedAccessLog = {
+            let records = records.clone();
+            Arc::new(move |record: &AccessRecord| records.lock().unwrap().push(record.clone()))
+        };
+        let mut request = Request::new(
+            http::Method::POST,
+            "http://origin.internal/upload?part=1".parse().unwrap(),
+            http::HeaderMap::new(),
+            crate::origin::Body::empty(),
+        );
+        request
+            .headers
+            .insert(http::header::HOST, "app.example.com".parse().unwrap());
+        {
+            let mut metric = RequestMetric::start(None, RequestKind::Http).logging(Some(&log));
+            metric.request(&request);
+            let chunks = futures_util::stream::iter([Ok(Bytes::from_static(b"hello"))]);
+            let read: Vec<_> = metric.count_received(chunks).collect().await;
+            assert_eq!(read.len(), 1);
+            metric.status(http::StatusCode::CREATED);
+            metric.responded(2);
+        }
+        {
+            let mut metric = RequestMetric::start(None, RequestKind::Tcp).logging(Some(&log));
+            metric.request(&Request::tcp("db.internal:5432"));
+            metric.origin_failed();
+        }
+        drop(RequestMetric::start(None, RequestKind::WebSocket).logging(Some(&log)));
+
+        let records = records.lock().unwrap();
+        let http = &records[0];
+        assert_eq!(http.method, Some(http::Method::POST));
+        assert_eq!(http.host.as_deref(), Some("app.example.com"));
+        assert_eq!(http.path.as_deref(), Some("/upload?part=1"));
+        assert_eq!(
+            (http.status, http.bytes_in, http.bytes_out),
+            (Some(201), 5, 2)
+        );
+        assert_eq!(http.outcome, AccessOutcome::Success);
+        let tcp = &records[1];
+        assert_eq!(tcp.host.as_deref(), Some("db.internal:5432"));
+        assert!(tcp.method.is_none() && tcp.path.is_none());
+        assert_eq!(tcp.outcome, AccessOutcome::OriginFailure);
+        assert_eq!(records[2].outcome, AccessOutcome::EdgeReset);
+    }
+}
@@ -0,0 +1,293 @@
+//! A recorder keeping cloudflared-style counters, rendered in the
+//! Prometheus text exposition format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::listener::{self, Reply};
+use super::{MetricsRecorder, QuicStats, RequestKind};
+use crate::origin::{EdgeProtocol, StreamSummary};
+
+/// A [`MetricsRecorder`] that keeps the counters and gauges of a run and
+/// renders them for Prometheus.
+///
+/// Share one recorder between the runs whose metrics should be summed, or
+/// give each run its own. [`render`](Self::render) produces the exposition
+/// text for an existing HTTP server; [`serve`](Self::serve) answers
+/// `GET /metrics` on a listener of its own.
+#[derive(Debug, Default)]
+pub struct PrometheusRecorder {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    requests: BTreeMap<&'static str, u64>,
+    responses: BTreeMap<u16, u64>,
+    concurrent_requests: u64,
+    ha_connections: u64,
+    registrations: u64,
+    registration_seconds: f64,
+    reconnects: u64,
+    bytes_from_edge: u64,
+    bytes_to_edge: u64,
+    quic: Option<QuicStats>,
+}
+
+impl PrometheusRecorder {
+    /// A recorder with every counter at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders the current values in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        header(
+            &mut out,
+            "libcfd_tunnel_total_requests",
+            "counter",
+            "Requests and streams received from the edge.",
+        );
+        for kind in [RequestKind::Http, RequestKind::WebSocket, RequestKind::Tcp] {
+            let count = state.requests.get(kind.as_str()).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "libcfd_tunnel_total_requests{{kind=\"{}\"}} {count}",
+                kind.as_str()
+            );
+        }
+        header(
+            &mut out,
+            "libcfd_tunnel_response_by_code",
+            "counter",
+            "Responses sent to the edge, by HTTP status.",
+        );
+        for (status, count) in &state.responses {
+            let _ = writeln!(
+                out,
+                "libcfd_tunnel_response_by_code{{status_code=\"{status}\"}} {count}"
+            );
+        }
+        gauge(
+            &mut out,
+            "libcfd_tunnel_concurrent_requests",
+            "Requests and streams in flight.",
+            state.concurrent_requests,
+        );
+        gauge(
+            &mut out,
+            "libcfd_tunnel_ha_connections",
+            "Connections registered with the edge.",
+            state.ha_connections,
+        );
+        counter(
+            &mut out,
+            "libcfd_tunnel_reconnects_total",
+            "Connection attempts retried after a failure.",
+            state.reconnects,
+        );
+        header(
+            &mut out,
+            "libcfd_tunnel_registration_latency_seconds",
+            "summary",
+            "Time taken to register connections with the edge.",
+        );
+        let _ = writeln!(
+            out,
+            "libcfd_tunnel_registration_latency_seconds_sum {}",
+            state.registration_seconds
+        );
+        let _ = writeln!(
+            out,
+            "libcfd_tunnel_registration_latency_seconds_count {}",
+            state.registrations
+        );
+        header(
+            &mut out,
+            "libcfd_tunnel_stream_bytes_total",
+            "counter",
+            "Bytes proxied by websocket and TCP streams.",
+        );
+        let _ = writeln!(
+            out,
+            "libcfd_tunnel_stream_bytes_total{{direction=\"from_edge\"}} {}",
+            state.bytes_from_edge
+        );
+        let _ = writeln!(
+            out,
+            "libcfd_tunnel_stream_bytes_total{{direction=\"to_edge\"}} {}",
+            state.bytes_to_edge
+        );
+        if let Some(quic) = &state.quic {
+            header(
+                &mut out,
+                "libcfd_quic_rtt_seconds",
+                "gauge",
+                "Smoothed round-trip time of the latest QUIC connection.",
+            );
+            let _ = writeln!(out, "libcfd_quic_rtt_seconds {}", quic.rtt.as_secs_f64());
+            gauge(
+                &mut out,
+                "libcfd_quic_congestion_window_bytes",
+                "Congestion window of the latest QUIC connection.",
+                quic.congestion_window,
+            );
+            gauge(
+                &mut out,
+                "libcfd_quic_sent_packets",
+                "Packets sent on the latest QUIC connection.",
+                quic.packets_sent,
+            );
+            gauge(
+                &mut out,
+                "libcfd_quic_lost_packets",
+                "Packets lost on the latest QUIC connection.",
+                quic.packets_lost,
+            );
+        }
+        out
+    }
+
+    /// Answers `GET /metrics` with [`render`](Self::render) on `address`
+    /// (e.g. `127.0.0.1:2000`, cloudflared's default metrics port range)
+    /// until the returned future is dropped. Fails only if the listener
+    /// cannot be bound.
+    pub async fn serve(self: Arc<Self>, address: SocketAddr) -> io::Result<()> {
+        tracing::debug!(%address, "serving metrics");
+        listener::serve(address, self.exposition()).await
+    }
+
+    /// The listener handler answering `/metrics`.
+    fn exposition(self: Arc<Self>) -> impl Fn(&str) -> Reply + Send + Sync + 'static {
+        move |path| match path {
+            "/metrics" => Reply::new(
+                http::StatusCode::OK,
+                "text/plain; version=0.0.4; charset=utf-8",
+                self.render(),
+            ),
+            _ => Reply::not_found(),
+        }
+    }
+}
+
+impl MetricsRecorder for PrometheusRecorder {
+    fn connection_registered(&self, _protocol: EdgeProtocol, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.ha_connections += 1;
+        state.registrations += 1;
+        state.registration_seconds += latency.as_secs_f64();
+    }
+
+    fn connection_closed(&self, _protocol: EdgeProtocol) {
+        let mut state = self.state.lock().unwrap();
+        state.ha_connections = state.ha_connections.saturating_sub(1);
+    }
+
+    fn reconnecting(&self, _attempt: u32) {
+        self.state.lock().unwrap().reconnects += 1;
+    }
+
+    fn request_started(&self, kind: RequestKind) {
+        let mut state = self.state.lock().unwrap();
+        *state.requests.entry(kind.as_str()).or_default() += 1;
+        state.concurrent_requests += 1;
+    }
+
+    fn request_finished(&self, _kind: RequestKind, status: Option<u16>, _duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.concurrent_requests = state.concurrent_requests.saturating_sub(1);
+        if let Some(status) = status {
+            *state.responses.entry(status).or_default() += 1;
+        }
+    }
+
+    fn stream_finished(&self, summary: &StreamSummary) {
+        let mut state = self.state.lock().unwrap();
+        state.bytes_from_edge += summary.bytes_from_edge;
+        state.bytes_to_edge += summary.bytes_to_edge;
+    }
+
+    fn quic_stats(&self, stats: &QuicStats) {
+        self.state.lock().unwrap().quic = Some(*stats);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    #[test]
+    fn renders_recorded_events() {
+        let recorder = PrometheusRecorder::new();
+        recorder.connection_registered(EdgeProtocol::Quic, Duration::from_millis(250));
+        recorder.request_started(RequestKind::Http);
+        recorder.request_started(RequestKind::Http);
+        recorder.request_finished(RequestKind::Http, Some(200), Duration::ZERO);
+        recorder.reconnecting(1);
+        recorder.quic_stats(&QuicStats {
+            rtt: Duration::from_millis(20),
+            congestion_window: 14_720,
+            packets_lost: 3,
+            ..QuicStats::default()
+        });
+
+        let text = recorder.render();
+        assert!(text.contains("libcfd_tunnel_total_requests{kind=\"http\"} 2\n"));
+        assert!(text.contains("libcfd_tunnel_total_requests{kind=\"tcp\"} 0\n"));
+        assert!(text.contains("libcfd_tunnel_response_by_code{status_code=\"200\"} 1\n"));
+        assert!(text.contains("libcfd_tunnel_concurrent_requests 1\n"));
+        assert!(text.contains("libcfd_tunnel_ha_connections 1\n"));
+        assert!(text.contains("libcfd_tunnel_reconnects_total 1\n"));
+        assert!(text.contains("libcfd_tunnel_registration_latency_seconds_sum 0.25\n"));
+        assert!(text.contains("libcfd_quic_rtt_seconds 0.02\n"));
+        assert!(text.contains("# TYPE libcfd_quic_lost_packets gauge\n"));
+        assert!(text.contains("libcfd_quic_lost_packets 3\n"));
+        assert!(text.contains("libcfd_quic_congestion_window_bytes 14720\n"));
+    }
+
+    #[tokio::test]
+    async fn serves_the_exposition() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let recorder = Arc::new(PrometheusRecorder::new());
+        let server = tokio::spawn(listener::serve_listener(
+            listener,
+            recorder.clone().exposition(),
+        ));
+
+        let mut socket = TcpStream::connect(address).await.unwrap();
+        socket
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("libcfd_tunnel_ha_connections 0\n"));
+        server.abort();
+    }
+}
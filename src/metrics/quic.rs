@@ -0,0 +1,125 @@
+//! Transport statistics of a QUIC edge connection.
+
+#[cfg(edge_conn)]
+use std::fmt;
+#[cfg(edge_conn)]
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A snapshot of a QUIC edge connection's transport state, the same from
+/// either backend.
+///
+/// Counters run from the start of the connection. A growing
+/// `packets_lost` with a steady `rtt` points at a lossy path; a healthy
+/// path with slow requests points at the origin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuicStats {
+    /// The smoothed round-trip time.
+    pub rtt: Duration,
+    /// The congestion window, in bytes.
+    pub congestion_window: u64,
+    /// UDP payload bytes sent.
+    pub bytes_sent: u64,
+    /// UDP payload bytes received.
+    pub bytes_received: u64,
+    /// Bytes in packets declared lost.
+    pub bytes_lost: u64,
+    /// Packets sent.
+    pub packets_sent: u64,
+    /// Packets declared lost.
+    pub packets_lost: u64,
+    /// Streams opened on the connection, by the edge or for control.
+    pub streams_opened: u64,
+    /// QUIC datagrams sent.
+    pub datagrams_sent: u64,
+    /// QUIC datagrams received.
+    pub datagrams_received: u64,
+}
+
+/// Reads a live [`QuicStats`] snapshot from a running connection.
+#[cfg(edge_conn)]
+pub(crate) type StatsReader = Box<dyn Fn() -> QuicStats + Send + Sync>;
+
+/// The live QUIC connections of a connector's runs, whose statistics its
+/// diagnostics read; clones share the connections.
+#[cfg(edge_conn)]
+#[derive(Clone, Default)]
+pub(crate) struct QuicStatsSource {
+    connections: Arc<Mutex<Connections>>,
+}
+
+#[cfg(edge_conn)]
+#[cfg_attr(not(quic_any), allow(dead_code))]
+#[derive(Default)]
+struct Connections {
+    next_id: u64,
+    /// The published connections, in publication order.
+    live: Vec<(u64, StatsReader)>,
+}
+
+#[cfg(edge_conn)]
+impl fmt::Debug for QuicStatsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuicStatsSource").finish_non_exhaustive()
+    }
+}
+
+#[cfg(edge_conn)]
+impl QuicStatsSource {
+    /// Publishes a connection's statistics until the returned guard drops.
+    #[cfg(quic_any)]
+    pub(crate) fn publish(&self, read: StatsReader) -> PublishedStats {
+        let mut connections = self.connections.lock().unwrap();
+        let id = connections.next_id;
+        connections.next_id += 1;
+        connections.live.push((id, read));
+        PublishedStats {
+            source: self.clone(),
+            id,
+        }
+    }
+
+    /// A snapshot of the most recently published connection still live.
+    pub(crate) fn latest(&self) -> Option<QuicStats> {
+        let connections = self.connections.lock().unwrap();
+        connections.live.last().map(|(_, read)| read())
+    }
+}
+
+/// One published connection, whose statistics stay readable while it
+/// lives.
+#[cfg(quic_any)]
+pub(crate) struct PublishedStats {
+    source: QuicStatsSource,
+    id: u64,
+}
+
+#[cfg(quic_any)]
+impl Drop for PublishedStats {
+    fn drop(&mut self) {
+        let mut connections = self.source.connections.lock().unwrap();
+        connections.live.retain(|(id, _)| *id != self.id);
+    }
+}
+
+#[cfg(all(test, quic_any))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_latest_live_connection() {
+        let source = QuicStatsSource::default();
+        assert!(source.latest().is_none());
+        let stats = |packets_lost| QuicStats {
+            packets_lost,
+            ..QuicStats::default()
+        };
+        let first = source.publish(Box::new(move || stats(7)));
+        let second = source.clone().publish(Box::new(move || stats(9)));
+        assert_eq!(source.latest().unwrap().packets_lost, 9);
+        drop(second);
+        assert_eq!(source.latest().unwrap().packets_lost, 7);
+        drop(first);
+        assert!(source.latest().is_none());
+    }
+}
@@ -0,0 +1,154 @@
+//! Whether a connector has a connection registered with the edge, in the
+//! shape of cloudflared's `/ready` endpoint.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use super::listener::{self, Reply};
+use crate::origin::EdgeProtocol;
+
+/// The readiness of an [`EdgeConnector`](crate::EdgeConnector), shared
+/// with its runs.
+///
+/// Get it from [`EdgeConnector::readiness`](crate::EdgeConnector::readiness)
+/// and query it while the connector runs, or [`serve`](Self::serve) it to
+/// orchestrator probes. A connection counts as ready from its registration
+/// until it starts to close.
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    connector_id: String,
+    /// The protocols of the registered connections, in registration order.
+    connections: Mutex<Vec<EdgeProtocol>>,
+}
+
+impl Readiness {
+    pub(crate) fn new(connector_id: String) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                connector_id,
+                connections: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Connections currently registered with the edge.
+    pub fn ready_connections(&self) -> usize {
+        self.inner.connections.lock().unwrap().len()
+    }
+
+    /// Whether at least one connection is registered.
+    pub fn is_ready(&self) -> bool {
+        self.ready_connections() > 0
+    }
+
+    /// The transport of the most recently registered connection, if any.
+    pub fn protocol(&self) -> Option<EdgeProtocol> {
+        self.inner.connections.lock().unwrap().last().copied()
+    }
+
+    /// The identifier the connector registers with, as cloudflared reports
+    /// it in `connectorId`.
+    pub fn connector_id(&self) -> &str {
+        &self.inner.connector_id
+    }
+
+    /// The `/ready` status code: `200` when ready, `503` otherwise.
+    pub fn status(&self) -> http::StatusCode {
+        if self.is_ready() {
+            http::StatusCode::OK
+        } else {
+            http::StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+
+    /// The `/ready` body: cloudflared's `status`, `readyConnections` and
+    /// `connectorId`, plus the `protocol` of the latest connection (`null`
+    /// when none is registered).
+    pub fn to_json(&self) -> String {
+        let (ready, protocol) = {
+            let connections = self.inner.connections.lock().unwrap();
+            (connections.len(), connections.last().copied())
+        };
+        let status = if ready > 0 { 200 } else { 503 };
+        let protocol = match protocol {
+            Some(EdgeProtocol::Quic) => "\"quic\"",
+            Some(EdgeProtocol::Http2) => "\"http2\"",
+            None => "null",
+        };
+        format!(
+            r#"{{"status":{status},"readyConnections":{ready},"connectorId":"{}","protocol":{protocol}}}"#,
+            self.inner.connector_id
+        )
+    }
+
+    /// Answers `GET /ready` with [`to_json`](Self::to_json) and
+    /// [`status`](Self::status), and `GET /healthcheck` with `OK` while the
+    /// process is alive, on `address` until the returned future is dropped.
+    /// Fails only if the listener cannot be bound.
+    pub async fn serve(self, address: SocketAddr) -> io::Result<()> {
+        tracing::debug!(%address, "serving readiness");
+        listener::serve(address, move |path| match path {
+            "/ready" => Reply::new(self.status(), "application/json", self.to_json()),
+            "/healthcheck" => Reply::new(http::StatusCode::OK, "text/plain; charset=utf-8", "OK\n"),
+            _ => Reply::not_found(),
+        })
+        .await
+    }
+
+    /// Marks a connection registered until the returned guard drops.
+    pub(crate) fn connected(&self, protocol: EdgeProtocol) -> ReadyConnection {
+        self.inner.connections.lock().unwrap().push(protocol);
+        ReadyConnection {
+            readiness: self.clone(),
+            protocol,
+        }
+    }
+}
+
+/// One registered connection, counted as ready while it lives.
+pub(crate) struct ReadyConnection {
+    readiness: Readiness,
+    protocol: EdgeProtocol,
+}
+
+impl Drop for ReadyConnection {
+    fn drop(&mut self) {
+        let mut connections = self.readiness.inner.connections.lock().unwrap();
+        if let Some(index) = connections.iter().rposition(|p| *p == self.protocol) {
+            connections.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_registered_connections() {
+        let readiness = Readiness::new("0011".into());
+        assert_eq!(readiness.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            readiness.to_json(),
+            r#"{"status":503,"readyConnections":0,"connectorId":"0011","protocol":null}"#
+        );
+
+        let quic = readiness.connected(EdgeProtocol::Quic);
+        let h2 = readiness.connected(EdgeProtocol::Http2);
+        assert_eq!(readiness.ready_connections(), 2);
+        assert_eq!(readiness.protocol(), Some(EdgeProtocol::Http2));
+        drop(h2);
+        assert_eq!(
+            readiness.to_json(),
+            r#"{"status":200,"readyConnections":1,"connectorId":"0011","protocol":"quic"}"#
+        );
+        drop(quic);
+        assert!(!readiness.is_ready());
+    }
+}
@@ -10,13 +10,7 @@
 //! proxying is likewise outside axum's HTTP-only model, so
 //! [`StreamOrigin<TcpResponder>`](crate::StreamOrigin) has no axum adapter.
 
-use std::pin::Pin;
-use std::task::{Context, Poll};
-
-use axum::body::{Body as AxumBody, BodyDataStream};
-use bytes::Bytes;
-use futures_util::Stream;
-use futures_util::io::AsyncRead;
+use axum::body::Body as AxumBody;
 
 use crate::error::{Error, Result};
 use crate::origin::{Body, HttpOrigin, HttpResponder, Request, Response};
@@ -63,6 +57,7 @@ async fn serve_router(mut router: axum::Router, request: Request) -> Result<Resp
         uri,
         mut headers,
         body,
+        extensions,
     } = request;
     if let Some(size) = body.size_hint() {
         headers.insert(
@@ -74,9 +69,11 @@ async fn serve_router(mut router: axum::Router, request: Request) -> Result<Resp
     let mut axum_request = http::Request::builder()
         .method(method)
         .uri(uri)
-        .body(AxumBody::from_stream(BodyReadStream::new(body)))
+        .body(AxumBody::new(body))
         .map_err(|e| Error::origin_handler(format!("failed to build axum request: {e}")))?;
     *axum_request.headers_mut() = headers;
+    // Handlers read the edge context with axum's `Extension` extractor.
+    *axum_request.extensions_mut() = extensions;
 
     // Router::call is infallible; axum converts handler errors into responses.
     let axum_response = tower::Service::call(&mut router, axum_request)
@@ -84,107 +81,10 @@ async fn serve_router(mut router: axum::Router, request: Request) -> Result<Resp
         .map_err(|e| Error::origin_handler(format!("axum router failed: {e}")))?;
 
     let (parts, body) = axum_response.into_parts();
-    let body = Body::from_reader(AxumBodyReader::new(body.into_data_stream()));
+    let body = Body::from_http_body(body);
     Ok(Response::new(parts.status, parts.headers, body))
 }
 
-/// Streams a libcfd [`Body`] as `Result<Bytes, _>` chunks for axum.
-struct BodyReadStream {
-    body: Option<Body>,
-    buffer: [u8; 8192],
-    length: usize,
-}
-
-impl BodyReadStream {
-    fn new(body: Body) -> Self {
-        Self {
-            body: Some(body),
-            buffer: [0u8; 8192],
-            length: 0,
-        }
-    }
-}
-
-impl Stream for BodyReadStream {
-    type Item = std::result::Result<Bytes, axum::Error>;
-
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if self.length == 0 {
-            let Self {
-                body,
-                buffer,
-                length,
-            } = self.as_mut().get_mut();
-            let body = body.as_mut().expect("polled after stream end");
-            match Pin::new(body).poll_read(cx, buffer) {
-                Poll::Ready(Ok(0)) => return Poll::Ready(None),
-                Poll::Ready(Ok(n)) => *length = n,
-                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(axum::Error::new(e)))),
-                Poll::Pending => return Poll::Pending,
-            }
-        }
-        let chunk = Bytes::copy_from_slice(&self.buffer[..self.length]);
-        self.length = 0;
-        Poll::Ready(Some(Ok(chunk)))
-    }
-}
-
-/// Reads an axum response body stream as a libcfd [`Body`].
-struct AxumBodyReader {
-    stream: Pin<Box<BodyDataStream>>,
-    chunk: Option<Bytes>,
-    position: usize,
-    eof: bool,
-}
-
-impl AxumBodyReader {
-    fn new(stream: BodyDataStream) -> Self {
-        Self {
-            stream: Box::pin(stream),
-            chunk: None,
-            position: 0,
-            eof: false,
-        }
-    }
-}
-
-impl AsyncRead for AxumBodyReader {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buffer: &mut [u8],
-    ) -> Poll<std::io::Result<usize>> {
-        loop {
-            if self.eof {
-                return Poll::Ready(Ok(0));
-            }
-            if let Some(chunk) = self.chunk.take() {
-                if self.position < chunk.len() {
-                    let n = std::cmp::min(chunk.len() - self.position, buffer.len());
-                    buffer[..n].copy_from_slice(&chunk[self.position..self.position + n]);
-                    self.position += n;
-                    if self.position < chunk.len() {
-                        self.chunk = Some(chunk);
-                    }
-                    return Poll::Ready(Ok(n));
-                }
-                self.position = 0;
-            }
-            match self.stream.as_mut().poll_next(cx) {
-                Poll::Ready(Some(Ok(chunk))) => self.chunk = Some(chunk),
-                Poll::Ready(Some(Err(e))) => {
-                    return Poll::Ready(Err(std::io::Error::other(e)));
-                }
-                Poll::Ready(None) => {
-                    self.eof = true;
-                    return Poll::Ready(Ok(0));
-                }
-                Poll::Pending => return Poll::Pending,
-            }
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,4 +149,14 @@ mod tests {
         let response = await_response(&origin, request).await;
         assert_eq!(response.status, http::StatusCode::NOT_FOUND);
     }
+
+    #[tokio::test]
+    async fn trailers_round_trip_through_axum_bodies() {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        let request = Body::from_bytes(b"ping".to_vec()).with_trailers(trailers);
+        let mut body = Body::from_http_body(AxumBody::new(request));
+        assert_eq!(body.collect().await.unwrap(), b"ping");
+        assert_eq!(body.trailers().await.unwrap()["grpc-status"], "0");
+    }
 }
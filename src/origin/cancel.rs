@@ -0,0 +1,140 @@
+//! Cancellation of requests the edge abandons.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::Notify;
+
+use crate::origin::http::body::Request;
+
+/// Fires when the edge abandons a request: the visitor disconnected, the
+/// stream was reset, or the tunnel connection dropped.
+///
+/// The transports attach one to every request, websocket and TCP connect;
+/// handlers get it with [`Request::cancellation`] and stop expensive work
+/// once it fires. Clones share the same signal.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenInner>,
+}
+
+#[derive(Default)]
+struct TokenInner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    /// A token that has not fired.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fires the token, waking every task waiting on it.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Whether the token has fired.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the token fires.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            tokio::pin!(notified);
+            // Register before checking so a concurrent `cancel` is not missed.
+            notified.as_mut().enable();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// A guard that fires the token when dropped, unless disarmed once the
+    /// exchange completes. Stream tasks aborted with their connection drop
+    /// it armed.
+    #[cfg(edge_conn)]
+    pub(crate) fn drop_guard(&self) -> CancelOnDrop {
+        CancelOnDrop {
+            token: Some(self.clone()),
+        }
+    }
+}
+
+impl std::fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Fires a [`CancellationToken`] on drop unless disarmed.
+#[cfg(edge_conn)]
+pub(crate) struct CancelOnDrop {
+    token: Option<CancellationToken>,
+}
+
+#[cfg(edge_conn)]
+impl CancelOnDrop {
+    /// The exchange completed, or the handler failed and has nothing left
+    /// to cancel; dropping the guard no longer fires the token.
+    pub(crate) fn disarm(&mut self) {
+        self.token = None;
+    }
+}
+
+#[cfg(edge_conn)]
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            token.cancel();
+        }
+    }
+}
+
+impl Request {
+    /// The token that fires when the edge abandons this request. Requests
+    /// built outside a transport get a token that never fires.
+    pub fn cancellation(&self) -> CancellationToken {
+        self.extensions
+            .get::<CancellationToken>()
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waiters_wake_on_cancel() {
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!token.is_cancelled());
+        token.cancel();
+        waiter.await.unwrap();
+        // Already fired: resolves immediately.
+        token.cancelled().await;
+    }
+
+    #[cfg(edge_conn)]
+    #[test]
+    fn drop_guard_fires_unless_disarmed() {
+        let token = CancellationToken::new();
+        token.drop_guard().disarm();
+        assert!(!token.is_cancelled());
+        drop(token.drop_guard());
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,366 @@
+//! Opt-in response compression for [`HttpOrigin`]s.
+//!
+//! [`CompressionOrigin`] wraps another origin, negotiates an encoding from
+//! the request's `Accept-Encoding`, and compresses the response body as it
+//! streams. Each encoding has its own feature (`compression-gzip`,
+//! `compression-br`, `compression-zstd`); `compression` enables all three.
+//!
+//! Responses that are already encoded, are ranges, carry trailers, have a
+//! content type that does not compress (images, archives, media), or are
+//! smaller than the minimum size pass through untouched. Server-sent events
+//! and gRPC are skipped too: an encoder holds bytes back until it has enough
+//! to emit, which would delay individual messages.
+
+use async_compression::Level;
+#[cfg(feature = "compression-br")]
+use async_compression::futures::bufread::BrotliEncoder;
+#[cfg(feature = "compression-gzip")]
+use async_compression::futures::bufread::GzipEncoder;
+#[cfg(feature = "compression-zstd")]
+use async_compression::futures::bufread::ZstdEncoder;
+use futures_util::io::BufReader;
+use http::header::{
+    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    ETAG, VARY,
+};
+
+use crate::origin::http::HttpOrigin;
+use crate::origin::http::body::{Body, Request, Response};
+use crate::origin::responder::HttpResponder;
+
+/// Bodies known to be smaller than this are sent uncompressed by default.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// Brotli's default quality (11) is tuned for offline compression and is
+/// far too slow for responses compressed on the fly.
+#[cfg(feature = "compression-br")]
+const BROTLI_QUALITY: i32 = 4;
+
+/// Content type prefixes that are compressed already, gain nothing from
+/// compression, or stream messages that must not be held back.
+const SKIPPED_CONTENT_TYPES: &[&str] = &[
+    "image/",
+    "audio/",
+    "video/",
+    "font/woff",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/x-7z-compressed",
+    "application/x-rar-compressed",
+    "application/octet-stream",
+    "application/pdf",
+    "text/event-stream",
+    "application/grpc",
+];
+
+/// Compresses the responses of the wrapped [`HttpOrigin`].
+///
+/// ```ignore
+/// let origin = Origin::http(CompressionOrigin::new(static_files));
+/// ```
+///
+/// A compressed response gets `Content-Encoding`, loses `Content-Length`
+/// (the compressed size is not known up front), and has a strong `ETag`
+/// weakened. Every response that could have been compressed carries
+/// `Vary: Accept-Encoding`, so caches keep the variants apart.
+pub struct CompressionOrigin<O> {
+    inner: O,
+    min_size: u64,
+}
+
+impl<O> CompressionOrigin<O> {
+    /// Wraps `inner`, compressing bodies of 1 KiB and more (or of unknown
+    /// length).
+    pub fn new(inner: O) -> Self {
+        Self {
+            inner,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Sets the smallest known body length that is compressed.
+    pub fn with_min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// The wrapped origin.
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+}
+
+impl<O: HttpOrigin> HttpOrigin for CompressionOrigin<O> {
+    fn handle(&self, request: Request, respond: HttpResponder) {
+        let encoding = if request.method == http::Method::HEAD {
+            None
+        } else {
+            negotiate(&request.headers)
+        };
+        let min_size = self.min_size;
+        let respond = respond.map_response(move |response| compress(response, encoding, min_size));
+        self.inner.handle(request, respond);
+    }
+}
+
+/// A content coding this build can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    #[cfg(feature = "compression-br")]
+    Brotli,
+    #[cfg(feature = "compression-zstd")]
+    Zstd,
+    #[cfg(feature = "compression-gzip")]
+    Gzip,
+}
+
+impl Encoding {
+    /// The enabled encodings, most preferred first when a client accepts
+    /// several equally.
+    const PREFERENCE: &[Self] = &[
+        #[cfg(feature = "compression-br")]
+        Self::Brotli,
+        #[cfg(feature = "compression-zstd")]
+        Self::Zstd,
+        #[cfg(feature = "compression-gzip")]
+        Self::Gzip,
+    ];
+
+    /// The `Content-Encoding` token.
+    fn token(self) -> &'static str {
+        match self {
+            #[cfg(feature = "compression-br")]
+            Self::Brotli => "br",
+            #[cfg(feature = "compression-zstd")]
+            Self::Zstd => "zstd",
+            #[cfg(feature = "compression-gzip")]
+            Self::Gzip => "gzip",
+        }
+    }
+
+    fn encode(self, body: Body) -> Body {
+        let reader = BufReader::new(body);
+        match self {
+            #[cfg(feature = "compression-br")]
+            Self::Brotli => Body::from_reader(BrotliEncoder::with_quality(
+                reader,
+                Level::Precise(BROTLI_QUALITY),
+            )),
+            #[cfg(feature = "compression-zstd")]
+            Self::Zstd => Body::from_reader(ZstdEncoder::with_quality(reader, Level::Default)),
+            #[cfg(feature = "compression-gzip")]
+            Self::Gzip => Body::from_reader(GzipEncoder::with_quality(reader, Level::Default)),
+        }
+    }
+}
+
+/// Picks the encoding with the highest `Accept-Encoding` quality; `None`
+/// when the client accepts none of the enabled encodings.
+fn negotiate(headers: &http::HeaderMap) -> Option<Encoding> {
+    let mut accepted: Vec<(String, f32)> = Vec::new();
+    for value in headers.get_all(ACCEPT_ENCODING) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            let mut parameters = item.split(';');
+            let token = parameters.next().unwrap_or_default().trim();
+            if token.is_empty() {
+                continue;
+            }
+            let quality = parameters
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .map_or(1.0, |q| q.trim().parse::<f32>().unwrap_or(0.0));
+            accepted.push((token.to_ascii_lowercase(), quality));
+        }
+    }
+    let quality = |token: &str| {
+        accepted
+            .iter()
+            .find(|(accepted, _)| accepted == token)
+            .or_else(|| accepted.iter().find(|(accepted, _)| accepted == "*"))
+            .map_or(0.0, |(_, quality)| *quality)
+    };
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in Encoding::PREFERENCE.iter().copied() {
+        let quality = quality(encoding.token());
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Whether compressing `response` could be worthwhile at all.
+fn compressible(response: &Response, min_size: u64) -> bool {
+    let status = response.status;
+    if status.is_informational()
+        || status == http::StatusCode::NO_CONTENT
+        || status == http::StatusCode::NOT_MODIFIED
+        || status == http::StatusCode::PARTIAL_CONTENT
+    {
+        return false;
+    }
+    let headers = &response.headers;
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    if header(CONTENT_ENCODING).is_some_and(|encoding| !encoding.eq_ignore_ascii_case("identity"))
+        || headers.contains_key(CONTENT_RANGE)
+        || header(CACHE_CONTROL)
+            .is_some_and(|control| control.to_ascii_lowercase().contains("no-transform"))
+        || response.body.has_trailers()
+    {
+        return false;
+    }
+    let Some(content_type) = header(CONTENT_TYPE).map(str::to_ascii_lowercase) else {
+        return false;
+    };
+    // SVG is the one image type that is text.
+    if !content_type.starts_with("image/svg+xml")
+        && SKIPPED_CONTENT_TYPES
+            .iter()
+            .any(|skipped| content_type.starts_with(skipped))
+    {
+        return false;
+    }
+    let length = header(CONTENT_LENGTH)
+        .and_then(|length| length.trim().parse::<u64>().ok())
+        .or(response.body.size_hint());
+    length.is_none_or(|length| length >= min_size)
+}
+
+/// Compresses `response` with `encoding` when it qualifies, fixing up the
+/// headers that describe the body.
+fn compress(mut response: Response, encoding: Option<Encoding>, min_size: u64) -> Response {
+    if !compressible(&response, min_size) {
+        return response;
+    }
+    add_vary(&mut response.headers);
+    let Some(encoding) = encoding else {
+        return response;
+    };
+    let headers = &mut response.headers;
+    headers.remove(CONTENT_LENGTH);
+    headers.insert(
+        CONTENT_ENCODING,
+        http::HeaderValue::from_static(encoding.token()),
+    );
+    // A strong validator promises byte-identical bodies, which no longer
+    // holds once the body is re-encoded.
+    if let Some(etag) = headers.get(ETAG).and_then(|etag| etag.to_str().ok())
+        && etag.starts_with('"')
+        && let Ok(weak) = http::HeaderValue::from_str(&format!("W/{etag}"))
+    {
+        headers.insert(ETAG, weak);
+    }
+    let body = std::mem::replace(&mut response.body, Body::empty());
+    let flush_policy = body.flush_policy();
+    let mut body = encoding.encode(body);
+    if let Some(policy) = flush_policy {
+        body = body.with_flush_policy(policy);
+    }
+    response.body = body;
+    response
+}
+
+/// Adds `Accept-Encoding` to `Vary` unless it is already listed.
+fn add_vary(headers: &mut http::HeaderMap) {
+    let listed = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case("accept-encoding"));
+    if !listed {
+        headers.append(VARY, http::HeaderValue::from_static("Accept-Encoding"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::origin::wait_outcome;
+
+    fn response(content_type: &str, body: &'static [u8]) -> Response {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+        Response::new(http::StatusCode::OK, headers, Body::from_bytes(body))
+    }
+
+    fn accept(value: &'static str) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, http::HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn negotiation_honours_quality_values() {
+        assert_eq!(negotiate(&http::HeaderMap::new()), None);
+        assert_eq!(negotiate(&accept("identity")), None);
+        assert_eq!(negotiate(&accept("*;q=0")), None);
+        #[cfg(feature = "compression-gzip")]
+        assert_eq!(negotiate(&accept("gzip")), Some(Encoding::Gzip));
+        #[cfg(all(feature = "compression-gzip", feature = "compression-br"))]
+        {
+            assert_eq!(negotiate(&accept("gzip, br")), Some(Encoding::Brotli));
+            assert_eq!(negotiate(&accept("gzip, br;q=0.5")), Some(Encoding::Gzip));
+        }
+    }
+
+    #[test]
+    fn skips_small_and_precompressed_bodies() {
+        let mut html = response("text/html", &[b'a'; 2048]);
+        assert!(compressible(&html, DEFAULT_MIN_SIZE));
+        html.headers
+            .insert(CONTENT_ENCODING, "gzip".parse().unwrap());
+        assert!(!compressible(&html, DEFAULT_MIN_SIZE));
+        assert!(!compressible(
+            &response("image/png", &[0; 4096]),
+            DEFAULT_MIN_SIZE
+        ));
+        assert!(!compressible(
+            &response("text/plain", b"tiny"),
+            DEFAULT_MIN_SIZE
+        ));
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[tokio::test]
+    async fn gzip_round_trips_and_fixes_headers() {
+        use async_compression::futures::bufread::GzipDecoder;
+        use futures_util::io::AsyncReadExt;
+
+        let origin = CompressionOrigin::new(|_request: Request, respond: HttpResponder| {
+            let mut response = response("text/plain; charset=utf-8", &[b'x'; 4096]);
+            response
+                .headers
+                .insert(CONTENT_LENGTH, "4096".parse().unwrap());
+            response.headers.insert(ETAG, "\"v1\"".parse().unwrap());
+            respond.send(response);
+        });
+        let request = Request::new(
+            http::Method::GET,
+            http::Uri::from_static("http://example.com/"),
+            accept("gzip"),
+            Body::empty(),
+        );
+        let (respond, receiver) = HttpResponder::channel();
+        origin.handle(request, respond);
+        let response = wait_outcome(receiver).await.unwrap();
+        assert_eq!(response.headers[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers[VARY], "Accept-Encoding");
+        assert_eq!(response.headers[ETAG], "W/\"v1\"");
+        assert!(!response.headers.contains_key(CONTENT_LENGTH));
+
+        let mut decoded = Vec::new();
+        GzipDecoder::new(BufReader::new(response.body))
+            .read_to_end(&mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, vec![b'x'; 4096]);
+    }
+}
@@ -0,0 +1,151 @@
+//! What the edge knows about a request, attached to every [`Request`].
+
+use std::net::IpAddr;
+
+use crate::origin::http::body::Request;
+
+/// The transport a request arrived over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeProtocol {
+    /// A QUIC data stream.
+    Quic,
+    /// An HTTP/2 stream.
+    Http2,
+}
+
+/// Edge details for one request: the connection it came through and the
+/// visitor identity the edge forwards.
+///
+/// The transports insert it into [`Request::extensions`]; read it with
+/// [`Request::context`]. Adapters that hand the extensions on (such as
+/// `AxumOrigin`) make it available to their handlers
+/// too.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    protocol: EdgeProtocol,
+    connection_index: u8,
+    colo: Option<String>,
+    stream_id: u64,
+    ray_id: Option<String>,
+    client_ip: Option<IpAddr>,
+    trace_id: Option<String>,
+}
+
+impl RequestContext {
+    /// Builds the context for a stream, reading the `Cf-Ray` and
+    /// `Cf-Connecting-IP` request headers; `trace_id` is the edge trace
+    /// id the transport parsed from `Cf-Trace-Id`.
+    #[cfg_attr(not(edge_conn), allow(dead_code))]
+    pub(crate) fn new(
+        protocol: EdgeProtocol,
+        connection_index: u8,
+        colo: Option<String>,
+        stream_id: u64,
+        trace_id: Option<String>,
+        headers: &http::HeaderMap,
+    ) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        Self {
+            protocol,
+            connection_index,
+            colo,
+            stream_id,
+            ray_id: header("cf-ray").map(str::to_string),
+            client_ip: header("cf-connecting-ip").and_then(|ip| ip.parse().ok()),
+            trace_id,
+        }
+    }
+
+    /// The transport the request arrived over.
+    pub fn protocol(&self) -> EdgeProtocol {
+        self.protocol
+    }
+
+    /// The index of the tunnel connection the request arrived on.
+    pub fn connection_index(&self) -> u8 {
+        self.connection_index
+    }
+
+    /// The edge location the connection registered with (for example
+    /// `lax08`), once registration has reported it.
+    pub fn colo(&self) -> Option<&str> {
+        self.colo.as_deref()
+    }
+
+    /// The QUIC or HTTP/2 stream id that carried the request.
+    pub fn stream_id(&self) -> u64 {
+        self.stream_id
+    }
+
+    /// The `Cf-Ray` id of the request.
+    pub fn ray_id(&self) -> Option<&str> {
+        self.ray_id.as_deref()
+    }
+
+    /// The visitor address from `Cf-Connecting-IP`.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.client_ip
+    }
+
+    /// The edge trace id from `Cf-Trace-Id` as 32 hex digits, when the
+    /// edge traces the request.
+    pub fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+}
+
+impl Request {
+    /// The edge context the transport attached, if any.
+    pub fn context(&self) -> Option<&RequestContext> {
+        self.extensions.get::<RequestContext>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::origin::Body;
+
+    #[test]
+    fn reads_ray_and_client_ip_from_edge_headers() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("cf-ray", "8a1b2c3d4e5f6a7b-LAX".parse().unwrap());
+        headers.insert("cf-connecting-ip", " 2001:db8::1 ".parse().unwrap());
+        let trace_id = Some("00000000000000000000000000a1b2c3".to_string());
+        let context = RequestContext::new(
+            EdgeProtocol::Quic,
+            0,
+            Some("lax08".into()),
+            4,
+            trace_id,
+            &headers,
+        );
+        assert_eq!(context.ray_id(), Some("8a1b2c3d4e5f6a7b-LAX"));
+        assert_eq!(context.client_ip(), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(context.colo(), Some("lax08"));
+        assert_eq!(context.trace_id(), Some("00000000000000000000000000a1b2c3"));
+    }
+
+    #[test]
+    fn context_travels_in_request_extensions() {
+        let mut request = Request::new(
+            http::Method::GET,
+            "http://example.com/".parse().unwrap(),
+            http::HeaderMap::new(),
+            Body::empty(),
+        );
+        assert!(request.context().is_none());
+        let context = RequestContext::new(EdgeProtocol::Http2, 0, None, 3, None, &request.headers);
+        request.extensions.insert(context);
+        let context = request.context().unwrap();
+        assert_eq!(context.protocol(), EdgeProtocol::Http2);
+        assert!(context.client_ip().is_none());
+        assert!(context.trace_id().is_none());
+    }
+}
@@ -1,7 +1,11 @@
 //! Errors from origin handlers and their byte streams.
 
+use std::time::Duration;
+
 use thiserror::Error;
 
+use crate::origin::http::body::{Body, Response};
+
 /// Errors from origin handlers and their byte streams.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -11,4 +15,204 @@ pub enum Error {
     /// An underlying I/O operation failed.
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    /// An origin was configured with a URL it cannot serve.
+    #[error("invalid origin url: {0}")]
+    InvalidUrl(String),
+    /// A message-level websocket failed.
+    #[error(transparent)]
+    WebSocket(#[from] crate::origin::websocket::WebSocketError),
+}
+
+/// A structured failure reported by an origin handler through
+/// [`HttpResponder::fail`](crate::HttpResponder::fail) or a stream
+/// responder.
+///
+/// Carries the status the visitor should see, an optional body with its
+/// content type, and an optional retry hint. A plain message converts into a
+/// bare `502`, which keeps the edge's own error page; any other status, a
+/// body, or a retry hint is sent back as a regular response, passing
+/// through the connector's error-page renderer
+/// ([`EdgeOptions::error_page`](crate::EdgeOptions)) first when one is set.
+#[derive(Debug, Clone, Error)]
+#[error("{message}")]
+pub struct OriginError {
+    status: http::StatusCode,
+    message: String,
+    body: Option<ErrorBody>,
+    retry_after: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+struct ErrorBody {
+    content_type: String,
+    bytes: Vec<u8>,
+}
+
+impl OriginError {
+    /// A failure with an explicit status and a message for logs.
+    pub fn new(status: http::StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            body: None,
+            retry_after: None,
+        }
+    }
+
+    /// A `502 Bad Gateway` failure: the origin could not be reached or
+    /// answered with garbage.
+    pub fn bad_gateway(message: impl Into<String>) -> Self {
+        Self::new(http::StatusCode::BAD_GATEWAY, message)
+    }
+
+    /// A `503 Service Unavailable` failure: the origin is overloaded or
+    /// down for maintenance.
+    pub fn service_unavailable(message: impl Into<String>) -> Self {
+        Self::new(http::StatusCode::SERVICE_UNAVAILABLE, message)
+    }
+
+    /// A `504 Gateway Timeout` failure: the origin did not answer in time.
+    pub fn gateway_timeout(message: impl Into<String>) -> Self {
+        Self::new(http::StatusCode::GATEWAY_TIMEOUT, message)
+    }
+
+    /// Sets the body sent to the visitor instead of the plain-text message.
+    pub fn with_body(mut self, content_type: impl Into<String>, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(ErrorBody {
+            content_type: content_type.into(),
+            bytes: body.into(),
+        });
+        self
+    }
+
+    /// Marks the failure as retryable after `delay`, sent to the visitor as
+    /// a `Retry-After` header.
+    pub fn with_retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after = Some(delay);
+        self
+    }
+
+    /// The status the visitor should see.
+    pub fn status(&self) -> http::StatusCode {
+        self.status
+    }
+
+    /// The failure message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The body content type, when a body was set.
+    pub fn content_type(&self) -> Option<&str> {
+        self.body.as_ref().map(|body| body.content_type.as_str())
+    }
+
+    /// The body bytes, when a body was set.
+    pub fn body(&self) -> Option<&[u8]> {
+        self.body.as_ref().map(|body| body.bytes.as_slice())
+    }
+
+    /// The retry hint, when one was set.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Whether the visitor may retry the request.
+    pub fn is_retryable(&self) -> bool {
+        self.retry_after.is_some()
+    }
+
+    /// The failure a transport reports when the handler dropped its
+    /// responder without answering.
+    #[cfg_attr(not(edge_conn), allow(dead_code))]
+    pub(crate) fn no_response() -> Self {
+        Self::bad_gateway("origin handler produced no response")
+    }
+
+    /// Whether this is a bare `502` that should surface as the edge's own
+    /// error page rather than a response of ours.
+    fn is_bare(&self) -> bool {
+        self.status == http::StatusCode::BAD_GATEWAY
+            && self.body.is_none()
+            && self.retry_after.is_none()
+    }
+
+    /// Renders the default error response, or hands the message back when
+    /// the failure is a bare `502`.
+    #[cfg_attr(not(edge_conn), allow(dead_code))]
+    pub(crate) fn into_response(self) -> std::result::Result<Response, String> {
+        if self.is_bare() {
+            return Err(self.message);
+        }
+        let (content_type, bytes) = match self.body {
+            Some(body) => (body.content_type, body.bytes),
+            None => (
+                "text/plain; charset=utf-8".to_string(),
+                self.message.into_bytes(),
+            ),
+        };
+        let mut headers = http::HeaderMap::new();
+        if let Ok(value) = http::HeaderValue::from_str(&content_type) {
+            headers.insert(http::header::CONTENT_TYPE, value);
+        }
+        headers.insert(
+            http::header::CONTENT_LENGTH,
+            http::HeaderValue::from(bytes.len()),
+        );
+        if let Some(delay) = self.retry_after {
+            // Retry-After carries whole seconds; round up so a sub-second hint is not lost.
+            let seconds = delay.as_secs() + u64::from(delay.subsec_nanos() > 0);
+            headers.insert(http::header::RETRY_AFTER, http::HeaderValue::from(seconds));
+        }
+        Ok(Response::new(self.status, headers, Body::from_bytes(bytes)))
+    }
+}
+
+impl From<String> for OriginError {
+    fn from(message: String) -> Self {
+        Self::bad_gateway(message)
+    }
+}
+
+impl From<&str> for OriginError {
+    fn from(message: &str) -> Self {
+        Self::bad_gateway(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_message_is_a_bare_bad_gateway() {
+        let error = OriginError::from("origin down");
+        assert_eq!(error.status(), http::StatusCode::BAD_GATEWAY);
+        assert_eq!(error.to_string(), "origin down");
+        assert_eq!(error.into_response().unwrap_err(), "origin down");
+    }
+
+    #[test]
+    fn explicit_status_renders_plain_text_response() {
+        let response = OriginError::service_unavailable("maintenance")
+            .with_retry_after(Duration::from_millis(1500))
+            .into_response()
+            .unwrap();
+        assert_eq!(response.status, http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers[http::header::RETRY_AFTER], "2");
+        assert_eq!(response.headers[http::header::CONTENT_LENGTH], "11");
+        assert_eq!(response.body.size_hint(), Some(11));
+    }
+
+    #[test]
+    fn custom_body_keeps_its_content_type() {
+        let error = OriginError::bad_gateway("upstream refused")
+            .with_body("application/json", br#"{"error":"upstream"}"#.to_vec());
+        let response = error.into_response().unwrap();
+        assert_eq!(response.status, http::StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            response.headers[http::header::CONTENT_TYPE],
+            "application/json"
+        );
+    }
 }
@@ -1,9 +1,15 @@
 //! Transport-neutral request, response and body types.
 
+use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use bytes::{Buf, Bytes, BytesMut};
 use futures_util::io::{self, AsyncRead};
+use futures_util::{Stream, StreamExt};
+use tokio::sync::oneshot;
+
+use crate::origin::http::flush::FlushPolicy;
 
 /// An incoming HTTP request from the edge.
 #[derive(Debug)]
@@ -19,6 +25,9 @@ pub struct Request {
     /// sends them, and a handler may respond before the body is fully
     /// consumed (the transport drains any unread remainder).
     pub body: Body,
+    /// Typed request extensions; the transports attach a
+    /// [`RequestContext`](crate::RequestContext).
+    pub extensions: http::Extensions,
 }
 
 impl Request {
@@ -29,6 +38,7 @@ impl Request {
             uri,
             headers,
             body,
+            extensions: http::Extensions::new(),
         }
     }
 
@@ -71,43 +81,193 @@ impl Response {
 
 /// A streaming byte body.
 ///
-/// Backed either by bytes owned in memory or by a reader that produces
-/// chunks on demand. `Body` implements `futures_util::io::AsyncRead`.
+/// Backed by bytes owned in memory, a reader that produces data on demand,
+/// or a stream of [`Bytes`] chunks. `Body` implements
+/// `futures_util::io::AsyncRead`, and also yields its data as a
+/// `Stream<Item = io::Result<Bytes>>` and as an [`http_body::Body`]; chunk
+/// sources ([`from_bytes`](Self::from_bytes), [`from_stream`](Self::from_stream),
+/// [`from_http_body`](Self::from_http_body)) pass through without copying.
+///
+/// A body may end with trailers (gRPC sends `grpc-status` this way). They
+/// are fixed up front with [`with_trailers`](Self::with_trailers) or sent
+/// once the data is produced through the [`TrailerSender`] returned by
+/// [`with_deferred_trailers`](Self::with_deferred_trailers); readers take
+/// them with [`trailers`](Self::trailers) after reading the data to the end.
 pub struct Body {
     inner: BodyInner,
     size_hint: Option<u64>,
+    trailers: Trailers,
+    flush_policy: Option<FlushPolicy>,
+    /// The unread rest of a chunk partially consumed by `poll_read`.
+    pending: Bytes,
+}
+
+enum Trailers {
+    None,
+    Ready(http::HeaderMap),
+    Deferred(oneshot::Receiver<http::HeaderMap>),
+}
+
+/// Sends the trailers of a body created with
+/// [`Body::with_deferred_trailers`].
+///
+/// Send the trailers before the body's data ends; dropping the sender ends
+/// the body without trailers.
+#[derive(Debug)]
+pub struct TrailerSender {
+    tx: oneshot::Sender<http::HeaderMap>,
+}
+
+impl TrailerSender {
+    /// Sends the trailers. Ignored when the body was already dropped.
+    pub fn send(self, trailers: http::HeaderMap) {
+        let _ = self.tx.send(trailers);
+    }
 }
 
+type ChunkStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
 enum BodyInner {
     Empty,
-    Bytes(futures_util::io::Cursor<Vec<u8>>),
-    Reader(Pin<Box<dyn AsyncRead + Send>>),
+    Bytes(Bytes),
+    /// A reader and the buffer it reads into, kept across polls and
+    /// split into chunks as they fill.
+    Reader(Pin<Box<dyn AsyncRead + Send>>, BytesMut),
+    Stream(ChunkStream),
 }
 
+/// Size of the buffers a reader-backed body reads into.
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The least room left in a reader's buffer before a fresh one is
+/// allocated.
+const MIN_READ_SIZE: usize = READ_CHUNK_SIZE / 4;
+
 impl Body {
-    /// An empty body.
-    pub fn empty() -> Self {
+    fn with_inner(inner: BodyInner, size_hint: Option<u64>) -> Self {
         Self {
-            inner: BodyInner::Empty,
-            size_hint: Some(0),
+            inner,
+            size_hint,
+            trailers: Trailers::None,
+            flush_policy: None,
+            pending: Bytes::new(),
         }
     }
 
+    /// An empty body.
+    pub fn empty() -> Self {
+        Self::with_inner(BodyInner::Empty, Some(0))
+    }
+
     /// A body backed by bytes owned in memory.
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+    pub fn from_bytes(bytes: impl Into<Bytes>) -> Self {
+        let bytes = bytes.into();
         let size_hint = Some(bytes.len() as u64);
-        Self {
-            inner: BodyInner::Bytes(futures_util::io::Cursor::new(bytes)),
-            size_hint,
-        }
+        Self::with_inner(BodyInner::Bytes(bytes), size_hint)
     }
 
     /// A streaming body backed by a reader that produces chunks on demand.
     pub fn from_reader(reader: impl AsyncRead + Send + 'static) -> Self {
-        Self {
-            inner: BodyInner::Reader(Box::pin(reader)),
-            size_hint: None,
-        }
+        Self::with_inner(BodyInner::Reader(Box::pin(reader), BytesMut::new()), None)
+    }
+
+    /// A streaming body backed by a stream of chunks, which are handed on
+    /// as they are.
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        Self::with_inner(BodyInner::Stream(Box::pin(stream)), None)
+    }
+
+    /// A streaming body backed by an [`http_body::Body`] (a hyper or axum
+    /// body, say). Data frames become chunks and a trailers frame becomes
+    /// the body's trailers.
+    pub fn from_http_body<B>(body: B) -> Self
+    where
+        B: http_body::Body + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let size_hint = body.size_hint().exact();
+        let mut body = Self::from_stream_with_trailers(|trailers| HttpBodyChunks {
+            body: Box::pin(body),
+            trailers: Some(trailers),
+        });
+        body.size_hint = size_hint;
+        body
+    }
+
+    /// A streaming body whose chunk stream forwards the trailers it finds
+    /// after the data through the sender it is built with.
+    pub(crate) fn from_stream_with_trailers<S>(stream: impl FnOnce(TrailerSender) -> S) -> Self
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let mut body = Self::from_stream(stream(TrailerSender { tx }));
+        body.trailers = Trailers::Deferred(rx);
+        body
+    }
+
+    /// Ends the body with `trailers` once its data is read.
+    ///
+    /// Only the HTTP/2 edge transport carries response trailers; over QUIC
+    /// they are dropped with a warning, so origins that rely on them (gRPC
+    /// and its `grpc-status`) need `Transport::H2`.
+    pub fn with_trailers(mut self, trailers: http::HeaderMap) -> Self {
+        self.trailers = Trailers::Ready(trailers);
+        self
+    }
+
+    /// Ends the body with trailers sent later through the returned
+    /// [`TrailerSender`], for trailers that depend on the data (a gRPC
+    /// status computed while streaming).
+    pub fn with_deferred_trailers(mut self) -> (Self, TrailerSender) {
+        let (tx, rx) = oneshot::channel();
+        self.trailers = Trailers::Deferred(rx);
+        (self, TrailerSender { tx })
+    }
+
+    /// Whether the body may end with trailers.
+    pub fn has_trailers(&self) -> bool {
+        !matches!(self.trailers, Trailers::None)
+    }
+
+    /// Takes the trailers, waiting for a deferred sender. Call after the
+    /// data is read to the end; returns `None` when the body has no
+    /// trailers, the sender was dropped, or they were already taken.
+    pub async fn trailers(&mut self) -> Option<http::HeaderMap> {
+        std::future::poll_fn(|cx| self.poll_trailers(cx)).await
+    }
+
+    /// Polls for the trailers; see [`trailers`](Self::trailers).
+    pub(crate) fn poll_trailers(&mut self, cx: &mut Context<'_>) -> Poll<Option<http::HeaderMap>> {
+        let trailers = match &mut self.trailers {
+            Trailers::None => None,
+            Trailers::Ready(_) => match std::mem::replace(&mut self.trailers, Trailers::None) {
+                Trailers::Ready(trailers) => Some(trailers),
+                _ => None,
+            },
+            Trailers::Deferred(rx) => match Pin::new(rx).poll(cx) {
+                Poll::Ready(trailers) => trailers.ok(),
+                Poll::Pending => return Poll::Pending,
+            },
+        };
+        self.trailers = Trailers::None;
+        Poll::Ready(trailers)
+    }
+
+    /// Overrides the connector's [`FlushPolicy`] for this body; use
+    /// [`FlushPolicy::Always`] for server-sent events or long polling so
+    /// every chunk reaches the visitor as soon as it is produced.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = Some(policy);
+        self
+    }
+
+    /// The flush policy set on this body, if any.
+    pub fn flush_policy(&self) -> Option<FlushPolicy> {
+        self.flush_policy
     }
 
     /// The expected body length, when known.
@@ -116,11 +276,51 @@ impl Body {
     }
 
     /// Reads the whole body into memory.
+    ///
+    /// With `StreamExt` in scope, `body.collect()` resolves to the stream
+    /// combinator instead; call this one as `Body::collect(&mut body)`.
     pub async fn collect(&mut self) -> std::io::Result<Vec<u8>> {
         let mut buffer = Vec::new();
-        io::copy(self, &mut buffer).await?;
+        while let Some(chunk) = self.next().await {
+            buffer.extend_from_slice(&chunk?);
+        }
         Ok(buffer)
     }
+
+    /// Polls the next chunk of data: the rest of a partially read chunk
+    /// first, then the next chunk from the source. Exhausted sources are
+    /// replaced with `Empty` so polling after the end is harmless.
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        if !self.pending.is_empty() {
+            return Poll::Ready(Some(Ok(std::mem::take(&mut self.pending))));
+        }
+        let polled = match &mut self.inner {
+            BodyInner::Empty => Poll::Ready(None),
+            BodyInner::Bytes(bytes) if bytes.is_empty() => Poll::Ready(None),
+            BodyInner::Bytes(bytes) => Poll::Ready(Some(Ok(std::mem::take(bytes)))),
+            BodyInner::Reader(reader, buffer) => {
+                if buffer.len() < MIN_READ_SIZE {
+                    *buffer = BytesMut::zeroed(READ_CHUNK_SIZE);
+                }
+                match reader.as_mut().poll_read(cx, buffer) {
+                    Poll::Ready(Ok(0)) => Poll::Ready(None),
+                    Poll::Ready(Ok(n)) => Poll::Ready(Some(Ok(buffer.split_to(n).freeze()))),
+                    Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+            BodyInner::Stream(stream) => loop {
+                match stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(Ok(chunk))) if chunk.is_empty() => continue,
+                    polled => break polled,
+                }
+            },
+        };
+        if let Poll::Ready(None) = polled {
+            self.inner = BodyInner::Empty;
+        }
+        polled
+    }
 }
 
 impl AsyncRead for Body {
@@ -129,12 +329,112 @@ impl AsyncRead for Body {
         cx: &mut Context<'_>,
         buffer: &mut [u8],
     ) -> Poll<std::io::Result<usize>> {
-        match &mut self.inner {
-            BodyInner::Empty => Poll::Ready(Ok(0)),
-            BodyInner::Bytes(cursor) => {
-                futures_util::io::AsyncRead::poll_read(Pin::new(&mut *cursor), cx, buffer)
+        if buffer.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let mut chunk = match self.poll_chunk(cx) {
+            Poll::Ready(Some(Ok(chunk))) => chunk,
+            Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+            Poll::Ready(None) => return Poll::Ready(Ok(0)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let n = std::cmp::min(chunk.len(), buffer.len());
+        buffer[..n].copy_from_slice(&chunk.split_to(n));
+        self.pending = chunk;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl Stream for Body {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_chunk(cx)
+    }
+}
+
+impl http_body::Body for Body {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<http_body::Frame<Bytes>>>> {
+        match self.poll_chunk(cx) {
+            Poll::Ready(Some(Ok(chunk))) => Poll::Ready(Some(Ok(http_body::Frame::data(chunk)))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => self
+                .poll_trailers(cx)
+                .map(|trailers| trailers.map(|trailers| Ok(http_body::Frame::trailers(trailers)))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let drained = match &self.inner {
+            BodyInner::Empty => true,
+            BodyInner::Bytes(bytes) => bytes.is_empty(),
+            BodyInner::Reader(..) | BodyInner::Stream(_) => false,
+        };
+        drained && self.pending.is_empty() && !self.has_trailers()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        let remaining = match &self.inner {
+            BodyInner::Empty => Some(0),
+            BodyInner::Bytes(bytes) => Some(bytes.len() as u64),
+            BodyInner::Reader(..) | BodyInner::Stream(_) => None,
+        };
+        match remaining {
+            Some(remaining) => {
+                http_body::SizeHint::with_exact(remaining + self.pending.len() as u64)
+            }
+            None => http_body::SizeHint::default(),
+        }
+    }
+}
+
+/// The data frames of an [`http_body::Body`] as chunks; the trailers frame
+/// goes to the body's [`TrailerSender`].
+struct HttpBodyChunks<B> {
+    body: Pin<Box<B>>,
+    trailers: Option<TrailerSender>,
+}
+
+impl<B> Stream for HttpBodyChunks<B>
+where
+    B: http_body::Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.body.as_mut().poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => match frame.into_data() {
+                    Ok(mut data) => {
+                        let remaining = data.remaining();
+                        return Poll::Ready(Some(Ok(data.copy_to_bytes(remaining))));
+                    }
+                    Err(frame) => {
+                        if let Ok(trailers) = frame.into_trailers()
+                            && let Some(sender) = self.trailers.take()
+                        {
+                            sender.send(trailers);
+                        }
+                    }
+                },
+                Poll::Ready(Some(Err(e))) => {
+                    return Poll::Ready(Some(Err(io::Error::other(e.into()))));
+                }
+                Poll::Ready(None) => {
+                    // Dropping an unused sender ends the body without trailers.
+                    self.trailers = None;
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
             }
-            BodyInner::Reader(reader) => reader.as_mut().poll_read(cx, buffer),
         }
     }
 }
@@ -143,6 +443,89 @@ impl std::fmt::Debug for Body {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Body")
             .field("size_hint", &self.size_hint)
+            .field("has_trailers", &self.has_trailers())
+            .field("flush_policy", &self.flush_policy)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grpc_status(code: &str) -> http::HeaderMap {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("grpc-status", code.parse().unwrap());
+        trailers
+    }
+
+    #[tokio::test]
+    async fn fixed_trailers_follow_the_data() {
+        let mut body = Body::from_bytes(b"payload".to_vec()).with_trailers(grpc_status("0"));
+        assert!(body.has_trailers());
+        assert_eq!(Body::collect(&mut body).await.unwrap(), b"payload");
+        assert_eq!(body.trailers().await.unwrap()["grpc-status"], "0");
+        assert!(body.trailers().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn deferred_trailers_arrive_through_the_sender() {
+        let (mut body, sender) = Body::from_bytes(b"payload".to_vec()).with_deferred_trailers();
+        sender.send(grpc_status("13"));
+        Body::collect(&mut body).await.unwrap();
+        assert_eq!(body.trailers().await.unwrap()["grpc-status"], "13");
+    }
+
+    #[tokio::test]
+    async fn stream_chunks_pass_through_unchanged() {
+        let chunk = Bytes::from_static(b"large download chunk");
+        let address = chunk.as_ptr();
+        let mut body = Body::from_stream(futures_util::stream::iter([Ok(chunk)]));
+        let received = body.next().await.unwrap().unwrap();
+        assert_eq!(received.as_ptr(), address);
+        assert!(body.next().await.is_none());
+        assert!(body.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn reader_chunks_share_one_buffer() {
+        use futures_util::TryStreamExt as _;
+        let source =
+            futures_util::stream::iter([Ok::<_, io::Error>(&b"first "[..]), Ok(&b"second"[..])]);
+        let mut body = Body::from_reader(source.into_async_read());
+        let first = body.next().await.unwrap().unwrap();
+        let second = body.next().await.unwrap().unwrap();
+        assert_eq!((&first[..], &second[..]), (&b"first "[..], &b"second"[..]));
+        assert_eq!(second.as_ptr(), first.as_ptr().wrapping_add(first.len()));
+        assert!(body.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn partial_reads_resume_mid_chunk() {
+        let mut body = Body::from_stream(futures_util::stream::iter([
+            Ok(Bytes::from_static(b"hello ")),
+            Ok(Bytes::from_static(b"world")),
+        ]));
+        let mut head = [0u8; 3];
+        futures_util::io::AsyncReadExt::read_exact(&mut body, &mut head)
+            .await
+            .unwrap();
+        assert_eq!(&head, b"hel");
+        assert_eq!(Body::collect(&mut body).await.unwrap(), b"lo world");
+    }
+
+    #[tokio::test]
+    async fn http_body_frames_become_data_and_trailers() {
+        let source = Body::from_bytes(b"ping".to_vec()).with_trailers(grpc_status("0"));
+        let mut body = Body::from_http_body(source);
+        assert_eq!(Body::collect(&mut body).await.unwrap(), b"ping");
+        assert_eq!(body.trailers().await.unwrap()["grpc-status"], "0");
+    }
+
+    #[tokio::test]
+    async fn dropped_sender_ends_without_trailers() {
+        let (mut body, sender) = Body::empty().with_deferred_trailers();
+        drop(sender);
+        assert!(body.trailers().await.is_none());
+    }
+}
@@ -0,0 +1,231 @@
+//! When response chunks are flushed to the edge.
+//!
+//! Flushing writes each chunk as soon as the origin produces it; otherwise
+//! chunks that are ready together are coalesced into larger writes. The
+//! automatic rule mirrors cloudflared's `shouldFlush`: responses without a
+//! known length, chunked responses, and server-sent events, gRPC and NDJSON
+//! streams are flushed.
+
+#[cfg(edge_conn)]
+use bytes::{Bytes, BytesMut};
+#[cfg(edge_conn)]
+use futures_util::{FutureExt, StreamExt};
+
+#[cfg(edge_conn)]
+use crate::origin::http::body::Body;
+
+/// Content types cloudflared flushes regardless of length.
+const FLUSHABLE_CONTENT_TYPES: &[&str] = &[
+    "text/event-stream",
+    "application/grpc",
+    "application/x-ndjson",
+];
+
+/// Coalesced writes stop growing at this size.
+#[cfg(edge_conn)]
+const COALESCE_LIMIT: usize = 64 * 1024;
+
+/// Chunks at least this large are written as they are, never copied into
+/// a coalesced write.
+#[cfg(edge_conn)]
+const COALESCE_CHUNK_LIMIT: usize = 16 * 1024;
+
+/// How response chunks are flushed to the edge.
+///
+/// Set connector-wide with [`EdgeOptions::flush_policy`](crate::EdgeOptions)
+/// and per response with [`Body::with_flush_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// cloudflared's rule: flush responses without a `Content-Length`,
+    /// chunked responses, and `text/event-stream`, `application/grpc` and
+    /// `application/x-ndjson` responses; coalesce the rest.
+    #[default]
+    Auto,
+    /// Flush every chunk as soon as the origin produces it.
+    Always,
+    /// Never flush early; coalesce chunks that are ready together.
+    Never,
+}
+
+impl FlushPolicy {
+    /// Whether a response with these headers flushes every chunk.
+    pub fn flushes(self, headers: &http::HeaderMap) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => auto_flushes(headers),
+        }
+    }
+}
+
+fn auto_flushes(headers: &http::HeaderMap) -> bool {
+    let length = headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok());
+    if matches!(length, None | Some("") | Some("-1")) {
+        return true;
+    }
+    if headers
+        .get_all(http::header::TRANSFER_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.to_ascii_lowercase().contains("chunked"))
+    {
+        return true;
+    }
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_ascii_lowercase)
+        .is_some_and(|content_type| {
+            FLUSHABLE_CONTENT_TYPES
+                .iter()
+                .any(|flushable| content_type.starts_with(flushable))
+        })
+}
+
+/// The writes a transport makes for a response body: each chunk as it
+/// arrives when flushing, otherwise small chunks that are already ready
+/// merged up to [`COALESCE_LIMIT`]. Large chunks pass through uncopied.
+#[cfg(edge_conn)]
+pub(crate) struct ResponseWrites<'a> {
+    body: &'a mut Body,
+    flush: bool,
+    /// What ended the previous coalesced write: a chunk too large to merge
+    /// or an error, written or reported after the data before it.
+    held: Option<std::io::Result<Bytes>>,
+}
+
+#[cfg(edge_conn)]
+impl<'a> ResponseWrites<'a> {
+    pub(crate) fn new(body: &'a mut Body, flush: bool) -> Self {
+        Self {
+            body,
+            flush,
+            held: None,
+        }
+    }
+
+    /// Whether each write should be flushed to the edge.
+    #[cfg(quic_any)]
+    pub(crate) fn flushes(&self) -> bool {
+        self.flush
+    }
+
+    /// The next write, or `None` once the body ends.
+    pub(crate) async fn next(&mut self) -> Option<std::io::Result<Bytes>> {
+        let first = match self.held.take() {
+            Some(held) => held,
+            None => self.body.next().await?,
+        };
+        let first = match first {
+            Ok(chunk) => chunk,
+            Err(e) => return Some(Err(e)),
+        };
+        if self.flush || first.len() >= COALESCE_CHUNK_LIMIT {
+            return Some(Ok(first));
+        }
+        let mut merged: Option<BytesMut> = None;
+        let mut length = first.len();
+        while length < COALESCE_LIMIT {
+            let Some(Some(next)) = self.body.next().now_or_never() else {
+                break;
+            };
+            match next {
+                Ok(chunk) if chunk.len() < COALESCE_CHUNK_LIMIT => {
+                    length += chunk.len();
+                    merged
+                        .get_or_insert_with(|| BytesMut::from(&first[..]))
+                        .extend_from_slice(&chunk);
+                }
+                next => {
+                    self.held = Some(next);
+                    break;
+                }
+            }
+        }
+        Some(Ok(merged.map_or(first, BytesMut::freeze)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn auto_flushes_streaming_responses_like_cloudflared() {
+        let policy = FlushPolicy::Auto;
+        assert!(policy.flushes(&headers(&[])));
+        assert!(policy.flushes(&headers(&[
+            ("content-length", "12"),
+            ("content-type", "text/event-stream; charset=utf-8"),
+        ])));
+        assert!(policy.flushes(&headers(&[
+            ("content-length", "12"),
+            ("content-type", "application/grpc+proto"),
+        ])));
+        assert!(policy.flushes(&headers(&[
+            ("content-length", "12"),
+            ("transfer-encoding", "chunked"),
+        ])));
+        assert!(!policy.flushes(&headers(&[
+            ("content-length", "12"),
+            ("content-type", "text/html"),
+        ])));
+    }
+
+    #[test]
+    fn explicit_policies_ignore_headers() {
+        assert!(FlushPolicy::Always.flushes(&headers(&[("content-length", "12")])));
+        assert!(!FlushPolicy::Never.flushes(&headers(&[("content-type", "text/event-stream")])));
+    }
+
+    #[cfg(edge_conn)]
+    #[tokio::test]
+    async fn coalesces_ready_chunks_unless_flushing() {
+        let chunks = || {
+            futures_util::stream::iter([
+                Ok(Bytes::from_static(b"data: a\n\n")),
+                Ok(Bytes::from_static(b"data: b\n\n")),
+            ])
+        };
+        let mut body = Body::from_stream(chunks());
+        let mut writes = ResponseWrites::new(&mut body, false);
+        assert_eq!(
+            writes.next().await.unwrap().unwrap(),
+            "data: a\n\ndata: b\n\n"
+        );
+        assert!(writes.next().await.is_none());
+
+        let mut body = Body::from_stream(chunks());
+        let mut writes = ResponseWrites::new(&mut body, true);
+        assert_eq!(writes.next().await.unwrap().unwrap(), "data: a\n\n");
+        assert_eq!(writes.next().await.unwrap().unwrap(), "data: b\n\n");
+        assert!(writes.next().await.is_none());
+    }
+
+    #[cfg(edge_conn)]
+    #[tokio::test]
+    async fn large_chunks_pass_through_uncopied() {
+        let large = Bytes::from(vec![b'x'; COALESCE_CHUNK_LIMIT]);
+        let address = large.as_ptr();
+        let mut body = Body::from_stream(futures_util::stream::iter([
+            Ok(Bytes::from_static(b"head")),
+            Ok(large),
+            Ok(Bytes::from_static(b"tail")),
+        ]));
+        let mut writes = ResponseWrites::new(&mut body, false);
+        assert_eq!(writes.next().await.unwrap().unwrap(), "head");
+        assert_eq!(writes.next().await.unwrap().unwrap().as_ptr(), address);
+        assert_eq!(writes.next().await.unwrap().unwrap(), "tail");
+        assert!(writes.next().await.is_none());
+    }
+}
@@ -1,6 +1,7 @@
 //! The [`HttpOrigin`] trait consumers implement for HTTP requests.
 
 pub mod body;
+pub mod flush;
 
 use crate::origin::http::body::Request;
 use crate::origin::responder::HttpResponder;
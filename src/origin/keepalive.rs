@@ -0,0 +1,281 @@
+//! Keepalive pings injected into pumped websocket streams.
+//!
+//! The pump sees websocket connections as raw bytes, so it tracks frame
+//! boundaries in both directions: pings are only written between two
+//! frames of the stream flowing toward the pinged side, and pongs are
+//! spotted in the stream flowing back. Control frames may sit between the
+//! fragments of a message, so any frame boundary will do.
+
+use std::time::Duration;
+
+#[cfg(edge_conn)]
+use tokio::time::Instant;
+
+use crate::origin::pump::StreamSide;
+
+#[cfg(edge_conn)]
+const OPCODE_PING: u8 = 0x9;
+#[cfg(edge_conn)]
+const OPCODE_PONG: u8 = 0xA;
+
+/// Pings libcfd sends on websocket streams so that idle connections are
+/// not reaped by timers along the path, and dead ones are noticed.
+///
+/// Set through
+/// [`StreamOptions::websocket_keepalive`](crate::StreamOptions::websocket_keepalive).
+/// The pings are injected into the byte stream the transport pumps, so a
+/// [`StreamOrigin<WebSocketResponder>`](crate::StreamOrigin) handing over a
+/// raw stream needs no changes. When pinging the edge, the visitor's pongs
+/// still reach the origin, where RFC 6455 has them ignored as unsolicited;
+/// likewise the origin's pongs reach the visitor when pinging the origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketKeepalive {
+    /// Time between pings.
+    pub interval: Duration,
+    /// How long a ping may go unanswered before the stream is torn down.
+    pub timeout: Duration,
+    /// The side that is pinged.
+    pub target: StreamSide,
+}
+
+impl Default for WebSocketKeepalive {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            target: StreamSide::Edge,
+        }
+    }
+}
+
+/// What the pump should do when a keepalive timer fires.
+#[cfg(edge_conn)]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Tick {
+    /// Write [`Keepalive::ping`] toward the target now.
+    Ping,
+    /// A ping is due but the target's stream is mid-frame, or nothing is
+    /// due yet.
+    Wait,
+    /// A ping went unanswered.
+    Dead,
+}
+
+/// The keepalive state of one pumped websocket stream.
+#[cfg(edge_conn)]
+pub(crate) struct Keepalive {
+    settings: WebSocketKeepalive,
+    /// Frames flowing toward the target, where pings are injected.
+    outbound: FrameScanner,
+    /// Frames flowing back from the target, where pongs show up.
+    inbound: FrameScanner,
+    next_ping: Instant,
+    pong_deadline: Option<Instant>,
+    due: bool,
+}
+
+#[cfg(edge_conn)]
+impl Keepalive {
+    pub(crate) fn new(settings: &WebSocketKeepalive, now: Instant) -> Self {
+        Self {
+            settings: settings.clone(),
+            outbound: FrameScanner::default(),
+            inbound: FrameScanner::default(),
+            next_ping: now + settings.interval,
+            pong_deadline: None,
+            due: false,
+        }
+    }
+
+    /// The side that is pinged.
+    pub(crate) fn target(&self) -> StreamSide {
+        self.settings.target
+    }
+
+    /// When the pump must next call [`tick`](Self::tick).
+    pub(crate) fn deadline(&self) -> Instant {
+        match self.pong_deadline {
+            Some(pong) => pong.min(self.next_ping),
+            None => self.next_ping,
+        }
+    }
+
+    pub(crate) fn tick(&mut self, now: Instant) -> Tick {
+        if self.pong_deadline.is_some_and(|deadline| deadline <= now) {
+            return Tick::Dead;
+        }
+        if self.next_ping <= now {
+            self.due = true;
+            self.next_ping = now + self.settings.interval;
+        }
+        if self.due && self.outbound.at_boundary() {
+            Tick::Ping
+        } else {
+            Tick::Wait
+        }
+    }
+
+    /// Whether a ping should follow the frame that just ended toward the
+    /// target.
+    pub(crate) fn due(&self) -> bool {
+        self.due
+    }
+
+    /// A ping frame to write toward the target at `now`. Frames toward the
+    /// origin come from the client side and must be masked.
+    pub(crate) fn ping(&mut self, now: Instant) -> Vec<u8> {
+        self.due = false;
+        self.pong_deadline
+            .get_or_insert(now + self.settings.timeout);
+        match self.settings.target {
+            StreamSide::Edge => vec![0x80 | OPCODE_PING, 0],
+            StreamSide::Origin => {
+                let mut mask = [0u8; 4];
+                let _ = getrandom::fill(&mut mask);
+                let mut frame = vec![0x80 | OPCODE_PING, 0x80];
+                frame.extend_from_slice(&mask);
+                frame
+            }
+        }
+    }
+
+    /// Splits `bytes` flowing toward the target at the end of the current
+    /// frame: the bytes consumed, and whether a frame ended there.
+    pub(crate) fn outbound(&mut self, bytes: &[u8]) -> (usize, bool) {
+        let (used, ended) = self.outbound.advance(bytes);
+        (used, ended.is_some())
+    }
+
+    /// Scans `bytes` flowing back from the target for pongs.
+    pub(crate) fn inbound(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let (used, ended) = self.inbound.advance(bytes);
+            if ended == Some(OPCODE_PONG) {
+                self.pong_deadline = None;
+            }
+            bytes = &bytes[used..];
+        }
+    }
+}
+
+/// Follows websocket frame boundaries through a byte stream without
+/// buffering payloads.
+#[cfg(edge_conn)]
+#[derive(Default)]
+struct FrameScanner {
+    header: [u8; 14],
+    filled: usize,
+    /// Payload bytes left in the current frame, once its header is read.
+    remaining: Option<u64>,
+}
+
+#[cfg(edge_conn)]
+impl FrameScanner {
+    fn at_boundary(&self) -> bool {
+        self.filled == 0 && self.remaining.is_none()
+    }
+
+    /// The header length, as far as the bytes read so far tell.
+    fn header_len(&self) -> usize {
+        if self.filled < 2 {
+            return 2;
+        }
+        let extended = match self.header[1] & 0x7F {
+            126 => 2,
+            127 => 8,
+            _ => 0,
+        };
+        let mask = if self.header[1] & 0x80 != 0 { 4 } else { 0 };
+        2 + extended + mask
+    }
+
+    fn payload_len(&self) -> u64 {
+        match self.header[1] & 0x7F {
+            126 => u64::from(u16::from_be_bytes([self.header[2], self.header[3]])),
+            127 => u64::from_be_bytes(self.header[2..10].try_into().expect("eight bytes")),
+            length => u64::from(length),
+        }
+    }
+
+    /// Consumes `bytes` up to the end of the current frame. Returns the
+    /// bytes consumed and, when the frame ended, its opcode.
+    fn advance(&mut self, bytes: &[u8]) -> (usize, Option<u8>) {
+        let mut used = 0;
+        let remaining = match self.remaining {
+            Some(remaining) => remaining,
+            None => {
+                while self.filled < self.header_len() && used < bytes.len() {
+                    self.header[self.filled] = bytes[used];
+                    self.filled += 1;
+                    used += 1;
+                }
+                if self.filled < self.header_len() {
+                    return (used, None);
+                }
+                self.payload_len()
+            }
+        };
+        let take = remaining.min((bytes.len() - used) as u64);
+        used += take as usize;
+        if remaining == take {
+            let opcode = self.header[0] & 0x0F;
+            self.filled = 0;
+            self.remaining = None;
+            (used, Some(opcode))
+        } else {
+            self.remaining = Some(remaining - take);
+            (used, None)
+        }
+    }
+}
+
+#[cfg(all(test, edge_conn))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanner_finds_frame_ends_across_chunks() {
+        let mut scanner = FrameScanner::default();
+        // A masked 3-byte text frame followed by an unmasked empty pong.
+        let bytes = [0x81, 0x83, 1, 2, 3, 4, b'a', b'b', b'c', 0x8A, 0x00];
+        assert_eq!(scanner.advance(&bytes[..4]), (4, None));
+        assert!(!scanner.at_boundary());
+        assert_eq!(scanner.advance(&bytes[4..]), (5, Some(0x1)));
+        assert!(scanner.at_boundary());
+        assert_eq!(scanner.advance(&bytes[9..]), (2, Some(OPCODE_PONG)));
+    }
+
+    #[test]
+    fn scanner_reads_extended_lengths() {
+        let mut scanner = FrameScanner::default();
+        let mut frame = vec![0x82, 126, 0x01, 0x00];
+        frame.extend(std::iter::repeat_n(0u8, 256));
+        assert_eq!(scanner.advance(&frame[..3]), (3, None));
+        assert_eq!(scanner.advance(&frame[3..100]), (97, None));
+        assert_eq!(scanner.advance(&frame[100..]), (160, Some(0x2)));
+    }
+
+    #[tokio::test]
+    async fn pings_wait_for_a_frame_boundary_and_pongs_clear_the_deadline() {
+        let settings = WebSocketKeepalive {
+            interval: Duration::from_millis(1),
+            ..WebSocketKeepalive::default()
+        };
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(&settings, start);
+        // Half a frame toward the edge: the ping has to wait.
+        assert_eq!(keepalive.outbound(&[0x81, 0x05, b'h']), (3, false));
+        let later = start + Duration::from_millis(5);
+        assert_eq!(keepalive.tick(later), Tick::Wait);
+        assert!(keepalive.due());
+        assert_eq!(keepalive.outbound(b"ello"), (4, true));
+        assert_eq!(keepalive.ping(later), vec![0x89, 0x00]);
+        let dead = later + settings.timeout;
+        assert!(keepalive.deadline() <= dead);
+        assert_eq!(keepalive.tick(dead), Tick::Dead);
+
+        keepalive.inbound(&[0x8A, 0x80, 0, 0, 0, 0]);
+        assert_eq!(keepalive.tick(later), Tick::Wait);
+        assert_ne!(keepalive.tick(dead), Tick::Dead);
+    }
+}
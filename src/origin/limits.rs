@@ -0,0 +1,351 @@
+//! Per-origin guard rails on request size and upload speed.
+//!
+//! Both transports check the headers before dispatching and wrap the request
+//! body, so an origin that was never hardened against oversized or trickled
+//! requests is not the first line of defence.
+
+use std::time::Duration;
+
+#[cfg(edge_conn)]
+use std::io;
+#[cfg(edge_conn)]
+use std::pin::Pin;
+#[cfg(edge_conn)]
+use std::sync::{Arc, OnceLock};
+#[cfg(edge_conn)]
+use std::task::{Context, Poll};
+
+#[cfg(edge_conn)]
+use bytes::Bytes;
+#[cfg(edge_conn)]
+use futures_util::Stream;
+#[cfg(edge_conn)]
+use tokio::time::{Instant, Sleep};
+
+#[cfg(edge_conn)]
+use crate::origin::error::OriginError;
+
+/// How long a body may upload before [`OriginLimits::min_upload_rate`]
+/// applies, so connection ramp-up is not mistaken for a slow client.
+#[cfg(edge_conn)]
+const UPLOAD_RATE_GRACE: Duration = Duration::from_secs(5);
+
+/// Limits the transports enforce on requests before and while an origin
+/// handles them. Every limit is off by default.
+///
+/// Set per origin with [`Origin::with_limits`](crate::Origin::with_limits).
+/// A rejected request gets the status below, rendered like any other
+/// [`OriginError`](crate::OriginError) (including by
+/// [`EdgeOptions::error_page`](crate::EdgeOptions)).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OriginLimits {
+    /// Most request header values; more answer `431` without reaching the
+    /// handler.
+    pub max_header_count: Option<usize>,
+    /// Most bytes across header names and values; more answer `431`.
+    pub max_header_bytes: Option<usize>,
+    /// Largest request body. A larger `Content-Length` answers `413` before
+    /// the handler runs; a body that grows past it fails the handler's read
+    /// and answers `413`.
+    pub max_body_bytes: Option<u64>,
+    /// Longest wait for the next body chunk; a stalled upload fails the
+    /// handler's read and answers `408`.
+    pub body_read_timeout: Option<Duration>,
+    /// Slowest average upload rate, in bytes per second, accepted once the
+    /// body has been uploading for five seconds; a slower upload answers
+    /// `408`.
+    pub min_upload_rate: Option<u64>,
+}
+
+#[cfg(edge_conn)]
+impl OriginLimits {
+    /// Checks the request head: header count and size, and a declared
+    /// `Content-Length` against the body limit.
+    #[cfg(any(h2_any, test))]
+    pub(crate) fn check_headers(&self, headers: &http::HeaderMap) -> Result<(), OriginError> {
+        self.check_header_fields(
+            headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_bytes())),
+        )
+    }
+
+    /// Checks header fields as a transport decodes them, so a head over
+    /// the limits is rejected before a [`HeaderMap`](http::HeaderMap) is
+    /// built for it. Stops at the first field past the count limit.
+    pub(crate) fn check_header_fields<'a>(
+        &self,
+        fields: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    ) -> Result<(), OriginError> {
+        let mut count = 0;
+        let mut bytes = 0;
+        let mut content_length = None;
+        for (name, value) in fields {
+            count += 1;
+            if let Some(max) = self.max_header_count
+                && count > max
+            {
+                return Err(headers_too_large(format!(
+                    "request has more than the limit of {max} headers"
+                )));
+            }
+            bytes += name.len() + value.len();
+            // The first `Content-Length` counts, as with `HeaderMap::get`.
+            if content_length.is_none()
+                && name.eq_ignore_ascii_case(http::header::CONTENT_LENGTH.as_str())
+            {
+                content_length = Some(
+                    std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|value| value.trim().parse::<u64>().ok()),
+                );
+            }
+        }
+        if let Some(max) = self.max_header_bytes
+            && bytes > max
+        {
+            return Err(headers_too_large(format!(
+                "request headers are {bytes} bytes, more than the limit of {max}"
+            )));
+        }
+        if let Some(max) = self.max_body_bytes
+            && content_length.flatten().is_some_and(|length| length > max)
+        {
+            return Err(body_too_large(max));
+        }
+        Ok(())
+    }
+
+    /// Wraps a request body's chunks so they enforce the body limits,
+    /// recording the first breach in `breach`.
+    pub(crate) fn limit_body<S>(&self, chunks: S, breach: &LimitBreach) -> LimitedBody<S>
+    where
+        S: Stream<Item = io::Result<Bytes>>,
+    {
+        LimitedBody {
+            inner: Box::pin(chunks),
+            max_bytes: self.max_body_bytes,
+            read_timeout: self.body_read_timeout,
+            min_rate: self.min_upload_rate.filter(|rate| *rate > 0),
+            received: 0,
+            started: Instant::now(),
+            last_chunk: Instant::now(),
+            timer: None,
+            breach: breach.clone(),
+            done: false,
+        }
+    }
+}
+
+#[cfg(edge_conn)]
+fn headers_too_large(message: String) -> OriginError {
+    OriginError::new(http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, message)
+}
+
+#[cfg(edge_conn)]
+fn body_too_large(max: u64) -> OriginError {
+    OriginError::new(
+        http::StatusCode::PAYLOAD_TOO_LARGE,
+        format!("request body is larger than the limit of {max} bytes"),
+    )
+}
+
+#[cfg(edge_conn)]
+fn upload_timed_out(message: &str) -> OriginError {
+    OriginError::new(http::StatusCode::REQUEST_TIMEOUT, message)
+}
+
+/// The first limit a request body broke, shared between the body the
+/// handler reads and the transport that answers for it.
+#[cfg(edge_conn)]
+#[derive(Clone, Default)]
+pub(crate) struct LimitBreach(Arc<OnceLock<OriginError>>);
+
+#[cfg(edge_conn)]
+impl LimitBreach {
+    /// Records `error` (unless an earlier breach is recorded) and returns
+    /// the read error the handler sees.
+    fn record(&self, error: OriginError) -> io::Error {
+        let kind = if error.status() == http::StatusCode::REQUEST_TIMEOUT {
+            io::ErrorKind::TimedOut
+        } else {
+            io::ErrorKind::InvalidData
+        };
+        let read_error = io::Error::new(kind, error.message().to_string());
+        let _ = self.0.set(error);
+        read_error
+    }
+
+    /// The breach to answer with instead of the handler's outcome, if any.
+    pub(crate) fn error(&self) -> Option<OriginError> {
+        self.0.get().cloned()
+    }
+}
+
+/// Request body chunks with the [`OriginLimits`] body limits applied.
+#[cfg(edge_conn)]
+pub(crate) struct LimitedBody<S> {
+    inner: Pin<Box<S>>,
+    max_bytes: Option<u64>,
+    read_timeout: Option<Duration>,
+    min_rate: Option<u64>,
+    received: u64,
+    started: Instant,
+    last_chunk: Instant,
+    timer: Option<Pin<Box<Sleep>>>,
+    breach: LimitBreach,
+    done: bool,
+}
+
+#[cfg(edge_conn)]
+impl<S> LimitedBody<S> {
+    /// When the upload stalls (`true`) or falls below the minimum rate
+    /// (`false`) unless another chunk arrives first.
+    fn deadline(&self) -> Option<(Instant, bool)> {
+        let stall = self
+            .read_timeout
+            .map(|timeout| (self.last_chunk + timeout, true));
+        let slow = self.min_rate.map(|rate| {
+            let earned = Duration::from_secs_f64(self.received as f64 / rate as f64);
+            (self.started + earned.max(UPLOAD_RATE_GRACE), false)
+        });
+        match (stall, slow) {
+            (Some(stall), Some(slow)) => Some(if stall.0 <= slow.0 { stall } else { slow }),
+            (deadline, None) | (None, deadline) => deadline,
+        }
+    }
+
+    fn fail(&mut self, error: OriginError) -> Poll<Option<io::Result<Bytes>>> {
+        self.done = true;
+        self.timer = None;
+        Poll::Ready(Some(Err(self.breach.record(error))))
+    }
+}
+
+#[cfg(edge_conn)]
+impl<S> Stream for LimitedBody<S>
+where
+    S: Stream<Item = io::Result<Bytes>>,
+{
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        match this.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.received += chunk.len() as u64;
+                this.last_chunk = Instant::now();
+                if let Some(max) = this.max_bytes
+                    && this.received > max
+                {
+                    return this.fail(body_too_large(max));
+                }
+                if let Some((at, false)) = this.deadline()
+                    && at <= this.last_chunk
+                {
+                    return this.fail(upload_timed_out("request body uploaded too slowly"));
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(end) => {
+                this.done = true;
+                this.timer = None;
+                Poll::Ready(end)
+            }
+            Poll::Pending => {
+                let Some((at, stalled)) = this.deadline() else {
+                    return Poll::Pending;
+                };
+                match &mut this.timer {
+                    Some(timer) if timer.deadline() != at => timer.as_mut().reset(at),
+                    Some(_) => {}
+                    None => this.timer = Some(Box::pin(tokio::time::sleep_until(at))),
+                }
+                let timer = this.timer.as_mut().expect("armed above");
+                if timer.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.fail(upload_timed_out(if stalled {
+                    "request body stalled"
+                } else {
+                    "request body uploaded too slowly"
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, edge_conn))]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn rejects_oversized_heads() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("x-one", "1".parse().unwrap());
+        headers.insert("x-two", "2".parse().unwrap());
+        headers.insert("content-length", "2048".parse().unwrap());
+
+        assert!(OriginLimits::default().check_headers(&headers).is_ok());
+        let limits = OriginLimits {
+            max_header_count: Some(2),
+            ..Default::default()
+        };
+        let error = limits.check_headers(&headers).unwrap_err();
+        assert_eq!(
+            error.status(),
+            http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+        let limits = OriginLimits {
+            max_body_bytes: Some(1024),
+            ..Default::default()
+        };
+        let error = limits.check_headers(&headers).unwrap_err();
+        assert_eq!(error.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn body_past_the_limit_fails_with_413() {
+        let limits = OriginLimits {
+            max_body_bytes: Some(4),
+            ..Default::default()
+        };
+        let breach = LimitBreach::default();
+        let chunks = futures_util::stream::iter([
+            Ok(Bytes::from_static(b"abc")),
+            Ok(Bytes::from_static(b"def")),
+        ]);
+        let mut body = limits.limit_body(chunks, &breach);
+        assert_eq!(body.next().await.unwrap().unwrap(), "abc");
+        let error = body.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(body.next().await.is_none());
+        assert_eq!(
+            breach.error().unwrap().status(),
+            http::StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn stalled_body_fails_with_408() {
+        let limits = OriginLimits {
+            body_read_timeout: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let breach = LimitBreach::default();
+        let chunks = futures_util::stream::iter([Ok(Bytes::from_static(b"abc"))])
+            .chain(futures_util::stream::pending());
+        let mut body = limits.limit_body(chunks, &breach);
+        assert_eq!(body.next().await.unwrap().unwrap(), "abc");
+        let error = body.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(
+            breach.error().unwrap().status(),
+            http::StatusCode::REQUEST_TIMEOUT
+        );
+    }
+}
@@ -10,25 +10,46 @@
 //! The request and response types are transport-neutral and
 //! runtime-agnostic.
 
+mod cancel;
+mod context;
 mod error;
-pub use error::Error;
+mod keepalive;
+mod limits;
+pub use cancel::CancellationToken;
+pub use context::{EdgeProtocol, RequestContext};
+pub use error::{Error, OriginError};
+pub use keepalive::WebSocketKeepalive;
+#[cfg(edge_conn)]
+pub(crate) use limits::LimitBreach;
+pub use limits::OriginLimits;
 mod pump;
 mod responder;
 
 #[cfg(feature = "axum-origin")]
 pub mod axum;
+#[cfg(compression_any)]
+pub mod compression;
 pub mod http;
 pub mod stream;
+pub mod websocket;
 
-pub use self::http::body::{Body, Request, Response};
+pub use self::http::body::{Body, Request, Response, TrailerSender};
+pub use self::http::flush::FlushPolicy;
+#[cfg(edge_conn)]
+pub(crate) use self::http::flush::ResponseWrites;
 pub use http::HttpOrigin;
 #[cfg(edge_conn)]
 pub(crate) use pump::pump;
-pub use pump::websocket_accept;
-#[cfg(edge_conn)]
+pub use pump::{StreamEnd, StreamKind, StreamOptions, StreamSide, StreamSummary, websocket_accept};
+#[cfg(all(edge_conn, test))]
 pub(crate) use responder::wait_outcome;
+#[cfg(edge_conn)]
+pub(crate) use responder::wait_outcome_unless;
 pub use responder::{HttpResponder, StreamResponder, TcpResponder, WebSocketResponder};
 pub use stream::{ReadHalf, Stream, StreamOrigin, WebSocketConnection, WriteHalf};
+#[cfg(edge_conn)]
+pub use websocket::WebSocketProxyOrigin;
+pub use websocket::{WebSocket, WebSocketUpgrade};
 
 use std::sync::Arc;
 
@@ -37,13 +58,19 @@ use std::sync::Arc;
 /// Every run needs an [`HttpOrigin`]; websocket and TCP stream handlers are
 /// optional and enabled with [`Origin::with_websocket`] and
 /// [`Origin::with_tcp`].
+#[derive(Clone)]
 #[cfg_attr(not(edge_conn), allow(dead_code))]
 pub struct Origin {
     pub(crate) http: Arc<dyn HttpOrigin>,
     pub(crate) websocket: Option<Arc<dyn StreamOrigin<WebSocketResponder>>>,
     pub(crate) tcp: Option<Arc<dyn StreamOrigin<TcpResponder>>>,
+    pub(crate) limits: OriginLimits,
+    pub(crate) streams: StreamOptions,
+    pub(crate) on_stream_complete: Option<StreamHook>,
 }
 
+type StreamHook = Arc<dyn Fn(&StreamSummary) + Send + Sync>;
+
 impl Origin {
     /// Creates an origin with an HTTP handler.
     pub fn http<O>(http: O) -> Self
@@ -54,6 +81,9 @@ impl Origin {
             http: Arc::new(http),
             websocket: None,
             tcp: None,
+            limits: OriginLimits::default(),
+            streams: StreamOptions::default(),
+            on_stream_complete: None,
         }
     }
 
@@ -74,6 +104,45 @@ impl Origin {
         self.tcp = Some(Arc::new(tcp));
         self
     }
+
+    /// Sets the request limits the transports enforce for this origin.
+    pub fn with_limits(mut self, limits: OriginLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the timeouts and half-close behaviour of the websocket and TCP
+    /// streams the transports pump for this origin.
+    pub fn with_stream_options(mut self, options: StreamOptions) -> Self {
+        self.streams = options;
+        self
+    }
+
+    /// Calls `hook` with a [`StreamSummary`] after every websocket or TCP
+    /// stream ends, e.g. to account traffic per session. The hook runs on
+    /// the transport's task and should return quickly.
+    pub fn on_stream_complete<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&StreamSummary) + Send + Sync + 'static,
+    {
+        self.on_stream_complete = Some(Arc::new(hook));
+        self
+    }
+
+    /// Reports a finished stream to the completion hook.
+    #[cfg(edge_conn)]
+    pub(crate) fn stream_completed(&self, summary: &StreamSummary) {
+        tracing::debug!(
+            kind = ?summary.kind,
+            bytes_from_edge = summary.bytes_from_edge,
+            bytes_to_edge = summary.bytes_to_edge,
+            end = ?summary.end,
+            "stream finished"
+        );
+        if let Some(hook) = &self.on_stream_complete {
+            hook(summary);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1,21 +1,161 @@
 //! Bidirectional byte pumping and websocket handshake helpers.
 
+use std::time::Duration;
+
+#[cfg(edge_conn)]
 use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(edge_conn)]
+use tokio::time::Instant;
 
-use crate::error::Result;
+use crate::origin::context::RequestContext;
+use crate::origin::keepalive::WebSocketKeepalive;
+#[cfg(edge_conn)]
+use crate::origin::keepalive::{Keepalive, Tick};
+#[cfg(edge_conn)]
 use crate::origin::stream::Stream;
 
+/// How the transports pump websocket and TCP streams for an origin.
+///
+/// Set per origin with
+/// [`Origin::with_stream_options`](crate::Origin::with_stream_options). The
+/// defaults pump until both directions end, however long that takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// Longest time no byte may move in either direction before the stream
+    /// is torn down.
+    pub idle_timeout: Option<Duration>,
+    /// Longest time a stream may live, busy or not.
+    pub max_lifetime: Option<Duration>,
+    /// Whether one direction keeps pumping after the other ends (a TCP
+    /// half-close). When off, the first side to finish closes both.
+    pub half_close: bool,
+    /// Pings injected into websocket streams; TCP streams are never
+    /// pinged.
+    pub websocket_keepalive: Option<WebSocketKeepalive>,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: None,
+            max_lifetime: None,
+            half_close: true,
+            websocket_keepalive: None,
+        }
+    }
+}
+
+/// Which kind of stream a [`StreamSummary`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    /// An upgraded websocket connection.
+    WebSocket,
+    /// A raw TCP stream.
+    Tcp,
+}
+
+/// One side of a pumped stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamSide {
+    /// The Cloudflare edge, i.e. the visitor.
+    Edge,
+    /// The origin handler's stream.
+    Origin,
+}
+
+/// Why a pumped stream ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEnd {
+    /// Both directions ended (or one did, without
+    /// [`half_close`](StreamOptions::half_close)).
+    Closed,
+    /// No byte moved for [`idle_timeout`](StreamOptions::idle_timeout).
+    IdleTimeout,
+    /// The stream outlived [`max_lifetime`](StreamOptions::max_lifetime).
+    LifetimeExceeded,
+    /// A [`websocket_keepalive`](StreamOptions::websocket_keepalive) ping
+    /// went unanswered.
+    KeepaliveTimeout,
+}
+
+/// What happened on one websocket or TCP stream, handed to the hook set
+/// with [`Origin::on_stream_complete`](crate::Origin::on_stream_complete)
+/// once the stream is done.
+#[derive(Debug, Clone)]
+pub struct StreamSummary {
+    /// The kind of stream.
+    pub kind: StreamKind,
+    /// The request the stream belongs to.
+    pub context: Option<RequestContext>,
+    /// Bytes delivered from the edge to the origin.
+    pub bytes_from_edge: u64,
+    /// Bytes delivered from the origin to the edge.
+    pub bytes_to_edge: u64,
+    /// How long the stream was pumped.
+    pub duration: Duration,
+    /// The side whose data ended first, if either did before the stream
+    /// was torn down.
+    pub first_closed: Option<StreamSide>,
+    /// Why the stream ended.
+    pub end: StreamEnd,
+    /// The first I/O error either side hit, if any.
+    pub error: Option<String>,
+}
+
+impl StreamSummary {
+    /// An empty summary for a stream about to be pumped.
+    #[cfg(edge_conn)]
+    pub(crate) fn new(kind: StreamKind, context: Option<RequestContext>) -> Self {
+        Self {
+            kind,
+            context,
+            bytes_from_edge: 0,
+            bytes_to_edge: 0,
+            duration: Duration::ZERO,
+            first_closed: None,
+            end: StreamEnd::Closed,
+            error: None,
+        }
+    }
+
+    /// Records that `side` finished, keeping the first side and the first
+    /// error.
+    #[cfg(edge_conn)]
+    fn finished(&mut self, side: StreamSide, error: Option<String>) {
+        self.first_closed.get_or_insert(side);
+        if let Some(error) = error {
+            tracing::debug!("{error}");
+            self.error.get_or_insert(error);
+        }
+    }
+
+    /// Whether the stream was torn down by a timeout rather than closed.
+    #[cfg(quic_any)]
+    pub(crate) fn timed_out(&self) -> bool {
+        self.end != StreamEnd::Closed
+    }
+}
+
 /// Pumps bytes in both directions between an origin stream and the edge
-/// stream until both directions reach the end.
+/// stream until both directions reach the end, or until `options` tears the
+/// stream down, and fills in `summary`.
 ///
 /// Mirrors cloudflared's `PipeBidirectional`: each direction closes only
 /// its own destination write side when the source ends, and the other
-/// direction keeps pumping until it ends as well.
-#[cfg_attr(not(edge_conn), allow(dead_code))]
-pub(crate) async fn pump<R, W>(origin: Stream, edge_read: R, edge_write: W) -> Result<()>
+/// direction keeps pumping until it ends as well. Writes count as activity
+/// only once they complete, so a peer that stops reading trips the idle
+/// timeout too.
+#[cfg(edge_conn)]
+pub(crate) async fn pump<R, W>(
+    origin: Stream,
+    edge_read: R,
+    edge_write: W,
+    options: &StreamOptions,
+    mut summary: StreamSummary,
+) -> StreamSummary
 where
     R: AsyncRead + Unpin,
-    W: AsyncWrite + Unpin,
+    W: AsyncWrite + Unpin + Send,
 {
     let (mut origin_read, mut origin_write) = origin.into_parts();
     let mut edge_read = edge_read;
@@ -24,52 +164,221 @@ where
     let mut origin_done = false;
     let mut edge_buffer = [0u8; 8192];
     let mut origin_buffer = [0u8; 8192];
-    loop {
-        if edge_done && origin_done {
-            break;
-        }
-        tokio::select! {
-            read = edge_read.read(&mut edge_buffer), if !edge_done => {
-                match read {
-                    Ok(0) => {
-                        edge_done = true;
-                        let _ = origin_write.close().await;
-                    }
-                    Ok(n) => {
-                        if let Err(e) = origin_write.write_all(&edge_buffer[..n]).await {
-                            tracing::debug!("origin write failed: {e}");
+    let started = Instant::now();
+    let expires = options.max_lifetime.map(|lifetime| started + lifetime);
+    let mut active = started;
+    let mut keepalive = options
+        .websocket_keepalive
+        .as_ref()
+        .filter(|_| summary.kind == StreamKind::WebSocket)
+        .map(|settings| Keepalive::new(settings, started));
+    while !(edge_done && origin_done) {
+        let idle = options.idle_timeout.map(|timeout| active + timeout);
+        let deadline = earliest(idle, expires);
+        let wake = earliest(deadline, keepalive.as_ref().map(Keepalive::deadline));
+        let step = tokio::select! {
+            read = edge_read.read(&mut edge_buffer), if !edge_done => match read {
+                Ok(0) => {
+                    edge_done = true;
+                    summary.finished(StreamSide::Edge, None);
+                    let _ = origin_write.close().await;
+                    Step::Ended
+                }
+                Ok(n) => {
+                    let chunk = &edge_buffer[..n];
+                    let relayed = relay(chunk, &mut origin_write, keepalive.as_mut(), StreamSide::Origin);
+                    match within(deadline, relayed).await {
+                        Some(Ok(())) => {
+                            summary.bytes_from_edge += n as u64;
+                            Step::Moved
+                        }
+                        Some(Err(e)) => {
                             edge_done = true;
+                            summary.finished(StreamSide::Origin, Some(format!("origin write failed: {e}")));
+                            Step::Ended
                         }
-                    }
-                    Err(e) => {
-                        tracing::debug!("edge read failed: {e}");
-                        edge_done = true;
+                        None => Step::Expired,
                     }
                 }
-            }
-            read = origin_read.read(&mut origin_buffer), if !origin_done => {
-                match read {
-                    Ok(0) => {
-                        origin_done = true;
-                        let _ = edge_write.close().await;
-                    }
-                    Ok(n) => {
-                        if let Err(e) = edge_write.write_all(&origin_buffer[..n]).await {
-                            tracing::debug!("edge write failed: {e}");
+                Err(e) => {
+                    edge_done = true;
+                    summary.finished(StreamSide::Edge, Some(format!("edge read failed: {e}")));
+                    Step::Ended
+                }
+            },
+            read = origin_read.read(&mut origin_buffer), if !origin_done => match read {
+                Ok(0) => {
+                    origin_done = true;
+                    summary.finished(StreamSide::Origin, None);
+                    let _ = edge_write.close().await;
+                    Step::Ended
+                }
+                Ok(n) => {
+                    let chunk = &origin_buffer[..n];
+                    let relayed = relay(chunk, &mut edge_write, keepalive.as_mut(), StreamSide::Edge);
+                    match within(deadline, relayed).await {
+                        Some(Ok(())) => {
+                            summary.bytes_to_edge += n as u64;
+                            Step::Moved
+                        }
+                        Some(Err(e)) => {
                             origin_done = true;
+                            summary.finished(StreamSide::Edge, Some(format!("edge write failed: {e}")));
+                            Step::Ended
                         }
+                        None => Step::Expired,
                     }
-                    Err(e) => {
-                        tracing::debug!("origin read failed: {e}");
-                        origin_done = true;
+                }
+                Err(e) => {
+                    origin_done = true;
+                    summary.finished(StreamSide::Origin, Some(format!("origin read failed: {e}")));
+                    Step::Ended
+                }
+            },
+            () = sleep_until(wake) => {
+                let now = Instant::now();
+                match keepalive.as_mut().map(|keepalive| keepalive.tick(now)) {
+                    _ if deadline.is_some_and(|deadline| deadline <= now) => Step::Expired,
+                    Some(Tick::Dead) => Step::Unanswered,
+                    Some(Tick::Ping) => {
+                        let keepalive = keepalive.as_mut().expect("ticked");
+                        let target = keepalive.target();
+                        // The pinged side's write half is closed once the
+                        // stream flowing toward it has ended.
+                        let write: Option<&mut (dyn AsyncWrite + Unpin + Send)> = match target {
+                            StreamSide::Edge if !origin_done => Some(&mut edge_write),
+                            StreamSide::Origin if !edge_done => Some(&mut origin_write),
+                            _ => None,
+                        };
+                        match write {
+                            Some(write) => match within(deadline, write.write_all(&keepalive.ping(now))).await {
+                                Some(Ok(())) => Step::Waited,
+                                Some(Err(e)) => {
+                                    let side = match target {
+                                        StreamSide::Edge => {
+                                            origin_done = true;
+                                            "edge"
+                                        }
+                                        StreamSide::Origin => {
+                                            edge_done = true;
+                                            "origin"
+                                        }
+                                    };
+                                    summary.finished(target, Some(format!("{side} write failed: {e}")));
+                                    Step::Ended
+                                }
+                                None => Step::Expired,
+                            },
+                            None => Step::Waited,
+                        }
                     }
+                    _ => Step::Waited,
                 }
+            },
+        };
+        match step {
+            Step::Moved => active = Instant::now(),
+            Step::Waited => {}
+            // A deadline passed, in the sleep or during a stalled write.
+            Step::Expired | Step::Unanswered => {
+                summary.end = if step == Step::Unanswered {
+                    StreamEnd::KeepaliveTimeout
+                } else if expires.is_some_and(|expires| expires <= Instant::now()) {
+                    StreamEnd::LifetimeExceeded
+                } else {
+                    StreamEnd::IdleTimeout
+                };
+                tracing::debug!(end = ?summary.end, "stream torn down");
+                break;
+            }
+            Step::Ended if !(options.half_close || edge_done && origin_done) => {
+                edge_done = true;
+                origin_done = true;
+                let _ = origin_write.close().await;
+                let _ = edge_write.close().await;
             }
+            Step::Ended => {}
+        }
+    }
+    summary.duration = started.elapsed();
+    summary
+}
+
+/// The outcome of one turn of the pump loop.
+#[cfg(edge_conn)]
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    /// Bytes were relayed.
+    Moved,
+    /// A direction ended.
+    Ended,
+    /// A timer fired with nothing to tear down.
+    Waited,
+    /// The idle timeout or the lifetime passed.
+    Expired,
+    /// A keepalive ping went unanswered.
+    Unanswered,
+}
+
+/// Writes `chunk` toward `toward`, injecting a due keepalive ping after the
+/// first frame that ends in it, or scanning it for pongs when it comes from
+/// the pinged side.
+#[cfg(edge_conn)]
+async fn relay<W>(
+    chunk: &[u8],
+    write: &mut W,
+    keepalive: Option<&mut Keepalive>,
+    toward: StreamSide,
+) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let Some(keepalive) = keepalive else {
+        return write.write_all(chunk).await;
+    };
+    if keepalive.target() != toward {
+        keepalive.inbound(chunk);
+        return write.write_all(chunk).await;
+    }
+    let mut rest = chunk;
+    while !rest.is_empty() {
+        let (used, ended) = keepalive.outbound(rest);
+        write.write_all(&rest[..used]).await?;
+        rest = &rest[used..];
+        if ended && keepalive.due() {
+            write.write_all(&keepalive.ping(Instant::now())).await?;
         }
     }
     Ok(())
 }
 
+/// The earlier of two optional deadlines.
+#[cfg(edge_conn)]
+fn earliest(first: Option<Instant>, second: Option<Instant>) -> Option<Instant> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some(first.min(second)),
+        (first, second) => first.or(second),
+    }
+}
+
+/// Runs `future` unless `deadline` passes first.
+#[cfg(edge_conn)]
+async fn within<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// Sleeps until `deadline`, or forever without one.
+#[cfg(edge_conn)]
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
 /// Computes the RFC 6455 `Sec-WebSocket-Accept` value for a challenge key.
 ///
 /// Consumers implementing [`StreamOrigin<WebSocketResponder>`](crate::StreamOrigin) use
@@ -83,3 +392,117 @@ pub fn websocket_accept(challenge_key: &str) -> String {
     hasher.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
     base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
 }
+
+#[cfg(all(test, edge_conn))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    #[tokio::test]
+    async fn summarizes_a_half_closed_stream() {
+        let (mut app, origin) = tokio::io::duplex(1024);
+        let (mut visitor, edge) = tokio::io::duplex(1024);
+        let (edge_read, edge_write) = edge.compat().split();
+        let origin = Stream::from_io(origin.compat());
+        tokio::spawn(async move {
+            visitor.write_all(b"hello").await.unwrap();
+            visitor.shutdown().await.unwrap();
+            let mut echoed = Vec::new();
+            visitor.read_to_end(&mut echoed).await.unwrap();
+        });
+        tokio::spawn(async move {
+            let mut received = Vec::new();
+            app.read_to_end(&mut received).await.unwrap();
+            app.write_all(b"world!").await.unwrap();
+            app.shutdown().await.unwrap();
+        });
+
+        let summary = pump(
+            origin,
+            edge_read,
+            edge_write,
+            &StreamOptions::default(),
+            StreamSummary::new(StreamKind::Tcp, None),
+        )
+        .await;
+        assert_eq!(summary.bytes_from_edge, 5);
+        assert_eq!(summary.bytes_to_edge, 6);
+        assert_eq!(summary.first_closed, Some(StreamSide::Edge));
+        assert_eq!(summary.end, StreamEnd::Closed);
+        assert!(summary.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn tears_down_an_idle_stream() {
+        let (_app, origin) = tokio::io::duplex(1024);
+        let (_visitor, edge) = tokio::io::duplex(1024);
+        let (edge_read, edge_write) = edge.compat().split();
+        let options = StreamOptions {
+            idle_timeout: Some(Duration::from_millis(20)),
+            max_lifetime: Some(Duration::from_secs(60)),
+            ..StreamOptions::default()
+        };
+        let summary = pump(
+            Stream::from_io(origin.compat()),
+            edge_read,
+            edge_write,
+            &options,
+            StreamSummary::new(StreamKind::WebSocket, None),
+        )
+        .await;
+        assert_eq!(summary.end, StreamEnd::IdleTimeout);
+        assert_eq!(summary.first_closed, None);
+        #[cfg(quic_any)]
+        assert!(summary.timed_out());
+    }
+
+    #[tokio::test]
+    async fn closes_both_sides_without_half_close() {
+        let (_app, origin) = tokio::io::duplex(1024);
+        let (mut visitor, edge) = tokio::io::duplex(1024);
+        let (edge_read, edge_write) = edge.compat().split();
+        visitor.shutdown().await.unwrap();
+        let options = StreamOptions {
+            half_close: false,
+            ..StreamOptions::default()
+        };
+        let summary = pump(
+            Stream::from_io(origin.compat()),
+            edge_read,
+            edge_write,
+            &options,
+            StreamSummary::new(StreamKind::Tcp, None),
+        )
+        .await;
+        assert_eq!(summary.first_closed, Some(StreamSide::Edge));
+        assert_eq!(summary.end, StreamEnd::Closed);
+    }
+
+    #[tokio::test]
+    async fn pings_the_visitor_and_drops_it_without_a_pong() {
+        let (_app, origin) = tokio::io::duplex(1024);
+        let (mut visitor, edge) = tokio::io::duplex(1024);
+        let (edge_read, edge_write) = edge.compat().split();
+        let options = StreamOptions {
+            websocket_keepalive: Some(WebSocketKeepalive {
+                interval: Duration::from_millis(10),
+                timeout: Duration::from_millis(20),
+                ..WebSocketKeepalive::default()
+            }),
+            ..StreamOptions::default()
+        };
+        let summary = pump(
+            Stream::from_io(origin.compat()),
+            edge_read,
+            edge_write,
+            &options,
+            StreamSummary::new(StreamKind::WebSocket, None),
+        )
+        .await;
+        assert_eq!(summary.end, StreamEnd::KeepaliveTimeout);
+        let mut ping = [0u8; 2];
+        visitor.read_exact(&mut ping).await.unwrap();
+        assert_eq!(ping, [0x89, 0x00]);
+    }
+}
@@ -3,6 +3,10 @@
 #[cfg(edge_conn)]
 use tokio::sync::oneshot;
 
+#[cfg(edge_conn)]
+use crate::origin::cancel::CancellationToken;
+#[cfg(edge_conn)]
+use crate::origin::error::OriginError;
 #[cfg(edge_conn)]
 use crate::origin::http::body::Response;
 #[cfg(edge_conn)]
@@ -12,35 +16,68 @@ use crate::origin::stream::{Stream, WebSocketConnection};
 ///
 /// Transports hand an `HttpResponder` to every
 /// [`HttpOrigin`](crate::HttpOrigin) call. `send` delivers the
-/// [`Response`] to the edge; `fail` writes the message back as an error
-/// response. Handlers that need to await origin I/O may move the responder
-/// into a spawned task and respond when the work completes. The responder
+/// [`Response`] to the edge; `fail` reports an
+/// [`OriginError`](crate::OriginError) that becomes an error response.
+/// Handlers that need to await origin I/O may move the responder into a
+/// spawned task and respond when the work completes. The responder
 /// is consumed by responding; dropping it without responding surfaces as a
 /// "handler produced no response" error to the edge.
 pub struct HttpResponder {
     #[cfg(edge_conn)]
-    tx: oneshot::Sender<Result<Response, String>>,
+    tx: oneshot::Sender<Result<Response, OriginError>>,
+    /// Rewrites the response on `send`, installed by wrapping origins.
+    #[cfg(edge_conn)]
+    transform: Option<ResponseTransform>,
 }
 
+#[cfg(edge_conn)]
+type ResponseTransform = Box<dyn FnOnce(Response) -> Response + Send>;
+
 impl HttpResponder {
     /// Creates the per-request responder and receiver pair for a transport.
     #[cfg(edge_conn)]
-    pub(crate) fn channel() -> (Self, oneshot::Receiver<Result<Response, String>>) {
+    pub(crate) fn channel() -> (Self, oneshot::Receiver<Result<Response, OriginError>>) {
         let (tx, rx) = oneshot::channel();
-        (Self { tx }, rx)
+        (
+            Self {
+                tx,
+                transform: None,
+            },
+            rx,
+        )
+    }
+
+    /// Applies `transform` to the response the handler sends, after any
+    /// transform installed earlier. Failures pass through untouched.
+    #[cfg(edge_conn)]
+    #[cfg_attr(not(compression_any), allow(dead_code))]
+    pub(crate) fn map_response<F>(mut self, transform: F) -> Self
+    where
+        F: FnOnce(Response) -> Response + Send + 'static,
+    {
+        self.transform = Some(match self.transform.take() {
+            Some(earlier) => Box::new(move |response| transform(earlier(response))),
+            None => Box::new(transform),
+        });
+        self
     }
 
     /// Sends the HTTP response to the edge.
     #[cfg(edge_conn)]
-    pub fn send(self, response: Response) {
+    pub fn send(mut self, response: Response) {
+        let response = match self.transform.take() {
+            Some(transform) => transform(response),
+            None => response,
+        };
         let _ = self.tx.send(Ok(response));
     }
 
-    /// Fails the request; the message is sent back to the edge as an error
-    /// response.
+    /// Fails the request. A plain message surfaces as the edge's `502`
+    /// error page; an [`OriginError`](crate::OriginError) with its own
+    /// status, body or retry hint is sent back as a regular response.
     #[cfg(edge_conn)]
-    pub fn fail(self, message: impl Into<String>) {
-        let _ = self.tx.send(Err(message.into()));
+    pub fn fail(self, error: impl Into<OriginError>) {
+        let _ = self.tx.send(Err(error.into()));
     }
 }
 
@@ -49,17 +86,20 @@ impl HttpResponder {
 /// Transports hand a `WebSocketResponder` to every
 /// [`StreamOrigin<WebSocketResponder>`](crate::StreamOrigin) call.
 /// `upgrade` delivers the [`WebSocketConnection`] (the 101 response headers
-/// plus the origin byte stream) to the edge; `fail` writes the message back
-/// as an error response.
+/// plus the origin byte stream) to the edge; `fail` reports an
+/// [`OriginError`](crate::OriginError) that becomes an error response.
 pub struct WebSocketResponder {
     #[cfg(edge_conn)]
-    tx: oneshot::Sender<Result<WebSocketConnection, String>>,
+    tx: oneshot::Sender<Result<WebSocketConnection, OriginError>>,
 }
 
 impl WebSocketResponder {
     /// Creates the per-request responder and receiver pair for a transport.
     #[cfg(edge_conn)]
-    pub(crate) fn channel() -> (Self, oneshot::Receiver<Result<WebSocketConnection, String>>) {
+    pub(crate) fn channel() -> (
+        Self,
+        oneshot::Receiver<Result<WebSocketConnection, OriginError>>,
+    ) {
         let (tx, rx) = oneshot::channel();
         (Self { tx }, rx)
     }
@@ -71,11 +111,12 @@ impl WebSocketResponder {
         let _ = self.tx.send(Ok(connection));
     }
 
-    /// Fails the request; the message is sent back to the edge as an error
-    /// response.
+    /// Fails the request. A plain message surfaces as the edge's `502`
+    /// error page; an [`OriginError`](crate::OriginError) with its own
+    /// status, body or retry hint is sent back as a regular response.
     #[cfg(edge_conn)]
-    pub fn fail(self, message: impl Into<String>) {
-        let _ = self.tx.send(Err(message.into()));
+    pub fn fail(self, error: impl Into<OriginError>) {
+        let _ = self.tx.send(Err(error.into()));
     }
 }
 
@@ -85,16 +126,17 @@ impl WebSocketResponder {
 /// [`StreamOrigin<TcpResponder>`](crate::StreamOrigin) call. `stream`
 /// delivers the byte stream to pump with the edge; the transport owns the
 /// proxy acknowledgement (a bare ack over QUIC, a synthesized 101 over
-/// HTTP/2). `fail` writes the message back as an error response.
+/// HTTP/2). `fail` reports the failure; TCP streams carry no HTTP response,
+/// so only the [`OriginError`](crate::OriginError) message reaches the edge.
 pub struct TcpResponder {
     #[cfg(edge_conn)]
-    tx: oneshot::Sender<Result<Stream, String>>,
+    tx: oneshot::Sender<Result<Stream, OriginError>>,
 }
 
 impl TcpResponder {
     /// Creates the per-request responder and receiver pair for a transport.
     #[cfg(edge_conn)]
-    pub(crate) fn channel() -> (Self, oneshot::Receiver<Result<Stream, String>>) {
+    pub(crate) fn channel() -> (Self, oneshot::Receiver<Result<Stream, OriginError>>) {
         let (tx, rx) = oneshot::channel();
         (Self { tx }, rx)
     }
@@ -105,11 +147,10 @@ impl TcpResponder {
         let _ = self.tx.send(Ok(stream));
     }
 
-    /// Fails the request; the message is sent back to the edge as an error
-    /// response.
+    /// Fails the stream; the error message is sent back to the edge.
     #[cfg(edge_conn)]
-    pub fn fail(self, message: impl Into<String>) {
-        let _ = self.tx.send(Err(message.into()));
+    pub fn fail(self, error: impl Into<OriginError>) {
+        let _ = self.tx.send(Err(error.into()));
     }
 }
 
@@ -121,15 +162,33 @@ impl StreamResponder for WebSocketResponder {}
 impl StreamResponder for TcpResponder {}
 
 /// Waits for the origin handler's outcome, folding handler failures and a
-/// dropped responder into an error message.
+/// dropped responder into an [`OriginError`].
 #[cfg(edge_conn)]
 pub(crate) async fn wait_outcome<T>(
-    receiver: oneshot::Receiver<Result<T, String>>,
-) -> Result<T, String> {
+    receiver: oneshot::Receiver<Result<T, OriginError>>,
+) -> Result<T, OriginError> {
     match receiver.await {
         Ok(Ok(value)) => Ok(value),
-        Ok(Err(message)) => Err(message),
-        Err(_) => Err("origin handler produced no response".to_string()),
+        Ok(Err(error)) => Err(error),
+        Err(_) => Err(OriginError::no_response()),
+    }
+}
+
+/// Waits for the handler's outcome unless `abandoned` resolves first (the
+/// edge reset the stream or the connection dropped); then fires
+/// `cancellation` and returns `None`.
+#[cfg(edge_conn)]
+pub(crate) async fn wait_outcome_unless<T>(
+    receiver: oneshot::Receiver<Result<T, OriginError>>,
+    abandoned: impl std::future::Future<Output = ()>,
+    cancellation: &CancellationToken,
+) -> Option<Result<T, OriginError>> {
+    tokio::select! {
+        outcome = wait_outcome(receiver) => Some(outcome),
+        () = abandoned => {
+            cancellation.cancel();
+            None
+        }
     }
 }
 
@@ -143,7 +202,7 @@ mod tests {
         let (responder, receiver) = HttpResponder::channel();
         drop(responder);
         assert_eq!(
-            wait_outcome(receiver).await.unwrap_err(),
+            wait_outcome(receiver).await.unwrap_err().message(),
             "origin handler produced no response"
         );
     }
@@ -152,7 +211,18 @@ mod tests {
     async fn failed_http_responder_surfaces_handler_message() {
         let (responder, receiver) = HttpResponder::channel();
         responder.fail("origin down");
-        assert_eq!(wait_outcome(receiver).await.unwrap_err(), "origin down");
+        assert_eq!(
+            wait_outcome(receiver).await.unwrap_err().message(),
+            "origin down"
+        );
+    }
+
+    #[tokio::test]
+    async fn structured_failure_keeps_its_status() {
+        let (responder, receiver) = HttpResponder::channel();
+        responder.fail(OriginError::gateway_timeout("report took too long"));
+        let error = wait_outcome(receiver).await.unwrap_err();
+        assert_eq!(error.status(), http::StatusCode::GATEWAY_TIMEOUT);
     }
 
     #[tokio::test]
@@ -161,7 +231,7 @@ mod tests {
         drop(responder);
         let outcome = wait_outcome(receiver).await;
         match outcome {
-            Err(message) => assert_eq!(message, "origin handler produced no response"),
+            Err(error) => assert_eq!(error.message(), "origin handler produced no response"),
             Ok(_) => panic!("expected a dropped-responder error"),
         }
     }
@@ -172,11 +242,20 @@ mod tests {
         drop(responder);
         let outcome = wait_outcome(receiver).await;
         match outcome {
-            Err(message) => assert_eq!(message, "origin handler produced no response"),
+            Err(error) => assert_eq!(error.message(), "origin handler produced no response"),
             Ok(_) => panic!("expected a dropped-responder error"),
         }
     }
 
+    #[tokio::test]
+    async fn abandoned_stream_cancels_the_handler() {
+        let (_responder, receiver) = HttpResponder::channel();
+        let cancellation = CancellationToken::new();
+        let outcome = wait_outcome_unless(receiver, async {}, &cancellation).await;
+        assert!(outcome.is_none());
+        assert!(cancellation.is_cancelled());
+    }
+
     #[tokio::test]
     async fn response_surfaces_through_http_responder() {
         let (responder, receiver) = HttpResponder::channel();
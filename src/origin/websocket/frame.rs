@@ -0,0 +1,153 @@
+//! RFC 6455 frame encoding and decoding for the server side of a
+//! websocket: frames from the client must be masked, frames to it are not.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::{CloseFrame, WebSocketError};
+
+pub(super) const OPCODE_CONTINUATION: u8 = 0x0;
+pub(super) const OPCODE_TEXT: u8 = 0x1;
+pub(super) const OPCODE_BINARY: u8 = 0x2;
+pub(super) const OPCODE_CLOSE: u8 = 0x8;
+pub(super) const OPCODE_PING: u8 = 0x9;
+pub(super) const OPCODE_PONG: u8 = 0xA;
+
+/// Control frames carry at most this many payload bytes.
+pub(super) const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// One decoded frame, already unmasked.
+pub(super) struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Bytes,
+}
+
+/// Decodes the next frame from `buffer`, or `None` until it holds a whole
+/// frame. Frames longer than `max_payload` are rejected from the header
+/// alone, before their payload is buffered.
+pub(super) fn decode(
+    buffer: &mut BytesMut,
+    max_payload: usize,
+) -> Result<Option<Frame>, WebSocketError> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+    let (first, second) = (buffer[0], buffer[1]);
+    if first & 0x70 != 0 {
+        // No extension is ever negotiated, so no reserved bit may be set.
+        return Err(WebSocketError::Protocol("reserved bits are set"));
+    }
+    let fin = first & 0x80 != 0;
+    let opcode = first & 0x0F;
+    if second & 0x80 == 0 {
+        return Err(WebSocketError::Protocol("client frame is not masked"));
+    }
+    let (length, header) = match second & 0x7F {
+        126 => {
+            if buffer.len() < 4 {
+                return Ok(None);
+            }
+            (u64::from(u16::from_be_bytes([buffer[2], buffer[3]])), 4)
+        }
+        127 => {
+            if buffer.len() < 10 {
+                return Ok(None);
+            }
+            let length = u64::from_be_bytes(buffer[2..10].try_into().expect("eight bytes"));
+            if length >> 63 != 0 {
+                return Err(WebSocketError::Protocol("frame length has its top bit set"));
+            }
+            (length, 10)
+        }
+        length => (u64::from(length), 2),
+    };
+    if opcode >= OPCODE_CLOSE {
+        if !fin {
+            return Err(WebSocketError::Protocol("control frame is fragmented"));
+        }
+        if length > MAX_CONTROL_PAYLOAD as u64 {
+            return Err(WebSocketError::Protocol(
+                "control frame payload is too long",
+            ));
+        }
+    }
+    if length > max_payload as u64 {
+        return Err(WebSocketError::TooLarge { limit: max_payload });
+    }
+    let length = length as usize;
+    let total = header + 4 + length;
+    if buffer.len() < total {
+        buffer.reserve(total - buffer.len());
+        return Ok(None);
+    }
+    let mask: [u8; 4] = buffer[header..header + 4].try_into().expect("four bytes");
+    let mut payload = buffer.split_to(total);
+    payload.advance(header + 4);
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload: payload.freeze(),
+    }))
+}
+
+/// Appends an unmasked, unfragmented frame to `buffer`.
+pub(super) fn encode(buffer: &mut BytesMut, opcode: u8, payload: &[u8]) {
+    buffer.reserve(payload.len() + 10);
+    buffer.put_u8(0x80 | opcode);
+    match payload.len() {
+        length @ 0..=125 => buffer.put_u8(length as u8),
+        length @ 126..=0xFFFF => {
+            buffer.put_u8(126);
+            buffer.put_u16(length as u16);
+        }
+        length => {
+            buffer.put_u8(127);
+            buffer.put_u64(length as u64);
+        }
+    }
+    buffer.put_slice(payload);
+}
+
+/// Parses a close frame payload: empty, or a status code and a UTF-8
+/// reason.
+pub(super) fn decode_close(payload: &[u8]) -> Result<Option<CloseFrame>, WebSocketError> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err(WebSocketError::Protocol(
+            "close payload has a truncated code",
+        )),
+        [high, low, reason @ ..] => {
+            let code = u16::from_be_bytes([*high, *low]);
+            if !valid_close_code(code) {
+                return Err(WebSocketError::Protocol("close code is not allowed"));
+            }
+            let reason = std::str::from_utf8(reason).map_err(|_| WebSocketError::InvalidUtf8)?;
+            Ok(Some(CloseFrame {
+                code,
+                reason: reason.to_string(),
+            }))
+        }
+    }
+}
+
+/// The payload of a close frame.
+pub(super) fn encode_close(frame: Option<&CloseFrame>) -> Result<Vec<u8>, WebSocketError> {
+    let Some(frame) = frame else {
+        return Ok(Vec::new());
+    };
+    if frame.reason.len() > MAX_CONTROL_PAYLOAD - 2 {
+        return Err(WebSocketError::Protocol("close reason is too long"));
+    }
+    let mut payload = Vec::with_capacity(2 + frame.reason.len());
+    payload.extend_from_slice(&frame.code.to_be_bytes());
+    payload.extend_from_slice(frame.reason.as_bytes());
+    Ok(payload)
+}
+
+/// Codes a peer may send in a close frame (RFC 6455 section 7.4).
+fn valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
@@ -0,0 +1,775 @@
+//! A message-level websocket for
+//! [`StreamOrigin<WebSocketResponder>`](crate::StreamOrigin) handlers.
+//!
+//! [`WebSocketUpgrade`] answers the handshake from the [`Request`] and
+//! [`accept`](WebSocketUpgrade::accept) returns both halves of the deal: the
+//! [`WebSocketConnection`] to hand to
+//! [`WebSocketResponder::upgrade`](crate::WebSocketResponder::upgrade), and a
+//! [`WebSocket`] that speaks RFC 6455 messages over it:
+//!
+//! ```ignore
+//! fn connect(&self, request: Request, respond: WebSocketResponder) {
+//!     let upgrade = match WebSocketUpgrade::from_request(&request) {
+//!         Ok(upgrade) => upgrade,
+//!         Err(error) => return respond.fail(error),
+//!     };
+//!     let (connection, mut socket) = upgrade.accept();
+//!     respond.upgrade(connection);
+//!     tokio::spawn(async move {
+//!         while let Some(Ok(message)) = socket.recv().await {
+//!             if let Message::Text(_) | Message::Binary(_) = message {
+//!                 let _ = socket.send(message).await;
+//!             }
+//!         }
+//!     });
+//! }
+//! ```
+//!
+//! The socket validates client framing (masking, reserved bits, control
+//! frame rules), reassembles fragmented messages, enforces the
+//! [`WebSocketConfig`] size limits, answers pings, and completes the close
+//! handshake. No extension (such as `permessage-deflate`) is negotiated.
+
+mod frame;
+#[cfg(edge_conn)]
+mod proxy;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use thiserror::Error;
+
+use self::frame::{
+    MAX_CONTROL_PAYLOAD, OPCODE_BINARY, OPCODE_CLOSE, OPCODE_CONTINUATION, OPCODE_PING,
+    OPCODE_PONG, OPCODE_TEXT,
+};
+use crate::origin::error::OriginError;
+use crate::origin::http::body::{Body, Request, Response};
+use crate::origin::pump::websocket_accept;
+use crate::origin::stream::{ReadHalf, Stream, WebSocketConnection, WriteHalf};
+
+#[cfg(edge_conn)]
+pub use self::proxy::WebSocketProxyOrigin;
+
+/// Bytes buffered between the socket and the transport in each direction.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Bytes read from the transport at a time.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Errors from a [`WebSocket`].
+#[derive(Debug, Error)]
+pub enum WebSocketError {
+    /// The peer broke the websocket protocol.
+    #[error("websocket protocol violation: {0}")]
+    Protocol(&'static str),
+    /// A frame or message was larger than the configured limit.
+    #[error("websocket message exceeds the {limit} byte limit")]
+    TooLarge {
+        /// The limit that was exceeded.
+        limit: usize,
+    },
+    /// A text message or close reason was not valid UTF-8.
+    #[error("websocket text is not valid UTF-8")]
+    InvalidUtf8,
+    /// The socket already sent its close frame.
+    #[error("websocket is closed")]
+    Closed,
+    /// The underlying stream failed.
+    #[error("websocket io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl WebSocketError {
+    /// The close code reported to the peer for this failure.
+    fn close_code(&self) -> Option<u16> {
+        match self {
+            Self::Protocol(_) => Some(1002),
+            Self::InvalidUtf8 => Some(1007),
+            Self::TooLarge { .. } => Some(1009),
+            Self::Closed | Self::Io(_) => None,
+        }
+    }
+}
+
+/// A websocket message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A UTF-8 text message.
+    Text(String),
+    /// A binary message.
+    Binary(Bytes),
+    /// A ping; the socket answers it with a pong on its own.
+    Ping(Bytes),
+    /// A pong.
+    Pong(Bytes),
+    /// A close frame. Receiving one ends the stream of messages; the socket
+    /// answers it with a close frame of its own.
+    Close(Option<CloseFrame>),
+}
+
+/// The status code and reason of a close frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// The close status code (`1000` for a normal closure).
+    pub code: u16,
+    /// A UTF-8 reason of at most 123 bytes.
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// A close frame with `code` and `reason`.
+    pub fn new(code: u16, reason: impl Into<String>) -> Self {
+        Self {
+            code,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Size limits a [`WebSocket`] enforces on what it receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebSocketConfig {
+    /// Largest single frame payload. Defaults to 16 MiB.
+    pub max_frame_size: usize,
+    /// Largest message after reassembling its fragments. Defaults to
+    /// 64 MiB.
+    pub max_message_size: usize,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 16 << 20,
+            max_message_size: 64 << 20,
+        }
+    }
+}
+
+/// A websocket handshake read from a [`Request`], waiting to be accepted.
+#[derive(Debug, Clone)]
+pub struct WebSocketUpgrade {
+    key: String,
+    protocols: Vec<String>,
+    extensions: Vec<String>,
+    protocol: Option<String>,
+    config: WebSocketConfig,
+}
+
+impl WebSocketUpgrade {
+    /// Reads the handshake headers. A missing `Sec-WebSocket-Key` or an
+    /// unsupported `Sec-WebSocket-Version` fails with a `400` to pass to
+    /// [`WebSocketResponder::fail`](crate::WebSocketResponder::fail).
+    pub fn from_request(request: &Request) -> Result<Self, OriginError> {
+        let header = |name: &str| {
+            request
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+        let Some(key) = header("sec-websocket-key").filter(|key| !key.is_empty()) else {
+            return Err(bad_handshake("missing Sec-WebSocket-Key"));
+        };
+        if let Some(version) = header("sec-websocket-version")
+            && version != "13"
+        {
+            return Err(bad_handshake("unsupported Sec-WebSocket-Version"));
+        }
+        let list = |name: &str| -> Vec<String> {
+            request
+                .headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        Ok(Self {
+            key: key.to_string(),
+            protocols: list("sec-websocket-protocol"),
+            extensions: list("sec-websocket-extensions"),
+            protocol: None,
+            config: WebSocketConfig::default(),
+        })
+    }
+
+    /// The subprotocols the client offered, in its order of preference.
+    pub fn protocols(&self) -> &[String] {
+        &self.protocols
+    }
+
+    /// The extensions the client offered, with their parameters. None is
+    /// accepted, so the client falls back to plain frames.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Selects `protocol` if the client offered it; otherwise no
+    /// subprotocol is confirmed.
+    pub fn with_protocol(mut self, protocol: &str) -> Self {
+        self.protocol = self
+            .protocols
+            .iter()
+            .find(|offered| offered.as_str() == protocol)
+            .cloned();
+        self
+    }
+
+    /// Sets the size limits of the accepted socket.
+    pub fn with_config(mut self, config: WebSocketConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Accepts the handshake: the `101` connection to pass to
+    /// [`WebSocketResponder::upgrade`](crate::WebSocketResponder::upgrade)
+    /// and the socket that exchanges messages over it.
+    pub fn accept(self) -> (WebSocketConnection, WebSocket) {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::UPGRADE,
+            http::HeaderValue::from_static("websocket"),
+        );
+        headers.insert(
+            http::header::CONNECTION,
+            http::HeaderValue::from_static("Upgrade"),
+        );
+        if let Ok(accept) = http::HeaderValue::from_str(&websocket_accept(&self.key)) {
+            headers.insert(http::header::SEC_WEBSOCKET_ACCEPT, accept);
+        }
+        if let Some(protocol) = &self.protocol
+            && let Ok(protocol) = http::HeaderValue::from_str(protocol)
+        {
+            headers.insert(http::header::SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        let (transport, socket) = tokio::io::duplex(PIPE_CAPACITY);
+        let connection = WebSocketConnection {
+            response: Response::new(
+                http::StatusCode::SWITCHING_PROTOCOLS,
+                headers,
+                Body::empty(),
+            ),
+            origin: Stream::from_io(Pipe(transport)),
+        };
+        let mut socket = WebSocket::from_stream(Stream::from_io(Pipe(socket)), self.config);
+        socket.protocol = self.protocol;
+        (connection, socket)
+    }
+}
+
+fn bad_handshake(message: &str) -> OriginError {
+    OriginError::new(
+        http::StatusCode::BAD_REQUEST,
+        format!("bad websocket handshake: {message}"),
+    )
+}
+
+/// The server side of a websocket connection, exchanging [`Message`]s.
+///
+/// `recv` and `send` are cancel-safe, so both may sit in a `select!`;
+/// [`split`](Self::split) gives them to separate tasks instead.
+pub struct WebSocket {
+    receiver: WebSocketReceiver,
+    sender: WebSocketSender,
+    protocol: Option<String>,
+}
+
+impl WebSocket {
+    /// Speaks the server side of the websocket protocol over a raw stream
+    /// whose handshake is already complete.
+    pub fn from_stream(stream: Stream, config: WebSocketConfig) -> Self {
+        let (read, write) = stream.into_parts();
+        let sender = WebSocketSender {
+            writer: Arc::new(tokio::sync::Mutex::new(Writer {
+                write,
+                pending: BytesMut::new(),
+                close_sent: false,
+            })),
+        };
+        Self {
+            receiver: WebSocketReceiver {
+                read: std::sync::Mutex::new(read),
+                buffer: BytesMut::new(),
+                fragments: None,
+                reply: None,
+                ready: None,
+                config,
+                sender: sender.clone(),
+                finished: false,
+            },
+            sender,
+            protocol: None,
+        }
+    }
+
+    /// The subprotocol confirmed in the handshake.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// The next message; `None` once the close handshake is complete or
+    /// the socket failed.
+    pub async fn recv(&mut self) -> Option<Result<Message, WebSocketError>> {
+        self.receiver.recv().await
+    }
+
+    /// Sends a message; see [`WebSocketSender::send`].
+    pub async fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        self.sender.send(message).await
+    }
+
+    /// Starts the close handshake; see [`WebSocketSender::close`].
+    pub async fn close(&self, frame: Option<CloseFrame>) -> Result<(), WebSocketError> {
+        self.sender.close(frame).await
+    }
+
+    /// Splits the socket so sending and receiving run on separate tasks.
+    pub fn split(self) -> (WebSocketSender, WebSocketReceiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+/// The sending side of a [`WebSocket`]. Clones share the connection.
+#[derive(Clone)]
+pub struct WebSocketSender {
+    writer: Arc<tokio::sync::Mutex<Writer>>,
+}
+
+struct Writer {
+    write: WriteHalf,
+    /// Encoded frames not yet written; kept across cancelled sends so a
+    /// frame is never cut short.
+    pending: BytesMut,
+    close_sent: bool,
+}
+
+impl Writer {
+    async fn write_pending(&mut self) -> Result<(), WebSocketError> {
+        while !self.pending.is_empty() {
+            let written = self.write.write(&self.pending).await?;
+            if written == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+            }
+            self.pending.advance(written);
+        }
+        self.write.flush().await?;
+        Ok(())
+    }
+}
+
+impl WebSocketSender {
+    /// Sends a message as a single frame. Sending a [`Message::Close`]
+    /// starts the close handshake; nothing can be sent after it.
+    pub async fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        match message {
+            Message::Text(text) => self.write_frame(OPCODE_TEXT, text.as_bytes()).await,
+            Message::Binary(data) => self.write_frame(OPCODE_BINARY, &data).await,
+            Message::Ping(data) => self.write_control(OPCODE_PING, &data).await,
+            Message::Pong(data) => self.write_control(OPCODE_PONG, &data).await,
+            Message::Close(frame) => self.close(frame).await,
+        }
+    }
+
+    /// Sends a close frame unless one was already sent. The peer answers
+    /// with its own, which the receiving side reports as
+    /// [`Message::Close`].
+    pub async fn close(&self, frame: Option<CloseFrame>) -> Result<(), WebSocketError> {
+        let payload = frame::encode_close(frame.as_ref())?;
+        let mut writer = self.writer.lock().await;
+        if writer.close_sent {
+            return writer.write_pending().await;
+        }
+        writer.close_sent = true;
+        frame::encode(&mut writer.pending, OPCODE_CLOSE, &payload);
+        writer.write_pending().await
+    }
+
+    async fn write_control(&self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        if payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(WebSocketError::Protocol(
+                "control frame payload is too long",
+            ));
+        }
+        self.write_frame(opcode, payload).await
+    }
+
+    async fn write_frame(&self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        let mut writer = self.writer.lock().await;
+        if writer.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        frame::encode(&mut writer.pending, opcode, payload);
+        writer.write_pending().await
+    }
+}
+
+/// The receiving side of a [`WebSocket`]. Pings and close frames are still
+/// answered through the shared sending side.
+pub struct WebSocketReceiver {
+    /// Only ever reached through `&mut self`, never locked; the mutex makes
+    /// the receiver (and so [`WebSocket`]) `Sync` over a read half that is
+    /// only `Send`.
+    read: std::sync::Mutex<ReadHalf>,
+    buffer: BytesMut,
+    /// The opcode and data of a fragmented message being reassembled.
+    fragments: Option<(u8, BytesMut)>,
+    /// A pong or close frame owed to the peer, sent before `ready` is
+    /// returned; both survive a cancelled `recv`.
+    reply: Option<(u8, Bytes)>,
+    ready: Option<Message>,
+    config: WebSocketConfig,
+    sender: WebSocketSender,
+    finished: bool,
+}
+
+impl WebSocketReceiver {
+    /// The next message; see [`WebSocket::recv`].
+    pub async fn recv(&mut self) -> Option<Result<Message, WebSocketError>> {
+        loop {
+            if self.reply.is_some() || self.ready.is_some() {
+                match self.deliver().await {
+                    Ok(Some(message)) => return Some(Ok(message)),
+                    Ok(None) => {}
+                    Err(error) => return Some(Err(self.fail(error).await)),
+                }
+            }
+            if self.finished {
+                return None;
+            }
+            match frame::decode(&mut self.buffer, self.config.max_frame_size) {
+                Ok(Some(frame)) => {
+                    if let Err(error) = self.handle(frame) {
+                        return Some(Err(self.fail(error).await));
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(error) => return Some(Err(self.fail(error).await)),
+            }
+            let mut chunk = [0u8; READ_CHUNK];
+            let read = self
+                .read
+                .get_mut()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            match read.read(&mut chunk).await {
+                Ok(0) => {
+                    self.finished = true;
+                    return Some(Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "websocket ended without a close frame",
+                    )
+                    .into()));
+                }
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error.into()));
+                }
+            }
+        }
+    }
+
+    /// Applies one frame, queueing the message it completes and the reply
+    /// it needs.
+    fn handle(&mut self, frame: frame::Frame) -> Result<(), WebSocketError> {
+        match frame.opcode {
+            OPCODE_TEXT | OPCODE_BINARY => {
+                if self.fragments.is_some() {
+                    return Err(WebSocketError::Protocol(
+                        "new message before the previous one finished",
+                    ));
+                }
+                if frame.fin {
+                    self.ready = Some(message(frame.opcode, frame.payload)?);
+                } else {
+                    self.check_message_size(frame.payload.len())?;
+                    self.fragments = Some((frame.opcode, BytesMut::from(&frame.payload[..])));
+                }
+            }
+            OPCODE_CONTINUATION => {
+                let Some((opcode, mut data)) = self.fragments.take() else {
+                    return Err(WebSocketError::Protocol("continuation without a message"));
+                };
+                self.check_message_size(data.len() + frame.payload.len())?;
+                data.extend_from_slice(&frame.payload);
+                if frame.fin {
+                    self.ready = Some(message(opcode, data.freeze())?);
+                } else {
+                    self.fragments = Some((opcode, data));
+                }
+            }
+            OPCODE_PING => {
+                self.reply = Some((OPCODE_PONG, frame.payload.clone()));
+                self.ready = Some(Message::Ping(frame.payload));
+            }
+            OPCODE_PONG => self.ready = Some(Message::Pong(frame.payload)),
+            OPCODE_CLOSE => {
+                let close = frame::decode_close(&frame.payload)?;
+                // Echo the peer's code, as RFC 6455 recommends.
+                let echo = close
+                    .as_ref()
+                    .map(|close| CloseFrame::new(close.code, String::new()));
+                self.reply = Some((OPCODE_CLOSE, frame::encode_close(echo.as_ref())?.into()));
+                self.ready = Some(Message::Close(close));
+                self.finished = true;
+            }
+            _ => return Err(WebSocketError::Protocol("unknown opcode")),
+        }
+        Ok(())
+    }
+
+    /// Sends the queued reply, then hands out the queued message. A socket
+    /// that already sent its close frame answers nothing more.
+    async fn deliver(&mut self) -> Result<Option<Message>, WebSocketError> {
+        if self.reply.is_some() {
+            let mut writer = self.sender.writer.lock().await;
+            let (opcode, payload) = self.reply.take().expect("checked above");
+            if !writer.close_sent {
+                writer.close_sent = opcode == OPCODE_CLOSE;
+                frame::encode(&mut writer.pending, opcode, &payload);
+            }
+            writer.write_pending().await?;
+        }
+        Ok(self.ready.take())
+    }
+
+    fn check_message_size(&self, size: usize) -> Result<(), WebSocketError> {
+        if size > self.config.max_message_size {
+            return Err(WebSocketError::TooLarge {
+                limit: self.config.max_message_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Ends the socket after a failure, telling the peer why when the
+    /// failure is its fault.
+    async fn fail(&mut self, error: WebSocketError) -> WebSocketError {
+        self.finished = true;
+        self.reply = None;
+        self.ready = None;
+        if let Some(code) = error.close_code() {
+            let _ = self
+                .sender
+                .close(Some(CloseFrame::new(code, String::new())))
+                .await;
+        }
+        error
+    }
+}
+
+fn message(opcode: u8, data: Bytes) -> Result<Message, WebSocketError> {
+    if opcode == OPCODE_TEXT {
+        let text = String::from_utf8(data.into()).map_err(|_| WebSocketError::InvalidUtf8)?;
+        Ok(Message::Text(text))
+    } else {
+        Ok(Message::Binary(data))
+    }
+}
+
+/// A Tokio byte stream adapted to the `futures_io` traits: one end of the
+/// in-memory pipe between a [`WebSocket`] and the transport, or a proxied
+/// backend connection.
+struct Pipe<T>(T);
+
+impl<T: tokio::io::AsyncRead + Unpin> AsyncRead for Pipe<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut buffer = tokio::io::ReadBuf::new(buffer);
+        match tokio::io::AsyncRead::poll_read(Pin::new(&mut self.0), cx, &mut buffer) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(buffer.filled().len())),
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: tokio::io::AsyncWrite + Unpin> AsyncWrite for Pipe<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        tokio::io::AsyncWrite::poll_write(Pin::new(&mut self.0), cx, buffer)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        tokio::io::AsyncWrite::poll_flush(Pin::new(&mut self.0), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        tokio::io::AsyncWrite::poll_shutdown(Pin::new(&mut self.0), cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgrade_request(headers: &[(&'static str, &'static str)]) -> Request {
+        let mut map = http::HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, http::HeaderValue::from_static(value));
+        }
+        Request::new(
+            http::Method::GET,
+            http::Uri::from_static("http://example.com/socket"),
+            map,
+            Body::empty(),
+        )
+    }
+
+    /// A masked client frame.
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x11, 0x22, 0x33, 0x44];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            length @ 0..=125 => frame.push(0x80 | length as u8),
+            length => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        frame
+    }
+
+    async fn read_server_frame(stream: &mut Stream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[1] & 0x80, 0, "server frames are unmasked");
+        let mut payload = vec![0u8; usize::from(header[1] & 0x7F)];
+        stream.read_exact(&mut payload).await.unwrap();
+        (header[0] & 0x0F, payload)
+    }
+
+    #[test]
+    fn handshake_confirms_an_offered_protocol() {
+        let request = upgrade_request(&[
+            ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="),
+            ("sec-websocket-version", "13"),
+            ("sec-websocket-protocol", "chat, superchat"),
+            ("sec-websocket-extensions", "permessage-deflate"),
+        ]);
+        let upgrade = WebSocketUpgrade::from_request(&request).unwrap();
+        assert_eq!(upgrade.protocols(), ["chat", "superchat"]);
+        assert_eq!(upgrade.extensions(), ["permessage-deflate"]);
+        let (connection, socket) = upgrade.with_protocol("superchat").accept();
+        let headers = &connection.response.headers;
+        assert_eq!(
+            connection.response.status,
+            http::StatusCode::SWITCHING_PROTOCOLS
+        );
+        assert_eq!(
+            headers[http::header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(headers[http::header::SEC_WEBSOCKET_PROTOCOL], "superchat");
+        assert!(!headers.contains_key(http::header::SEC_WEBSOCKET_EXTENSIONS));
+        assert_eq!(socket.protocol(), Some("superchat"));
+
+        let missing_key = upgrade_request(&[("sec-websocket-version", "13")]);
+        let error = WebSocketUpgrade::from_request(&missing_key).unwrap_err();
+        assert_eq!(error.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn reassembles_fragments_and_answers_pings_and_close() {
+        let request = upgrade_request(&[("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")]);
+        let (connection, mut socket) = WebSocketUpgrade::from_request(&request).unwrap().accept();
+        let mut edge = connection.origin;
+
+        let mut frames = client_frame(false, OPCODE_TEXT, b"hel");
+        frames.extend(client_frame(true, OPCODE_PING, b"beat"));
+        frames.extend(client_frame(true, OPCODE_CONTINUATION, b"lo"));
+        frames.extend(client_frame(true, OPCODE_CLOSE, &1000u16.to_be_bytes()));
+        edge.write_all(&frames).await.unwrap();
+
+        assert_eq!(
+            socket.recv().await.unwrap().unwrap(),
+            Message::Ping(Bytes::from_static(b"beat"))
+        );
+        assert_eq!(
+            read_server_frame(&mut edge).await,
+            (OPCODE_PONG, b"beat".to_vec())
+        );
+        assert_eq!(
+            socket.recv().await.unwrap().unwrap(),
+            Message::Text("hello".into())
+        );
+        socket
+            .send(Message::Binary(Bytes::from_static(b"ok")))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_server_frame(&mut edge).await,
+            (OPCODE_BINARY, b"ok".to_vec())
+        );
+        assert_eq!(
+            socket.recv().await.unwrap().unwrap(),
+            Message::Close(Some(CloseFrame::new(1000, "")))
+        );
+        assert_eq!(
+            read_server_frame(&mut edge).await,
+            (OPCODE_CLOSE, 1000u16.to_be_bytes().to_vec())
+        );
+        assert!(socket.recv().await.is_none());
+        assert!(matches!(
+            socket.send(Message::Text("late".into())).await,
+            Err(WebSocketError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_unmasked_and_oversized_frames() {
+        let request = upgrade_request(&[("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")]);
+        let (connection, mut socket) = WebSocketUpgrade::from_request(&request).unwrap().accept();
+        let mut edge = connection.origin;
+        edge.write_all(&[0x81, 0x02, b'h', b'i']).await.unwrap();
+        assert!(matches!(
+            socket.recv().await.unwrap(),
+            Err(WebSocketError::Protocol(_))
+        ));
+        assert_eq!(
+            read_server_frame(&mut edge).await,
+            (OPCODE_CLOSE, 1002u16.to_be_bytes().to_vec())
+        );
+
+        let config = WebSocketConfig {
+            max_frame_size: 4,
+            ..Default::default()
+        };
+        let (connection, mut socket) = WebSocketUpgrade::from_request(&request)
+            .unwrap()
+            .with_config(config)
+            .accept();
+        let mut edge = connection.origin;
+        edge.write_all(&client_frame(true, OPCODE_BINARY, b"too long"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            socket.recv().await.unwrap(),
+            Err(WebSocketError::TooLarge { limit: 4 })
+        ));
+        assert_eq!(
+            read_server_frame(&mut edge).await,
+            (OPCODE_CLOSE, 1009u16.to_be_bytes().to_vec())
+        );
+    }
+}
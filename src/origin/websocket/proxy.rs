@@ -0,0 +1,505 @@
+//! Forwards websocket upgrades to an existing `ws://` or `wss://` server.
+
+#[cfg(feature = "websocket-proxy-tls")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::io::{AsyncReadExt as _, Cursor};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::{Pipe, bad_handshake};
+use crate::origin::error::{Error, OriginError};
+use crate::origin::http::body::{Body, Request, Response};
+use crate::origin::pump::websocket_accept;
+use crate::origin::responder::WebSocketResponder;
+use crate::origin::stream::{Stream, StreamOrigin, WebSocketConnection};
+
+/// How long connecting to the backend and its handshake may take.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest handshake response head accepted from the backend.
+const MAX_RESPONSE_HEAD: usize = 16 * 1024;
+
+/// Request headers that describe the hop from the edge rather than the
+/// request itself; the proxy writes its own framing headers instead.
+const HOP_HEADERS: [&str; 11] = [
+    "host",
+    "content-length",
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A [`StreamOrigin<WebSocketResponder>`](crate::StreamOrigin) that proxies
+/// every websocket upgrade to a backend websocket server.
+///
+/// For each upgrade the proxy dials the backend, replays the handshake with
+/// the visitor's path, `Sec-WebSocket-Key`, subprotocols and other end-to-end
+/// headers, and checks the backend's `101`. The backend's response headers
+/// (including the confirmed `Sec-WebSocket-Protocol`) are passed on to the
+/// edge unchanged, and the transport then pumps bytes between the two.
+///
+/// A backend that cannot be reached fails with a `502`, or a `504` when the
+/// connection and handshake exceed the timeout. A backend that refuses the
+/// upgrade with a `4xx` or `5xx` status has that status passed through; any
+/// other answer, or a `101` that does not complete the handshake, is a
+/// `502`.
+///
+/// `wss://` backends need the `websocket-proxy-tls` feature. They are
+/// verified against the Mozilla root store plus an optional CA
+/// ([`with_ca_cert_pem`](Self::with_ca_cert_pem)).
+#[derive(Clone)]
+pub struct WebSocketProxyOrigin {
+    host: String,
+    port: u16,
+    authority: String,
+    path: String,
+    host_header: Option<String>,
+    timeout: Duration,
+    /// Shared so the clone each upgrade takes does not copy the root
+    /// store.
+    #[cfg(feature = "websocket-proxy-tls")]
+    tls: Option<Arc<tls::Target>>,
+}
+
+impl WebSocketProxyOrigin {
+    /// Proxies to the backend at `url` (`ws://host[:port][/path]` or
+    /// `wss://…`). A path on the URL is prepended to every request path.
+    pub fn new(url: &str) -> Result<Self, crate::Error> {
+        let invalid = |reason: &str| Error::InvalidUrl(format!("{url}: {reason}"));
+        let uri = http::Uri::try_from(url).map_err(|error| invalid(&error.to_string()))?;
+        let secure = match uri.scheme_str() {
+            Some("ws") => false,
+            Some("wss") => true,
+            _ => return Err(invalid("the scheme must be ws or wss").into()),
+        };
+        #[cfg(not(feature = "websocket-proxy-tls"))]
+        if secure {
+            return Err(invalid("wss backends need the websocket-proxy-tls feature").into());
+        }
+        if uri.query().is_some() {
+            return Err(invalid("a query is not supported").into());
+        }
+        let (Some(host), Some(authority)) = (uri.host(), uri.authority()) else {
+            return Err(invalid("the host is missing").into());
+        };
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        Ok(Self {
+            port: uri.port_u16().unwrap_or(if secure { 443 } else { 80 }),
+            authority: authority.as_str().to_string(),
+            path: uri.path().trim_end_matches('/').to_string(),
+            host_header: None,
+            timeout: DEFAULT_TIMEOUT,
+            #[cfg(feature = "websocket-proxy-tls")]
+            tls: secure.then(|| Arc::new(tls::Target::new(&host))),
+            host,
+        })
+    }
+
+    /// Sends `host` as the `Host` header instead of the backend's address.
+    pub fn with_host_header(mut self, host: impl Into<String>) -> Self {
+        self.host_header = Some(host.into());
+        self
+    }
+
+    /// Bounds connecting to the backend, the TLS handshake and the upgrade
+    /// handshake together. Defaults to 30 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the TLS server name (SNI) and the name the backend certificate
+    /// is verified against. Defaults to the URL's host; ignored for `ws://`
+    /// backends.
+    #[cfg(feature = "websocket-proxy-tls")]
+    pub fn with_server_name(mut self, server_name: impl Into<String>) -> Self {
+        if let Some(tls) = &mut self.tls {
+            Arc::make_mut(tls).server_name = server_name.into();
+        }
+        self
+    }
+
+    /// Trusts the PEM certificates in `pem` for the backend in addition to
+    /// the Mozilla roots, e.g. a private CA. Ignored for `ws://` backends.
+    #[cfg(feature = "websocket-proxy-tls")]
+    pub fn with_ca_cert_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        if let Some(tls) = &mut self.tls {
+            Arc::make_mut(tls).trust(&pem.into());
+        }
+        self
+    }
+
+    /// Dials the backend and replays the upgrade `head` for `key`.
+    async fn handshake(&self, head: &[u8], key: &str) -> Result<WebSocketConnection, OriginError> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map_err(|error| {
+                OriginError::bad_gateway(format!(
+                    "could not connect to websocket backend {}: {error}",
+                    self.authority
+                ))
+            })?;
+        #[cfg(feature = "websocket-proxy-tls")]
+        if let Some(tls) = &self.tls {
+            let stream = tls.connect(tcp).await.map_err(|error| {
+                OriginError::bad_gateway(format!(
+                    "tls handshake with websocket backend {} failed: {error}",
+                    self.authority
+                ))
+            })?;
+            return exchange(stream, head, key).await;
+        }
+        exchange(tcp, head, key).await
+    }
+
+    /// The upgrade request replayed to the backend.
+    fn request_head(&self, request: &Request) -> Vec<u8> {
+        let path = request
+            .uri
+            .path_and_query()
+            .map_or("/", |path| path.as_str());
+        let host = self.host_header.as_deref().unwrap_or(&self.authority);
+        let mut head = format!(
+            "GET {}{path} HTTP/1.1\r\nHost: {host}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n",
+            self.path
+        )
+        .into_bytes();
+        for (name, value) in &request.headers {
+            if HOP_HEADERS.contains(&name.as_str()) {
+                continue;
+            }
+            head.extend_from_slice(name.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+        if !request
+            .headers
+            .contains_key(http::header::SEC_WEBSOCKET_VERSION)
+        {
+            head.extend_from_slice(b"sec-websocket-version: 13\r\n");
+        }
+        head.extend_from_slice(b"\r\n");
+        head
+    }
+}
+
+impl StreamOrigin<WebSocketResponder> for WebSocketProxyOrigin {
+    fn connect(&self, request: Request, respond: WebSocketResponder) {
+        let key = match request
+            .headers
+            .get(http::header::SEC_WEBSOCKET_KEY)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+        {
+            Some(key) => key.to_string(),
+            None => return respond.fail(bad_handshake("missing Sec-WebSocket-Key")),
+        };
+        let head = self.request_head(&request);
+        let cancellation = request.cancellation();
+        let proxy = self.clone();
+        tokio::spawn(async move {
+            let handshake = proxy.handshake(&head, &key);
+            let outcome = tokio::select! {
+                outcome = tokio::time::timeout(proxy.timeout, handshake) => outcome,
+                () = cancellation.cancelled() => return,
+            };
+            match outcome {
+                Ok(Ok(connection)) => respond.upgrade(connection),
+                Ok(Err(error)) => respond.fail(error),
+                Err(_) => respond.fail(OriginError::gateway_timeout(format!(
+                    "websocket backend {} did not complete the handshake in time",
+                    proxy.authority
+                ))),
+            }
+        });
+    }
+}
+
+/// Sends the request head over `io` and reads the backend's answer. Bytes
+/// the backend sent after its `101` head are replayed ahead of the stream.
+async fn exchange<S>(mut io: S, head: &[u8], key: &str) -> Result<WebSocketConnection, OriginError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    io.write_all(head).await.map_err(backend_io)?;
+    io.flush().await.map_err(backend_io)?;
+    let mut buffer = Vec::with_capacity(1024);
+    let end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_RESPONSE_HEAD {
+            return Err(OriginError::bad_gateway(
+                "websocket backend sent an oversized handshake response",
+            ));
+        }
+        let mut chunk = [0u8; 1024];
+        match io.read(&mut chunk).await.map_err(backend_io)? {
+            0 => {
+                return Err(OriginError::bad_gateway(
+                    "websocket backend closed the connection during the handshake",
+                ));
+            }
+            read => buffer.extend_from_slice(&chunk[..read]),
+        }
+    };
+    let leftover = buffer.split_off(end);
+    let (status, headers) = parse_response_head(&buffer)?;
+    check_upgrade(status, &headers, key)?;
+    let (read, write) = Stream::from_io(Pipe(io)).into_parts();
+    Ok(WebSocketConnection {
+        response: Response::new(status, headers, Body::empty()),
+        origin: Stream::new(Cursor::new(leftover).chain(read), write),
+    })
+}
+
+fn backend_io(error: std::io::Error) -> OriginError {
+    OriginError::bad_gateway(format!("websocket backend handshake failed: {error}"))
+}
+
+/// Parses the backend's status line and headers.
+fn parse_response_head(head: &[u8]) -> Result<(http::StatusCode, http::HeaderMap), OriginError> {
+    let malformed =
+        || OriginError::bad_gateway("websocket backend sent a malformed handshake response");
+    let head = std::str::from_utf8(head).map_err(|_| malformed())?;
+    let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+    let status = lines
+        .next()
+        .filter(|line| line.starts_with("HTTP/1."))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| http::StatusCode::from_u16(code).ok())
+        .ok_or_else(malformed)?;
+    let mut headers = http::HeaderMap::new();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(malformed)?;
+        let name = http::HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| malformed())?;
+        let value = http::HeaderValue::from_str(value.trim()).map_err(|_| malformed())?;
+        headers.append(name, value);
+    }
+    Ok((status, headers))
+}
+
+/// Checks that the backend accepted the upgrade for `key`.
+fn check_upgrade(
+    status: http::StatusCode,
+    headers: &http::HeaderMap,
+    key: &str,
+) -> Result<(), OriginError> {
+    if status != http::StatusCode::SWITCHING_PROTOCOLS {
+        let message = format!("websocket backend refused the upgrade with {status}");
+        return Err(if status.is_client_error() || status.is_server_error() {
+            OriginError::new(status, message)
+        } else {
+            OriginError::bad_gateway(message)
+        });
+    }
+    let header = |name: http::header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+    };
+    if !header(http::header::UPGRADE).is_some_and(|value| value.eq_ignore_ascii_case("websocket")) {
+        return Err(OriginError::bad_gateway(
+            "websocket backend answered 101 without Upgrade: websocket",
+        ));
+    }
+    if header(http::header::SEC_WEBSOCKET_ACCEPT) != Some(websocket_accept(key).as_str()) {
+        return Err(OriginError::bad_gateway(
+            "websocket backend sent a mismatched Sec-WebSocket-Accept",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "websocket-proxy-tls")]
+mod tls {
+    use std::sync::Arc;
+
+    use rustls_pki_types::ServerName;
+    use rustls_pki_types::pem::PemObject;
+    use tokio::net::TcpStream;
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::client::TlsStream;
+
+    /// The TLS settings of a `wss://` backend.
+    #[derive(Clone)]
+    pub(super) struct Target {
+        pub(super) server_name: String,
+        roots: rustls::RootCertStore,
+        connector: TlsConnector,
+    }
+
+    impl Target {
+        pub(super) fn new(host: &str) -> Self {
+            let mut roots = rustls::RootCertStore::empty();
+            roots
+                .roots
+                .extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            Self {
+                server_name: host.to_string(),
+                connector: connector(roots.clone()),
+                roots,
+            }
+        }
+
+        /// Adds the certificates in `pem` to the trusted roots; entries
+        /// that fail to parse are skipped.
+        pub(super) fn trust(&mut self, pem: &[u8]) {
+            for cert in PemObject::pem_slice_iter(pem).flatten() {
+                let _ = self.roots.add(cert);
+            }
+            self.connector = connector(self.roots.clone());
+        }
+
+        pub(super) async fn connect(
+            &self,
+            tcp: TcpStream,
+        ) -> std::io::Result<TlsStream<TcpStream>> {
+            let server_name = ServerName::try_from(self.server_name.clone())
+                .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+            self.connector.connect(server_name, tcp).await
+        }
+    }
+
+    fn connector(roots: rustls::RootCertStore) -> TlsConnector {
+        let configuration = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        TlsConnector::from(Arc::new(configuration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::io::AsyncWriteExt as _;
+
+    const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+    fn upgrade_request(path: &'static str) -> Request {
+        let mut headers = http::HeaderMap::new();
+        for (name, value) in [
+            ("host", "example.com"),
+            ("connection", "Upgrade"),
+            ("upgrade", "websocket"),
+            ("sec-websocket-key", KEY),
+            ("sec-websocket-version", "13"),
+            ("sec-websocket-protocol", "chat, superchat"),
+        ] {
+            headers.append(name, http::HeaderValue::from_static(value));
+        }
+        Request::new(
+            http::Method::GET,
+            http::Uri::from_static(path),
+            headers,
+            Body::empty(),
+        )
+    }
+
+    /// A one-shot backend answering the first request head with `answer`,
+    /// returning its address and the request head it received.
+    async fn backend(answer: String) -> (String, tokio::task::JoinHandle<(String, TcpStream)>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let task = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8; 1];
+                socket.read_exact(&mut byte).await.unwrap();
+                head.push(byte[0]);
+            }
+            socket.write_all(answer.as_bytes()).await.unwrap();
+            (String::from_utf8(head).unwrap(), socket)
+        });
+        (address, task)
+    }
+
+    #[test]
+    fn rejects_non_websocket_urls() {
+        assert!(WebSocketProxyOrigin::new("http://127.0.0.1:8080").is_err());
+        assert!(WebSocketProxyOrigin::new("ws://127.0.0.1:8080/?token=1").is_err());
+        assert!(WebSocketProxyOrigin::new("ws://127.0.0.1:8080/base/").is_ok());
+    }
+
+    #[test]
+    fn replays_end_to_end_headers() {
+        let proxy = WebSocketProxyOrigin::new("ws://127.0.0.1:8080/base/").unwrap();
+        let head = proxy.request_head(&upgrade_request("http://example.com/chat?room=1"));
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("GET /base/chat?room=1 HTTP/1.1\r\nHost: 127.0.0.1:8080\r\n"));
+        assert!(head.contains(&format!("sec-websocket-key: {KEY}\r\n")));
+        assert!(head.contains("sec-websocket-protocol: chat, superchat\r\n"));
+        assert!(!head.contains("example.com"));
+        assert_eq!(head.matches("onnection").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn proxies_the_upgrade_and_early_bytes() {
+        let answer = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\nSec-WebSocket-Protocol: chat\r\n\r\nearly",
+            websocket_accept(KEY)
+        );
+        let (address, task) = backend(answer).await;
+        let proxy = WebSocketProxyOrigin::new(&format!("ws://{address}")).unwrap();
+        let (respond, receiver) = WebSocketResponder::channel();
+        proxy.connect(upgrade_request("http://example.com/socket"), respond);
+
+        let connection = receiver.await.unwrap().unwrap();
+        assert_eq!(
+            connection.response.status,
+            http::StatusCode::SWITCHING_PROTOCOLS
+        );
+        assert_eq!(
+            connection.response.headers[http::header::SEC_WEBSOCKET_PROTOCOL],
+            "chat"
+        );
+        let (head, mut socket) = task.await.unwrap();
+        assert!(head.starts_with("GET /socket HTTP/1.1\r\n"));
+
+        let mut origin = connection.origin;
+        let mut early = [0u8; 5];
+        origin.read_exact(&mut early).await.unwrap();
+        assert_eq!(&early, b"early");
+        origin.write_all(b"ping").await.unwrap();
+        origin.flush().await.unwrap();
+        let mut ping = [0u8; 4];
+        socket.read_exact(&mut ping).await.unwrap();
+        assert_eq!(&ping, b"ping");
+    }
+
+    #[tokio::test]
+    async fn maps_backend_refusals() {
+        let (address, _task) = backend("HTTP/1.1 403 Forbidden\r\n\r\n".to_string()).await;
+        let proxy = WebSocketProxyOrigin::new(&format!("ws://{address}")).unwrap();
+        let (respond, receiver) = WebSocketResponder::channel();
+        proxy.connect(upgrade_request("http://example.com/"), respond);
+        let error = receiver.await.unwrap().err().unwrap();
+        assert_eq!(error.status(), http::StatusCode::FORBIDDEN);
+
+        let answer = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                      Sec-WebSocket-Accept: wrong\r\n\r\n";
+        let (address, _task) = backend(answer.to_string()).await;
+        let proxy = WebSocketProxyOrigin::new(&format!("ws://{address}")).unwrap();
+        let (respond, receiver) = WebSocketResponder::channel();
+        proxy.connect(upgrade_request("http://example.com/"), respond);
+        let error = receiver.await.unwrap().err().unwrap();
+        assert_eq!(error.status(), http::StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,313 @@
+//! Remotely-managed tunnel configuration: the ingress model the dashboard
+//! edits, uploaded through the tunnel configurations endpoint.
+//!
+//! After an upload the edge pushes the new version to connected
+//! connectors, where it arrives as a
+//! [`RemoteConfiguration`](crate::RemoteConfiguration) with the same
+//! version.
+
+use http::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+use super::TunnelApi;
+
+/// A tunnel's ingress configuration, in cloudflared's config layout.
+///
+/// Rules are matched in order; the last one must be a catch-all with
+/// neither hostname nor path. [`IngressConfiguration::new`] starts with
+/// just that rule and [`IngressConfiguration::with_rule`] adds rules ahead
+/// of it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IngressConfiguration {
+    /// The ingress rules, in matching order.
+    #[serde(default)]
+    pub ingress: Vec<IngressRule>,
+    /// Private network routing through the tunnel.
+    #[serde(
+        rename = "warp-routing",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub warp_routing: Option<WarpRouting>,
+    /// Origin request settings applied to every rule.
+    #[serde(
+        rename = "originRequest",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub origin_request: Option<OriginRequest>,
+}
+
+/// One ingress rule: requests for `hostname` (and `path`) go to `service`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IngressRule {
+    /// The public hostname; `None` matches any hostname.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// A regular expression the path must match; `None` matches any path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Where matching requests go, e.g. `http://localhost:8080`,
+    /// `tcp://localhost:22` or `http_status:404`.
+    pub service: String,
+    /// Origin request settings for this rule, overriding the global ones.
+    #[serde(
+        rename = "originRequest",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub origin_request: Option<OriginRequest>,
+}
+
+/// Private network routing settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarpRouting {
+    /// Whether private network traffic is routed through the tunnel.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// How the connector talks to an origin.
+///
+/// The common settings are typed; anything else cloudflared understands
+/// (e.g. `access` or `proxyType`) is kept as JSON in `other`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OriginRequest {
+    /// Timeout for establishing a connection to the origin, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    /// Skip verifying the origin's TLS certificate.
+    #[serde(
+        rename = "noTLSVerify",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub no_tls_verify: Option<bool>,
+    /// The `Host` header sent to the origin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_host_header: Option<String>,
+    /// The TLS server name expected from the origin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_server_name: Option<String>,
+    /// Speak HTTP/2 to the origin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http2_origin: Option<bool>,
+    /// Settings without a typed field, by their cloudflared name.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A tunnel's configuration as stored by the API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunnelConfiguration {
+    /// The configuration version; the edge pushes it along with the
+    /// configuration.
+    pub version: i32,
+    /// The ingress configuration.
+    pub configuration: IngressConfiguration,
+}
+
+#[derive(Deserialize)]
+struct StoredConfiguration {
+    #[serde(default)]
+    version: i32,
+    #[serde(default)]
+    config: Option<IngressConfiguration>,
+}
+
+#[derive(Serialize)]
+struct UploadedConfiguration<'a> {
+    config: &'a IngressConfiguration,
+}
+
+impl IngressConfiguration {
+    /// A configuration sending every request to `catch_all`, e.g.
+    /// `http_status:404`.
+    pub fn new(catch_all: impl Into<String>) -> Self {
+        Self {
+            ingress: vec![IngressRule {
+                service: catch_all.into(),
+                ..IngressRule::default()
+            }],
+            ..Self::default()
+        }
+    }
+
+    /// Adds `rule` after the existing rules but ahead of the catch-all.
+    pub fn with_rule(mut self, rule: IngressRule) -> Self {
+        let position = match self.ingress.last() {
+            Some(last) if last.is_catch_all() => self.ingress.len() - 1,
+            _ => self.ingress.len(),
+        };
+        self.ingress.insert(position, rule);
+        self
+    }
+
+    /// The public hostnames the rules route, in order.
+    pub fn hostnames(&self) -> impl Iterator<Item = &str> {
+        self.ingress
+            .iter()
+            .filter_map(|rule| rule.hostname.as_deref())
+            .filter(|hostname| !hostname.is_empty())
+    }
+
+    /// Checks what the API would otherwise reject: there must be rules,
+    /// the last one a catch-all, and each with a service.
+    pub fn validate(&self) -> Result<()> {
+        let Some(last) = self.ingress.last() else {
+            return Err(Error::invalid_configuration("no ingress rules"));
+        };
+        if !last.is_catch_all() {
+            return Err(Error::invalid_configuration(
+                "the last ingress rule must match every hostname and path",
+            ));
+        }
+        if let Some(position) = self.ingress.iter().position(|rule| rule.service.is_empty()) {
+            return Err(Error::invalid_configuration(format!(
+                "ingress rule {position} has no service"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl IngressRule {
+    /// A rule routing `hostname` to `service`.
+    pub fn new(hostname: impl Into<String>, service: impl Into<String>) -> Self {
+        Self {
+            hostname: Some(hostname.into()),
+            service: service.into(),
+            ..Self::default()
+        }
+    }
+
+    fn is_catch_all(&self) -> bool {
+        self.hostname
+            .as_deref()
+            .is_none_or(|hostname| hostname.is_empty() || hostname == "*")
+            && self.path.is_none()
+    }
+}
+
+impl TunnelApi {
+    /// The tunnel's remotely-managed configuration and its version.
+    pub async fn tunnel_configuration(&self, tunnel: &str) -> Result<TunnelConfiguration> {
+        let path = format!("{}/configurations", self.tunnel_path(tunnel)?);
+        let stored: StoredConfiguration = self.request(Method::GET, &path, None).await?.result()?;
+        Ok(stored.into())
+    }
+
+    /// Uploads a new configuration for a remotely-managed tunnel and
+    /// returns it with its new version.
+    ///
+    /// With `expected_version`, the upload is refused with
+    /// [`ConfigurationVersion`](crate::tunnel::Error::ConfigurationVersion)
+    /// when the stored configuration is no longer that version, so edits
+    /// made elsewhere in the meantime are not overwritten. The check and
+    /// the upload are separate requests; the API has no atomic
+    /// compare-and-set.
+    pub async fn update_tunnel_configuration(
+        &self,
+        tunnel: &str,
+        configuration: &IngressConfiguration,
+        expected_version: Option<i32>,
+    ) -> Result<TunnelConfiguration> {
+        configuration.validate()?;
+        if let Some(expected) = expected_version {
+            let current = self.tunnel_configuration(tunnel).await?.version;
+            if current != expected {
+                return Err(Error::configuration_version(expected, current));
+            }
+        }
+        let path = format!("{}/configurations", self.tunnel_path(tunnel)?);
+        let body = serde_json::to_vec(&UploadedConfiguration {
+            config: configuration,
+        })
+        .map_err(|e| Error::tunnel_api_response(e.to_string()))?;
+        let stored: StoredConfiguration = self
+            .request(Method::PUT, &path, Some(body))
+            .await?
+            .result()?;
+        Ok(stored.into())
+    }
+}
+
+impl From<StoredConfiguration> for TunnelConfiguration {
+    fn from(stored: StoredConfiguration) -> Self {
+        Self {
+            version: stored.version,
+            configuration: stored.config.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_in_cloudflared_layout() {
+        let mut configuration = IngressConfiguration::new("http_status:404")
+            .with_rule(IngressRule::new("app.example.com", "http://localhost:8080"));
+        configuration.warp_routing = Some(WarpRouting { enabled: true });
+        configuration.ingress[0].origin_request = Some(OriginRequest {
+            no_tls_verify: Some(true),
+            http_host_header: Some("app.internal".into()),
+            ..OriginRequest::default()
+        });
+        assert_eq!(
+            serde_json::to_value(&configuration).unwrap(),
+            serde_json::json!({
+                "ingress": [
+                    {
+                        "hostname": "app.example.com",
+                        "service": "http://localhost:8080",
+                        "originRequest": {"noTLSVerify": true, "httpHostHeader": "app.internal"},
+                    },
+                    {"service": "http_status:404"},
+                ],
+                "warp-routing": {"enabled": true},
+            })
+        );
+        assert_eq!(
+            configuration.hostnames().collect::<Vec<_>>(),
+            ["app.example.com"]
+        );
+    }
+
+    #[test]
+    fn keeps_untyped_origin_settings() {
+        let json = serde_json::json!({
+            "ingress": [{
+                "service": "http://localhost:8080",
+                "originRequest": {"connectTimeout": 10, "access": {"required": true, "teamName": "team"}},
+            }],
+        });
+        let configuration: IngressConfiguration = serde_json::from_value(json.clone()).unwrap();
+        let origin = configuration.ingress[0].origin_request.as_ref().unwrap();
+        assert_eq!(origin.connect_timeout, Some(10));
+        assert_eq!(origin.other["access"]["teamName"], "team");
+        assert_eq!(serde_json::to_value(&configuration).unwrap(), json);
+    }
+
+    #[test]
+    fn validation_needs_a_trailing_catch_all() {
+        assert!(IngressConfiguration::default().validate().is_err());
+        assert!(
+            IngressConfiguration::new("http_status:404")
+                .validate()
+                .is_ok()
+        );
+        let no_catch_all = IngressConfiguration {
+            ingress: vec![IngressRule::new("app.example.com", "http://localhost:8080")],
+            ..IngressConfiguration::default()
+        };
+        assert!(no_catch_all.validate().is_err());
+        let missing_service = IngressConfiguration::new("http_status:404")
+            .with_rule(IngressRule::new("app.example.com", ""));
+        assert!(missing_service.validate().is_err());
+    }
+}
@@ -0,0 +1,493 @@
+//! Cloudflare API client for the named tunnel lifecycle: what
+//! `cloudflared tunnel create`, `list`, `info`, `delete`, `cleanup` and
+//! `token` do with an API token, or with the one inside a login
+//! certificate ([`OriginCertificate`]).
+//!
+//! [`TunnelApi`] also routes traffic to tunnels, like `cloudflared tunnel
+//! route dns|lb|ip`, manages virtual networks, and uploads the ingress
+//! configuration of remotely-managed tunnels.
+
+mod certificate;
+mod configuration;
+mod routes;
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use http::Method;
+use http::header::{AUTHORIZATION, HeaderName, USER_AGENT};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+use super::NamedTunnel;
+use super::https::{HttpsClient, HttpsError};
+use super::parse_tunnel_identifier;
+
+pub use certificate::OriginCertificate;
+pub use configuration::{
+    IngressConfiguration, IngressRule, OriginRequest, TunnelConfiguration, WarpRouting,
+};
+pub use routes::{
+    DnsRoute, IpRoute, IpRouteFilter, LoadBalancerRoute, RouteChange, VirtualNetwork,
+};
+
+/// Default Cloudflare API base URL.
+pub const DEFAULT_API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+/// Tunnels requested per page when listing.
+const PAGE_SIZE: usize = 50;
+
+/// Options for [`TunnelApi`].
+#[derive(Debug, Clone)]
+pub struct TunnelApiOptions {
+    /// Base URL of the API, including the version path. Defaults to
+    /// `DEFAULT_API_URL`; point it at a stand-in server to test against.
+    pub base_url: String,
+    /// PEM-encoded CA certificates trusted in addition to the web PKI
+    /// roots.
+    pub ca_cert_pem: Option<Vec<u8>>,
+    /// HTTP timeout for each request.
+    pub http_timeout: Duration,
+}
+
+impl Default for TunnelApiOptions {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_API_URL.to_string(),
+            ca_cert_pem: None,
+            http_timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Where a tunnel's ingress configuration comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// The connector's own configuration, as with `cloudflared tunnel
+    /// create`.
+    #[default]
+    Local,
+    /// The dashboard or the API (see
+    /// [`TunnelApi::update_tunnel_configuration`]); the edge pushes it to
+    /// connectors.
+    Cloudflare,
+}
+
+/// A tunnel as the API describes it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TunnelInfo {
+    /// The tunnel id as a UUID string.
+    #[serde(rename = "id")]
+    pub identifier: String,
+    /// The tunnel name, unique within the account.
+    pub name: String,
+    /// When the tunnel was created (RFC 3339).
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// When the tunnel was deleted (RFC 3339), for deleted tunnels.
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+    /// The tunnel's active connections.
+    #[serde(default)]
+    pub connections: Vec<TunnelConnection>,
+}
+
+/// One edge connection of a tunnel's connector.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TunnelConnection {
+    /// The connection id.
+    #[serde(rename = "id")]
+    pub identifier: String,
+    /// The edge data center, e.g. `lhr01`.
+    #[serde(rename = "colo_name", default)]
+    pub colo: String,
+    /// The id of the connector holding the connection.
+    #[serde(rename = "client_id", default)]
+    pub client_identifier: String,
+    /// The connector's version string.
+    #[serde(default)]
+    pub client_version: String,
+    /// The connector's public address as the edge saw it.
+    #[serde(default)]
+    pub origin_ip: String,
+    /// When the connection was opened (RFC 3339).
+    #[serde(default)]
+    pub opened_at: String,
+    /// Whether the edge expects the connector to reconnect.
+    #[serde(default)]
+    pub is_pending_reconnect: bool,
+}
+
+/// A newly created tunnel and its credentials.
+///
+/// The secret is generated locally and never returned by the API again;
+/// keep the credentials (e.g. with
+/// [`NamedTunnel::write_credentials_file`]) or fetch a token later with
+/// [`TunnelApi::credentials`].
+#[derive(Debug, Clone)]
+pub struct CreatedTunnel {
+    /// The tunnel as the API created it.
+    pub tunnel: TunnelInfo,
+    /// Credentials for running the tunnel.
+    pub credentials: NamedTunnel,
+}
+
+/// Which tunnels [`TunnelApi::list_tunnels`] returns.
+#[derive(Debug, Clone, Default)]
+pub struct TunnelFilter {
+    /// Only the tunnel with this name.
+    pub name: Option<String>,
+    /// Include deleted tunnels, which are left out by default.
+    pub include_deleted: bool,
+}
+
+/// A Cloudflare API client for one account, authenticated with an API
+/// token that has the `Cloudflare Tunnel: Edit` permission.
+#[derive(Clone)]
+pub struct TunnelApi {
+    account_tag: String,
+    api_token: String,
+    base_url: String,
+    client: HttpsClient,
+}
+
+impl fmt::Debug for TunnelApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TunnelApi")
+            .field("account_tag", &self.account_tag)
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize)]
+struct CreateTunnel<'a> {
+    name: &'a str,
+    #[serde(serialize_with = "crate::tunnel::secret::serialize")]
+    tunnel_secret: &'a [u8],
+    config_src: ConfigSource,
+}
+
+/// The envelope around every API response.
+#[derive(Debug, Deserialize)]
+struct Envelope<T> {
+    #[serde(default)]
+    success: bool,
+    #[serde(default)]
+    errors: Vec<ApiMessage>,
+    result: Option<T>,
+    #[serde(default)]
+    result_info: Option<ResultInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMessage {
+    #[serde(default)]
+    code: i64,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResultInfo {
+    #[serde(default)]
+    page: usize,
+    #[serde(default)]
+    total_pages: Option<usize>,
+}
+
+impl TunnelApi {
+    /// Creates a client for `account_tag` against the public API.
+    pub fn new(account_tag: impl Into<String>, api_token: impl Into<String>) -> Self {
+        Self::with_options(account_tag, api_token, TunnelApiOptions::default())
+    }
+
+    /// Creates a client with the given options.
+    pub fn with_options(
+        account_tag: impl Into<String>,
+        api_token: impl Into<String>,
+        options: TunnelApiOptions,
+    ) -> Self {
+        Self {
+            account_tag: account_tag.into(),
+            api_token: api_token.into(),
+            base_url: options.base_url.trim_end_matches('/').to_string(),
+            client: HttpsClient::new(options.ca_cert_pem.as_deref(), options.http_timeout),
+        }
+    }
+
+    /// The account this client manages tunnels for.
+    pub fn account_tag(&self) -> &str {
+        &self.account_tag
+    }
+
+    /// Creates a tunnel with a freshly generated 32-byte secret, like
+    /// `cloudflared tunnel create`.
+    pub async fn create_tunnel(
+        &self,
+        name: &str,
+        config_source: ConfigSource,
+    ) -> Result<CreatedTunnel> {
+        let mut secret = vec![0u8; 32];
+        getrandom::fill(&mut secret).map_err(|e| Error::tunnel_api_request(io::Error::other(e)))?;
+        let body = serde_json::to_vec(&CreateTunnel {
+            name,
+            tunnel_secret: &secret,
+            config_src: config_source,
+        })
+        .map_err(|e| Error::tunnel_api_response(e.to_string()))?;
+        let tunnel: TunnelInfo = self
+            .request(Method::POST, &self.tunnels_path(), Some(body))
+            .await?
+            .result()?;
+        parse_tunnel_identifier(&tunnel.identifier)?;
+        let credentials = NamedTunnel {
+            account_tag: self.account_tag.clone(),
+            tunnel_secret: secret,
+            tunnel_identifier: tunnel.identifier.clone(),
+            endpoint: None,
+        };
+        Ok(CreatedTunnel {
+            tunnel,
+            credentials,
+        })
+    }
+
+    /// Lists the account's tunnels, following pagination.
+    pub async fn list_tunnels(&self, filter: &TunnelFilter) -> Result<Vec<TunnelInfo>> {
+        let mut query = format!("per_page={PAGE_SIZE}");
+        if let Some(name) = &filter.name {
+            query.push_str(&format!("&name={}", encode_component(name)));
+        }
+        if !filter.include_deleted {
+            query.push_str("&is_deleted=false");
+        }
+        self.paginate(&format!("{}?{query}", self.tunnels_path()))
+            .await
+    }
+
+    /// Fetches one tunnel, like `cloudflared tunnel info`.
+    pub async fn tunnel(&self, identifier: &str) -> Result<TunnelInfo> {
+        let path = self.tunnel_path(identifier)?;
+        self.request(Method::GET, &path, None).await?.result()
+    }
+
+    /// Deletes a tunnel. With `cascade`, the API also removes what still
+    /// references it (e.g. connections) instead of refusing.
+    pub async fn delete_tunnel(&self, identifier: &str, cascade: bool) -> Result<()> {
+        let path = format!("{}?cascade={cascade}", self.tunnel_path(identifier)?);
+        self.request::<IgnoredAny>(Method::DELETE, &path, None)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes a tunnel's stale connections, like `cloudflared tunnel
+    /// cleanup`; with `connector`, only that connector's.
+    pub async fn cleanup_connections(
+        &self,
+        identifier: &str,
+        connector: Option<&str>,
+    ) -> Result<()> {
+        let mut path = format!("{}/connections", self.tunnel_path(identifier)?);
+        if let Some(connector) = connector {
+            path.push_str(&format!("?client_id={}", encode_component(connector)));
+        }
+        self.request::<IgnoredAny>(Method::DELETE, &path, None)
+            .await?;
+        Ok(())
+    }
+
+    /// The connector token for `cloudflared tunnel run --token`, the same
+    /// the dashboard shows.
+    pub async fn tunnel_token(&self, identifier: &str) -> Result<String> {
+        let path = format!("{}/token", self.tunnel_path(identifier)?);
+        self.request(Method::GET, &path, None).await?.result()
+    }
+
+    /// The tunnel's credentials, decoded from its connector token; write
+    /// them out with [`NamedTunnel::write_credentials_file`].
+    pub async fn credentials(&self, identifier: &str) -> Result<NamedTunnel> {
+        NamedTunnel::from_token(&self.tunnel_token(identifier).await?)
+    }
+
+    fn account_path(&self) -> String {
+        format!("/accounts/{}", encode_component(&self.account_tag))
+    }
+
+    fn tunnels_path(&self) -> String {
+        format!("{}/cfd_tunnel", self.account_path())
+    }
+
+    /// Fetches every page of a list; `path` already has a query.
+    async fn paginate<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        for page in 1.. {
+            let path = format!("{path}&page={page}");
+            let envelope = self.request::<Vec<T>>(Method::GET, &path, None).await?;
+            let total_pages = envelope
+                .result_info
+                .as_ref()
+                .and_then(|info| info.total_pages);
+            let returned_page = envelope.result_info.as_ref().map(|info| info.page);
+            let batch = envelope.result()?;
+            let last = match total_pages {
+                Some(total) => page >= total,
+                None => batch.len() < PAGE_SIZE,
+            };
+            items.extend(batch);
+            if last || returned_page.is_some_and(|returned| returned != page) {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn tunnel_path(&self, identifier: &str) -> Result<String> {
+        parse_tunnel_identifier(identifier)?;
+        Ok(format!("{}/{identifier}", self.tunnels_path()))
+    }
+
+    /// Sends one request and unwraps the response envelope, turning
+    /// unsuccessful responses into [`Error`]s.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Envelope<T>> {
+        let url = format!("{}{path}", self.base_url);
+        let headers: [(HeaderName, String); 2] = [
+            (AUTHORIZATION, format!("Bearer {}", self.api_token)),
+            (USER_AGENT, format!("libcfd/{}", env!("CARGO_PKG_VERSION"))),
+        ];
+        let (status, body) = self
+            .client
+            .send(method, &url, &headers, body.as_deref())
+            .await
+            .map_err(|e| match e {
+                HttpsError::Request(e) => Error::tunnel_api_request(e),
+                HttpsError::Response(message) => Error::tunnel_api_response(message),
+                HttpsError::Timeout(message) => {
+                    Error::tunnel_api_request(io::Error::new(io::ErrorKind::TimedOut, message))
+                }
+            })?;
+        parse_envelope(status, &body)
+    }
+}
+
+impl<T> Envelope<T> {
+    fn result(self) -> Result<T> {
+        self.result
+            .ok_or_else(|| Error::tunnel_api_response("response has no result"))
+    }
+}
+
+/// Decodes a response body, failing with the API's own errors when the
+/// status or the `success` flag says the request did not succeed.
+fn parse_envelope<T: DeserializeOwned>(status: u16, body: &[u8]) -> Result<Envelope<T>> {
+    let envelope = serde_json::from_slice::<Envelope<T>>(body);
+    match envelope {
+        Ok(envelope) if (200..300).contains(&status) && envelope.success => Ok(envelope),
+        Ok(envelope) => {
+            let message = if envelope.errors.is_empty() {
+                "unknown error".to_string()
+            } else {
+                envelope
+                    .errors
+                    .iter()
+                    .map(|e| format!("{}: {}", e.code, e.message))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            };
+            Err(Error::tunnel_api(
+                status,
+                envelope.errors.first().map(|e| e.code),
+                message,
+            ))
+        }
+        Err(e) if (200..300).contains(&status) => Err(Error::tunnel_api_response(e.to_string())),
+        Err(_) => Err(Error::tunnel_api(
+            status,
+            None,
+            String::from_utf8_lossy(body).into_owned(),
+        )),
+    }
+}
+
+/// Percent-encodes a path segment or query value.
+fn encode_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_components() {
+        assert_eq!(encode_component("my tunnel/1"), "my%20tunnel%2F1");
+        assert_eq!(encode_component("a-b.c_d~e"), "a-b.c_d~e");
+    }
+
+    #[test]
+    fn parses_successful_envelopes() {
+        let body = br#"{"success":true,"errors":[],"messages":[],"result":{"id":"550e8400-e29b-41d4-a716-446655440000","name":"web","created_at":"2024-01-01T00:00:00Z","connections":[{"id":"c1","colo_name":"lhr01","client_id":"k1","client_version":"2024.1.0","origin_ip":"203.0.113.7","opened_at":"2024-01-01T00:00:01Z","is_pending_reconnect":false}]}}"#;
+        let tunnel: TunnelInfo = parse_envelope(200, body).unwrap().result().unwrap();
+        assert_eq!(tunnel.name, "web");
+        assert_eq!(tunnel.deleted_at, None);
+        assert_eq!(tunnel.connections[0].colo, "lhr01");
+        assert_eq!(tunnel.connections[0].client_identifier, "k1");
+    }
+
+    #[test]
+    fn surfaces_api_errors() {
+        let body = br#"{"success":false,"errors":[{"code":1013,"message":"tunnel name already in use"}],"result":null}"#;
+        let error = parse_envelope::<TunnelInfo>(409, body).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Tunnel(crate::tunnel::Error::TunnelApi {
+                status: 409,
+                code: Some(1013),
+                ..
+            })
+        ));
+        assert!(
+            error
+                .to_string()
+                .contains("1013: tunnel name already in use")
+        );
+
+        let error = parse_envelope::<TunnelInfo>(502, b"<html>bad gateway</html>").unwrap_err();
+        assert!(error.to_string().contains("502"), "{error}");
+        assert!(parse_envelope::<TunnelInfo>(200, b"not json").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_tunnel_identifiers() {
+        let api = TunnelApi::new("account", "token");
+        assert!(api.tunnel_path("../zones").is_err());
+        assert_eq!(
+            api.tunnel_path("550e8400-e29b-41d4-a716-446655440000")
+                .unwrap(),
+            "/accounts/account/cfd_tunnel/550e8400-e29b-41d4-a716-446655440000"
+        );
+    }
+
+    #[test]
+    fn debug_leaves_out_the_token() {
+        let api = TunnelApi::new("account", "very-secret-token");
+        assert!(!format!("{api:?}").contains("very-secret-token"));
+    }
+}
@@ -0,0 +1,363 @@
+//! Routes to tunnels: DNS and load-balancer hostnames, private network
+//! CIDRs, and the virtual networks those live in.
+
+use http::Method;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::tunnel::parse_tunnel_identifier;
+
+use super::{PAGE_SIZE, TunnelApi, encode_component};
+
+/// API error codes meaning a route already exists: the tunnel route
+/// endpoint's and the DNS records API's "record with that host already
+/// exists".
+const CONFLICT_CODES: &[i64] = &[1003, 81053];
+
+/// What a hostname route request changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteChange {
+    /// The record already pointed at the tunnel.
+    Unchanged,
+    /// A record was created.
+    New,
+    /// An existing record was repointed at the tunnel.
+    Updated,
+}
+
+/// The outcome of [`TunnelApi::route_dns`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct DnsRoute {
+    /// The hostname as the zone stores it.
+    #[serde(rename = "name")]
+    pub hostname: String,
+    /// What happened to the hostname's CNAME record.
+    #[serde(rename = "cname")]
+    pub change: RouteChange,
+}
+
+/// The outcome of [`TunnelApi::route_load_balancer`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoadBalancerRoute {
+    /// What happened to the load balancer.
+    pub load_balancer: RouteChange,
+    /// What happened to the pool.
+    pub pool: RouteChange,
+}
+
+/// A private network route to a tunnel.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IpRoute {
+    /// The route id.
+    #[serde(rename = "id")]
+    pub identifier: String,
+    /// The routed network in CIDR notation.
+    pub network: String,
+    /// The tunnel the network is routed to.
+    #[serde(rename = "tunnel_id")]
+    pub tunnel_identifier: String,
+    /// The virtual network the route belongs to; `None` for the default.
+    #[serde(rename = "virtual_network_id", default)]
+    pub virtual_network: Option<String>,
+    /// A free-form description.
+    #[serde(default)]
+    pub comment: String,
+    /// When the route was created (RFC 3339).
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// When the route was deleted (RFC 3339), for deleted routes.
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+}
+
+/// Which routes [`TunnelApi::ip_routes`] returns.
+#[derive(Debug, Clone, Default)]
+pub struct IpRouteFilter {
+    /// Only routes to this tunnel.
+    pub tunnel: Option<String>,
+    /// Only routes in this virtual network.
+    pub virtual_network: Option<String>,
+    /// Include deleted routes, which are left out by default.
+    pub include_deleted: bool,
+}
+
+/// A virtual network, which lets private networks overlap.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VirtualNetwork {
+    /// The virtual network id.
+    #[serde(rename = "id")]
+    pub identifier: String,
+    /// The name, unique within the account.
+    pub name: String,
+    /// A free-form description.
+    #[serde(default)]
+    pub comment: String,
+    /// Whether routes without a virtual network land in this one.
+    #[serde(rename = "is_default_network", default)]
+    pub is_default: bool,
+    /// When the virtual network was created (RFC 3339).
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// When the virtual network was deleted (RFC 3339), for deleted ones.
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum HostnameRoute<'a> {
+    Dns {
+        user_hostname: &'a str,
+        overwrite_existing: bool,
+    },
+    Lb {
+        lb_name: &'a str,
+        lb_pool: &'a str,
+    },
+}
+
+#[derive(Serialize)]
+struct NewIpRoute<'a> {
+    network: &'a str,
+    tunnel_id: &'a str,
+    comment: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    virtual_network_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct NewVirtualNetwork<'a> {
+    name: &'a str,
+    comment: &'a str,
+    is_default: bool,
+}
+
+impl TunnelApi {
+    /// Points `hostname` in the zone `zone_tag` at the tunnel with a
+    /// proxied CNAME to `<tunnel-id>.cfargotunnel.com`, like `cloudflared
+    /// tunnel route dns`.
+    ///
+    /// An existing record for the hostname is replaced only with
+    /// `overwrite`; otherwise the request fails with
+    /// [`RouteConflict`](crate::tunnel::Error::RouteConflict).
+    pub async fn route_dns(
+        &self,
+        zone_tag: &str,
+        tunnel: &str,
+        hostname: &str,
+        overwrite: bool,
+    ) -> Result<DnsRoute> {
+        self.route_hostname(
+            zone_tag,
+            tunnel,
+            &HostnameRoute::Dns {
+                user_hostname: hostname,
+                overwrite_existing: overwrite,
+            },
+        )
+        .await
+    }
+
+    /// Adds the tunnel to the pool `pool` behind the load balancer
+    /// `load_balancer` in the zone `zone_tag`, creating either as needed,
+    /// like `cloudflared tunnel route lb`.
+    pub async fn route_load_balancer(
+        &self,
+        zone_tag: &str,
+        tunnel: &str,
+        load_balancer: &str,
+        pool: &str,
+    ) -> Result<LoadBalancerRoute> {
+        self.route_hostname(
+            zone_tag,
+            tunnel,
+            &HostnameRoute::Lb {
+                lb_name: load_balancer,
+                lb_pool: pool,
+            },
+        )
+        .await
+    }
+
+    /// Routes the private network `network` (CIDR notation) to the tunnel,
+    /// like `cloudflared tunnel route ip add`; `virtual_network` picks a
+    /// virtual network other than the default.
+    pub async fn add_ip_route(
+        &self,
+        network: &str,
+        tunnel: &str,
+        virtual_network: Option<&str>,
+        comment: &str,
+    ) -> Result<IpRoute> {
+        parse_tunnel_identifier(tunnel)?;
+        let body = to_json(&NewIpRoute {
+            network,
+            tunnel_id: tunnel,
+            comment,
+            virtual_network_id: virtual_network,
+        })?;
+        let path = format!("{}/teamnet/routes", self.account_path());
+        self.request(Method::POST, &path, Some(body))
+            .await
+            .and_then(|envelope| envelope.result())
+            .map_err(conflict)
+    }
+
+    /// Lists private network routes, following pagination.
+    pub async fn ip_routes(&self, filter: &IpRouteFilter) -> Result<Vec<IpRoute>> {
+        let mut query = format!("per_page={PAGE_SIZE}");
+        if let Some(tunnel) = &filter.tunnel {
+            parse_tunnel_identifier(tunnel)?;
+            query.push_str(&format!("&tunnel_id={tunnel}"));
+        }
+        if let Some(virtual_network) = &filter.virtual_network {
+            query.push_str(&format!(
+                "&virtual_network_id={}",
+                encode_component(virtual_network)
+            ));
+        }
+        if !filter.include_deleted {
+            query.push_str("&is_deleted=false");
+        }
+        self.paginate(&format!("{}/teamnet/routes?{query}", self.account_path()))
+            .await
+    }
+
+    /// Deletes a private network route by id.
+    pub async fn delete_ip_route(&self, route: &str) -> Result<()> {
+        let path = format!(
+            "{}/teamnet/routes/{}",
+            self.account_path(),
+            encode_component(route)
+        );
+        self.request::<IgnoredAny>(Method::DELETE, &path, None)
+            .await?;
+        Ok(())
+    }
+
+    /// Creates a virtual network, like `cloudflared tunnel vnet add`.
+    pub async fn create_virtual_network(
+        &self,
+        name: &str,
+        comment: &str,
+        is_default: bool,
+    ) -> Result<VirtualNetwork> {
+        let body = to_json(&NewVirtualNetwork {
+            name,
+            comment,
+            is_default,
+        })?;
+        let path = format!("{}/teamnet/virtual_networks", self.account_path());
+        self.request(Method::POST, &path, Some(body))
+            .await
+            .and_then(|envelope| envelope.result())
+            .map_err(conflict)
+    }
+
+    /// Lists the account's virtual networks.
+    pub async fn virtual_networks(&self) -> Result<Vec<VirtualNetwork>> {
+        let path = format!(
+            "{}/teamnet/virtual_networks?is_deleted=false",
+            self.account_path()
+        );
+        self.request(Method::GET, &path, None).await?.result()
+    }
+
+    /// Deletes a virtual network by id; it must have no routes left.
+    pub async fn delete_virtual_network(&self, virtual_network: &str) -> Result<()> {
+        let path = format!(
+            "{}/teamnet/virtual_networks/{}",
+            self.account_path(),
+            encode_component(virtual_network)
+        );
+        self.request::<IgnoredAny>(Method::DELETE, &path, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn route_hostname<T: serde::de::DeserializeOwned>(
+        &self,
+        zone_tag: &str,
+        tunnel: &str,
+        route: &HostnameRoute<'_>,
+    ) -> Result<T> {
+        parse_tunnel_identifier(tunnel)?;
+        let path = format!(
+            "/zones/{}/tunnels/{tunnel}/routes",
+            encode_component(zone_tag)
+        );
+        self.request(Method::PUT, &path, Some(to_json(route)?))
+            .await
+            .and_then(|envelope| envelope.result())
+            .map_err(conflict)
+    }
+}
+
+fn to_json(value: &impl Serialize) -> Result<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|e| Error::tunnel_api_response(e.to_string()))
+}
+
+/// Turns the API's "already exists" rejections into
+/// [`RouteConflict`](crate::tunnel::Error::RouteConflict).
+fn conflict(error: Error) -> Error {
+    match error {
+        Error::Tunnel(crate::tunnel::Error::TunnelApi {
+            status,
+            code,
+            message,
+        }) if status == 409 || code.is_some_and(|code| CONFLICT_CODES.contains(&code)) => {
+            Error::route_conflict(message)
+        }
+        error => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hostname_routes_serialize_like_cloudflared() {
+        let dns = HostnameRoute::Dns {
+            user_hostname: "app.example.com",
+            overwrite_existing: true,
+        };
+        assert_eq!(
+            serde_json::to_value(&dns).unwrap(),
+            serde_json::json!({
+                "type": "dns",
+                "user_hostname": "app.example.com",
+                "overwrite_existing": true,
+            })
+        );
+        let lb = HostnameRoute::Lb {
+            lb_name: "lb.example.com",
+            lb_pool: "pool",
+        };
+        assert_eq!(
+            serde_json::to_value(&lb).unwrap(),
+            serde_json::json!({"type": "lb", "lb_name": "lb.example.com", "lb_pool": "pool"})
+        );
+    }
+
+    #[test]
+    fn already_exists_errors_become_conflicts() {
+        let mapped = conflict(Error::tunnel_api(400, Some(1003), "record exists"));
+        assert!(matches!(
+            mapped,
+            Error::Tunnel(crate::tunnel::Error::RouteConflict(_))
+        ));
+        let mapped = conflict(Error::tunnel_api(409, None, "route exists"));
+        assert!(matches!(
+            mapped,
+            Error::Tunnel(crate::tunnel::Error::RouteConflict(_))
+        ));
+        let kept = conflict(Error::tunnel_api(403, Some(10000), "forbidden"));
+        assert!(matches!(
+            kept,
+            Error::Tunnel(crate::tunnel::Error::TunnelApi { status: 403, .. })
+        ));
+    }
+}
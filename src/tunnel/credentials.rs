@@ -0,0 +1,222 @@
+//! Credential providers: where a run gets the tunnel credentials it
+//! registers with.
+//!
+//! [`EdgeConnector`](crate::EdgeConnector) asks its provider before every
+//! registration, so credentials rotated by a secret manager take effect on
+//! the next reconnect without restarting the run.
+
+use std::future::Future;
+#[cfg(feature = "named-tunnel")]
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "named-tunnel")]
+use super::NamedTunnel;
+use super::Tunnel;
+
+/// A source of tunnel credentials.
+///
+/// A [`Tunnel`] is itself a provider that never changes. For rotation,
+/// use [`FileCredentials`], [`EnvCredentials`], [`FnCredentials`] or an
+/// implementation of your own.
+pub trait CredentialProvider: Send + Sync + 'static {
+    /// The credentials to register with next.
+    fn credentials(&self) -> Pin<Box<dyn Future<Output = Result<Tunnel>> + Send + '_>>;
+}
+
+impl CredentialProvider for Tunnel {
+    fn credentials(&self) -> Pin<Box<dyn Future<Output = Result<Tunnel>> + Send + '_>> {
+        Box::pin(std::future::ready(Ok(self.clone())))
+    }
+}
+
+/// Named tunnel credentials read from a file on every request, so the file
+/// can be replaced while the tunnel runs.
+///
+/// The file holds either a cloudflared credentials file's JSON or a
+/// connector token (as `cloudflared tunnel run --token-file` reads).
+#[cfg(feature = "named-tunnel")]
+#[derive(Debug, Clone)]
+pub struct FileCredentials {
+    path: PathBuf,
+}
+
+#[cfg(feature = "named-tunnel")]
+impl FileCredentials {
+    /// Credentials read from `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[cfg(feature = "named-tunnel")]
+impl CredentialProvider for FileCredentials {
+    fn credentials(&self) -> Pin<Box<dyn Future<Output = Result<Tunnel>> + Send + '_>> {
+        Box::pin(async move {
+            let contents = tokio::fs::read(&self.path).await.map_err(|e| {
+                Error::named_tunnel_credentials(format!(
+                    "failed to read {}: {e}",
+                    self.path.display()
+                ))
+            })?;
+            let tunnel = if contents.trim_ascii_start().starts_with(b"{") {
+                NamedTunnel::from_credentials_json(&contents)?
+            } else {
+                let token = std::str::from_utf8(&contents)
+                    .map_err(|_| Error::named_tunnel_credentials("token file is not UTF-8"))?;
+                NamedTunnel::from_token(token.trim())?
+            };
+            Ok(Tunnel::named(tunnel))
+        })
+    }
+}
+
+/// Named tunnel credentials from a connector token in an environment
+/// variable, read on every request.
+#[cfg(feature = "named-tunnel")]
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    variable: String,
+}
+
+#[cfg(feature = "named-tunnel")]
+impl EnvCredentials {
+    /// Credentials from the token in `variable`.
+    pub fn new(variable: impl Into<String>) -> Self {
+        Self {
+            variable: variable.into(),
+        }
+    }
+}
+
+#[cfg(feature = "named-tunnel")]
+impl Default for EnvCredentials {
+    /// `TUNNEL_TOKEN`, the variable cloudflared reads.
+    fn default() -> Self {
+        Self::new("TUNNEL_TOKEN")
+    }
+}
+
+#[cfg(feature = "named-tunnel")]
+impl CredentialProvider for EnvCredentials {
+    fn credentials(&self) -> Pin<Box<dyn Future<Output = Result<Tunnel>> + Send + '_>> {
+        let token = std::env::var(&self.variable)
+            .map_err(|e| Error::named_tunnel_credentials(format!("{}: {e}", self.variable)));
+        Box::pin(async move { Ok(Tunnel::named(NamedTunnel::from_token(token?.trim())?)) })
+    }
+}
+
+/// Credentials from an async function, e.g. a secret manager lookup.
+///
+/// The function's errors surface as
+/// [`CredentialProvider`](crate::tunnel::Error::CredentialProvider)
+/// errors.
+pub struct FnCredentials<F> {
+    fetch: F,
+}
+
+impl<F> FnCredentials<F> {
+    /// Credentials from `fetch`, called for every request.
+    pub fn new(fetch: F) -> Self {
+        Self { fetch }
+    }
+}
+
+impl<F> std::fmt::Debug for FnCredentials<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnCredentials").finish_non_exhaustive()
+    }
+}
+
+impl<F, Fut, E> CredentialProvider for FnCredentials<F>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = std::result::Result<Tunnel, E>> + Send + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn credentials(&self) -> Pin<Box<dyn Future<Output = Result<Tunnel>> + Send + '_>> {
+        let fetched = (self.fetch)();
+        Box::pin(async move { fetched.await.map_err(Error::credential_provider) })
+    }
+}
+
+/// Whether two tunnels register with the same credentials.
+#[cfg_attr(not(edge_conn), allow(dead_code))]
+pub(crate) fn same_credentials(first: &Tunnel, second: &Tunnel) -> bool {
+    first.account_tag() == second.account_tag()
+        && first.tunnel_identifier() == second.tunnel_identifier()
+        && first.tunnel_secret() == second.tunnel_secret()
+}
+
+#[cfg(all(test, feature = "named-tunnel"))]
+mod tests {
+    use base64::Engine as _;
+
+    use super::*;
+
+    const TUNNEL_ID: &str = "6ea05ba1-9e0e-4f0d-9e9e-3d0f0f0f0f0f";
+
+    fn token(secret: &[u8]) -> String {
+        let secret = base64::engine::general_purpose::STANDARD.encode(secret);
+        let payload = format!(r#"{{"a":"tag","s":"{secret}","t":"{TUNNEL_ID}"}}"#);
+        base64::engine::general_purpose::STANDARD.encode(payload)
+    }
+
+    #[tokio::test]
+    async fn file_credentials_follow_the_file() {
+        let path = std::env::temp_dir().join(format!(
+            "libcfd-test-file-credentials-{}",
+            std::process::id()
+        ));
+        let provider = FileCredentials::new(&path);
+        assert!(provider.credentials().await.is_err());
+
+        std::fs::write(&path, format!("{}\n", token(b"first"))).unwrap();
+        let first = provider.credentials().await.unwrap();
+        assert_eq!(first.tunnel_secret(), b"first");
+
+        let json =
+            format!(r#"{{"AccountTag":"tag","TunnelSecret":"c2Vjb25k","TunnelID":"{TUNNEL_ID}"}}"#);
+        std::fs::write(&path, json).unwrap();
+        let second = provider.credentials().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(second.tunnel_secret(), b"second");
+        assert!(!same_credentials(&first, &second));
+        assert!(same_credentials(&second, &second.clone()));
+    }
+
+    #[tokio::test]
+    async fn env_credentials_name_a_missing_variable() {
+        let variable = format!("LIBCFD_TEST_UNSET_TUNNEL_TOKEN_{}", std::process::id());
+        let error = EnvCredentials::new(&variable)
+            .credentials()
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains(&variable), "{error}");
+        assert_eq!(EnvCredentials::default().variable, "TUNNEL_TOKEN");
+    }
+
+    #[tokio::test]
+    async fn fn_credentials_wrap_fetch_errors() {
+        let provider =
+            FnCredentials::new(|| async { Err::<Tunnel, _>("secret manager unavailable") });
+        let error = provider.credentials().await.unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::Tunnel(crate::tunnel::Error::CredentialProvider(_))
+            ),
+            "{error:?}"
+        );
+
+        let provider = FnCredentials::new(|| async {
+            NamedTunnel::from_token(&token(b"fetched")).map(Tunnel::named)
+        });
+        assert_eq!(
+            provider.credentials().await.unwrap().tunnel_secret(),
+            b"fetched"
+        );
+    }
+}
@@ -1,14 +1,18 @@
-//! Errors from tunnel identities and the quick tunnel HTTP API.
+//! Errors from tunnel identities and the quick tunnel and Cloudflare APIs.
 
 use thiserror::Error;
 
-/// Errors from tunnel identities and the quick tunnel HTTP API.
+/// Errors from tunnel identities and the quick tunnel and Cloudflare APIs.
 #[derive(Debug, Error)]
 pub enum Error {
     /// The quick tunnel HTTP API rejected the request or returned an error.
     #[cfg(feature = "quick-tunnel")]
     #[error("quick tunnel API error: {0}")]
     QuickTunnelApi(String),
+    /// The quick tunnel HTTP API is rate limiting tunnel creation.
+    #[cfg(feature = "quick-tunnel")]
+    #[error("quick tunnel API is rate limiting requests: {0}")]
+    QuickTunnelRateLimited(String),
     /// The quick tunnel HTTP API response could not be parsed.
     #[cfg(feature = "quick-tunnel")]
     #[error("quick tunnel API response was malformed: {0}")]
@@ -21,6 +25,54 @@ pub enum Error {
     #[cfg(feature = "named-tunnel")]
     #[error("named tunnel credentials error: {0}")]
     NamedTunnelCredentials(String),
+    /// The Cloudflare API rejected a request.
+    #[cfg(feature = "tunnel-api")]
+    #[error("Cloudflare API returned status {status}: {message}")]
+    TunnelApi {
+        /// The HTTP status of the response.
+        status: u16,
+        /// The first Cloudflare error code in the response, if any.
+        code: Option<i64>,
+        /// The error messages from the response.
+        message: String,
+    },
+    /// A Cloudflare API response could not be parsed.
+    #[cfg(feature = "tunnel-api")]
+    #[error("Cloudflare API response was malformed: {0}")]
+    TunnelApiResponse(String),
+    /// The HTTP request to the Cloudflare API failed.
+    #[cfg(feature = "tunnel-api")]
+    #[error("Cloudflare API request failed: {0}")]
+    TunnelApiRequest(#[source] std::io::Error),
+    /// A route already exists: a DNS record for the hostname, or an IP
+    /// route or virtual network with the same network or name.
+    #[cfg(feature = "tunnel-api")]
+    #[error("route conflicts with an existing one: {0}")]
+    RouteConflict(String),
+    /// An ingress configuration the API would reject.
+    #[cfg(feature = "tunnel-api")]
+    #[error("invalid ingress configuration: {0}")]
+    InvalidConfiguration(String),
+    /// The stored tunnel configuration is not the version an update
+    /// expected.
+    #[cfg(feature = "tunnel-api")]
+    #[error("tunnel configuration is at version {current}, expected {expected}")]
+    ConfigurationVersion {
+        /// The version the update was based on.
+        expected: i32,
+        /// The version currently stored.
+        current: i32,
+    },
+    /// An origin certificate (`cert.pem`) could not be read or holds no
+    /// usable API token.
+    #[cfg(feature = "tunnel-api")]
+    #[error("invalid origin certificate: {0}")]
+    OriginCertificate(String),
+    /// A [`CredentialProvider`](crate::CredentialProvider) could not
+    /// produce credentials.
+    #[cfg(any_tunnel)]
+    #[error("credential provider failed: {0}")]
+    CredentialProvider(#[source] Box<dyn std::error::Error + Send + Sync>),
     /// A tunnel id could not be parsed as a UUID.
     #[error("invalid tunnel id: {0}")]
     InvalidTunnelIdentifier(String),
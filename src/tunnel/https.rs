@@ -0,0 +1,270 @@
+//! Minimal HTTPS client used for the quick tunnel and Cloudflare APIs.
+//!
+//! Hand-rolled HTTP/1.1 over tokio-rustls so the crate does not need a full
+//! HTTP client stack. One request per connection (`Connection: close`),
+//! with an optional JSON body.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::Method;
+use http::header::HeaderName;
+use rustls_pki_types::ServerName;
+use rustls_pki_types::pem::PemObject;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// How a request failed; each API maps these onto its own error variants.
+#[derive(Debug)]
+pub(crate) enum HttpsError {
+    /// Connecting, writing or reading failed.
+    Request(io::Error),
+    /// The URL or the response could not be used.
+    Response(String),
+    /// A step did not finish within the timeout.
+    Timeout(&'static str),
+}
+
+/// A client trusting the web PKI roots, plus an optional PEM bundle.
+#[derive(Clone)]
+pub(crate) struct HttpsClient {
+    connector: tokio_rustls::TlsConnector,
+    timeout: Duration,
+}
+
+impl HttpsClient {
+    pub(crate) fn new(ca_cert_pem: Option<&[u8]>, timeout: Duration) -> Self {
+        let configuration = rustls::ClientConfig::builder()
+            .with_root_certificates(root_store(ca_cert_pem))
+            .with_no_client_auth();
+        Self {
+            connector: tokio_rustls::TlsConnector::from(Arc::new(configuration)),
+            timeout,
+        }
+    }
+
+    /// Sends one request and returns the response status and body; a
+    /// `body` is sent as `application/json`.
+    pub(crate) async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(HeaderName, String)],
+        body: Option<&[u8]>,
+    ) -> Result<(u16, Vec<u8>), HttpsError> {
+        let uri = http::Uri::try_from(url)
+            .map_err(|e| HttpsError::Response(format!("invalid api url: {e}")))?;
+        let scheme = uri.scheme_str().unwrap_or("https");
+        if scheme != "https" {
+            return Err(HttpsError::Response(format!("unsupported scheme {scheme}")));
+        }
+        let host = uri
+            .host()
+            .ok_or_else(|| HttpsError::Response("missing host".into()))?;
+        let port = uri.port_u16().unwrap_or(443);
+        let path = if uri.path().is_empty() {
+            "/"
+        } else {
+            uri.path()
+        };
+        let query = uri.query().map(|q| format!("?{q}")).unwrap_or_default();
+
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| HttpsError::Response(format!("invalid host: {e}")))?;
+
+        let address = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(HttpsError::Request)?
+            .next()
+            .ok_or_else(|| HttpsError::Response(format!("could not resolve {host}:{port}")))?;
+
+        let tcp = tokio::time::timeout(self.timeout, TcpStream::connect(address))
+            .await
+            .map_err(|_| HttpsError::Timeout("connection timed out"))?
+            .map_err(HttpsError::Request)?;
+
+        let mut stream =
+            tokio::time::timeout(self.timeout, self.connector.connect(server_name, tcp))
+                .await
+                .map_err(|_| HttpsError::Timeout("tls handshake timed out"))?
+                .map_err(|e| HttpsError::Request(io::Error::other(e)))?;
+
+        let mut request =
+            format!("{method} {path}{query} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n");
+        if let Some(body) = body {
+            request.push_str(&format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                body.len()
+            ));
+        } else if method != Method::GET {
+            request.push_str("Content-Length: 0\r\n");
+        }
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+
+        let mut message = request.into_bytes();
+        message.extend_from_slice(body.unwrap_or_default());
+        stream
+            .write_all(&message)
+            .await
+            .map_err(HttpsError::Request)?;
+        stream.flush().await.map_err(HttpsError::Request)?;
+
+        let mut buffer = Vec::new();
+        tokio::time::timeout(self.timeout, stream.read_to_end(&mut buffer))
+            .await
+            .map_err(|_| HttpsError::Timeout("response timed out"))?
+            .map_err(HttpsError::Request)?;
+
+        let (status, _headers, body) = parse_http_response(&buffer)?;
+        Ok((status, body))
+    }
+}
+
+fn root_store(ca_cert_pem: Option<&[u8]>) -> rustls::RootCertStore {
+    let mut store = rustls::RootCertStore::empty();
+    store
+        .roots
+        .extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(pem) = ca_cert_pem {
+        for cert in rustls_pki_types::CertificateDer::pem_slice_iter(pem).flatten() {
+            if let Err(e) = store.add(cert) {
+                tracing::warn!("ignoring unusable CA certificate: {e}");
+            }
+        }
+    }
+    store
+}
+
+/// Parses a minimal HTTP/1.1 response (status line, headers, body),
+/// decoding chunked transfer encoding.
+type HttpResponse = (u16, Vec<(String, String)>, Vec<u8>);
+
+fn parse_http_response(bytes: &[u8]) -> Result<HttpResponse, HttpsError> {
+    let malformed = |message: &str| HttpsError::Response(message.to_string());
+    let header_end = bytes
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| malformed("missing response headers"))?;
+    let head = std::str::from_utf8(&bytes[..header_end])
+        .map_err(|_| malformed("response headers not utf-8"))?;
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().ok_or_else(|| malformed("empty status line"))?;
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| malformed("malformed status line"))?
+        .parse()
+        .map_err(|_| malformed("malformed status code"))?;
+
+    let mut headers = Vec::new();
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse::<usize>().ok();
+        } else if name.eq_ignore_ascii_case("transfer-encoding")
+            && value.to_ascii_lowercase().contains("chunked")
+        {
+            chunked = true;
+        }
+        headers.push((name.to_string(), value.to_string()));
+    }
+
+    let body = &bytes[header_end + 4..];
+    let body = if chunked {
+        decode_chunked(body)?
+    } else {
+        match content_length {
+            Some(length) if length <= body.len() => body[..length].to_vec(),
+            _ => body.to_vec(),
+        }
+    };
+    Ok((status, headers, body))
+}
+
+/// Decodes a chunked body; trailers after the last chunk are ignored.
+fn decode_chunked(mut data: &[u8]) -> Result<Vec<u8>, HttpsError> {
+    let malformed = |message: &str| HttpsError::Response(message.to_string());
+    let mut out = Vec::new();
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| malformed("chunk size line unterminated"))?;
+        let size = std::str::from_utf8(&data[..line_end])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| malformed("malformed chunk size"))?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if data.len() < size + 2 {
+            return Err(malformed("chunk body truncated"));
+        }
+        out.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_chunked, parse_http_response};
+
+    #[test]
+    fn parses_http_response_with_content_length() {
+        let bytes =
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 5\r\n\r\nhello";
+        let (status, headers, body) = parse_http_response(bytes).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(
+            headers[0],
+            ("Content-Type".to_string(), "application/json".to_string())
+        );
+        assert_eq!(body, b"hello");
+    }
+
+    #[test]
+    fn parses_http_response_without_content_length() {
+        let bytes = b"HTTP/1.1 500 Internal Server Error\r\nConnection: close\r\n\r\nboom";
+        let (status, _headers, body) = parse_http_response(bytes).unwrap();
+        assert_eq!(status, 500);
+        assert_eq!(body, b"boom");
+    }
+
+    #[test]
+    fn parses_chunked_http_response() {
+        let bytes = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6;x=y\r\n world\r\n0\r\n\r\n";
+        let (_status, _headers, body) = parse_http_response(bytes).unwrap();
+        assert_eq!(body, b"hello world");
+        assert!(decode_chunked(b"5\r\nhel").is_err());
+        assert!(decode_chunked(b"zz\r\n").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_http_response() {
+        // No header terminator at all.
+        assert!(parse_http_response(b"HTTP/1.1 200 OK\r\nX: y").is_err());
+        assert!(parse_http_response(b"").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_status_line() {
+        assert!(parse_http_response(b"garbage\r\n\r\n").is_err());
+        assert!(parse_http_response(b"HTTP/1.1 OK\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn rejects_non_utf8_headers() {
+        assert!(parse_http_response(b"HTTP/1.1 200 OK\r\nX: \xff\xfe\r\n\r\n").is_err());
+    }
+}
@@ -1,8 +1,14 @@
 //! Tunnel identities: quick tunnels and named tunnels.
 
+#[cfg(feature = "tunnel-api")]
+mod api;
+#[cfg(any_tunnel)]
+mod credentials;
 mod error;
 pub use error::Error;
 
+#[cfg(any(feature = "quick-tunnel", feature = "tunnel-api"))]
+mod https;
 pub(crate) mod secret;
 
 #[cfg(feature = "named-tunnel")]
@@ -12,6 +18,20 @@ mod quick;
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "tunnel-api")]
+pub use api::{
+    ConfigSource, CreatedTunnel, DEFAULT_API_URL, DnsRoute, IngressConfiguration, IngressRule,
+    IpRoute, IpRouteFilter, LoadBalancerRoute, OriginCertificate, OriginRequest, RouteChange,
+    TunnelApi, TunnelApiOptions, TunnelConfiguration, TunnelConnection, TunnelFilter, TunnelInfo,
+    VirtualNetwork, WarpRouting,
+};
+#[cfg(any_tunnel)]
+#[cfg(edge_conn)]
+pub(crate) use credentials::same_credentials;
+#[cfg(any_tunnel)]
+pub use credentials::{CredentialProvider, FnCredentials};
+#[cfg(feature = "named-tunnel")]
+pub use credentials::{EnvCredentials, FileCredentials};
 #[cfg(feature = "named-tunnel")]
 pub use named::NamedTunnel;
 #[cfg(feature = "quick-tunnel")]
@@ -1,5 +1,6 @@
 //! Named tunnel identity loaded from a cloudflared credentials file.
 
+use std::io::Write as _;
 use std::path::Path;
 
 use base64::Engine as _;
@@ -36,7 +37,12 @@ impl NamedTunnel {
         let bytes = std::fs::read(path.as_ref()).map_err(|e| {
             Error::named_tunnel_credentials(format!("failed to read credentials file: {e}"))
         })?;
-        let tunnel: NamedTunnel = serde_json::from_slice(&bytes)
+        Self::from_credentials_json(&bytes)
+    }
+
+    /// Parses the contents of a cloudflared credentials file.
+    pub(crate) fn from_credentials_json(bytes: &[u8]) -> Result<NamedTunnel> {
+        let tunnel: NamedTunnel = serde_json::from_slice(bytes)
             .map_err(|e| Error::named_tunnel_credentials(e.to_string()))?;
         if tunnel.tunnel_identifier.is_empty() {
             return Err(Error::named_tunnel_credentials(
@@ -46,6 +52,28 @@ impl NamedTunnel {
         Ok(tunnel)
     }
 
+    /// Writes the credentials as a cloudflared credentials file, readable
+    /// only by its owner (mode 0400 on Unix, as cloudflared does). An
+    /// existing file is never overwritten.
+    pub fn write_credentials_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json =
+            serde_json::to_vec(self).map_err(|e| Error::named_tunnel_credentials(e.to_string()))?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o400);
+        let mut file = options.open(path).map_err(|e| {
+            Error::named_tunnel_credentials(format!(
+                "failed to create credentials file {}: {e}",
+                path.display()
+            ))
+        })?;
+        file.write_all(&json).map_err(|e| {
+            Error::named_tunnel_credentials(format!("failed to write credentials file: {e}"))
+        })
+    }
+
     /// Parses a Cloudflare dashboard connector token into tunnel
     /// credentials.
     ///
@@ -169,6 +197,33 @@ mod tests {
         assert!(NamedTunnel::from_token(&token).is_err());
     }
 
+    #[test]
+    fn credentials_file_round_trips_and_is_never_overwritten() {
+        let path = std::env::temp_dir().join(format!(
+            "libcfd-test-named-written-credentials-{}.json",
+            std::process::id()
+        ));
+        std::fs::remove_file(&path).ok();
+        let tunnel = NamedTunnel {
+            account_tag: "abc123".into(),
+            tunnel_secret: b"top-secret".to_vec(),
+            tunnel_identifier: "550e8400-e29b-41d4-a716-446655440000".into(),
+            endpoint: None,
+        };
+        tunnel.write_credentials_file(&path).unwrap();
+        let loaded = NamedTunnel::from_credentials_file(&path).unwrap();
+        assert_eq!(loaded.tunnel_secret, b"top-secret");
+        assert_eq!(loaded.tunnel_identifier, tunnel.tunnel_identifier);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o400);
+        }
+        assert!(tunnel.write_credentials_file(&path).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
     #[test]
     fn credentials_file_loader_rejects_missing_file() {
         let path = std::env::temp_dir().join("libcfd-test-named-missing-credentials.json");
@@ -1,18 +1,21 @@
 //! Quick tunnel identity and the trycloudflare.com HTTP API client.
 
-mod api;
-
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, Result};
 
+use super::https::{HttpsClient, HttpsError};
 use super::parse_tunnel_identifier;
 
 /// Default quick tunnel service (trycloudflare.com).
 pub const DEFAULT_QUICK_SERVICE_URL: &str = "https://api.trycloudflare.com";
 
+/// API error codes meaning "slow down": Cloudflare's rate limiting (1015)
+/// and request throttling (971).
+const RATE_LIMIT_CODES: &[i64] = &[971, 1015];
+
 /// Options for [`create_quick_tunnel`].
 #[derive(Debug, Clone)]
 pub struct QuickTunnelOptions {
@@ -105,14 +108,30 @@ struct QuickTunnelError {
 /// Requests a new quick tunnel from the service.
 ///
 /// This mirrors `cloudflared tunnel --url` with no account: the service
-/// assigns the tunnel and returns its credentials.
+/// assigns the tunnel and returns its credentials. A rate-limited request
+/// fails with
+/// [`QuickTunnelRateLimited`](crate::tunnel::Error::QuickTunnelRateLimited).
 pub async fn create_quick_tunnel(options: &QuickTunnelOptions) -> Result<QuickTunnel> {
     let url = format!("{}/tunnel", options.service_url.trim_end_matches('/'));
     let headers = vec![(
         http::header::USER_AGENT,
         format!("cloudflared/{}", env!("CARGO_PKG_VERSION")),
     )];
-    let (status, body) = api::post_empty(&url, &headers, options.http_timeout).await?;
+    // An empty JSON POST, as cloudflared sends.
+    let (status, body) = HttpsClient::new(None, options.http_timeout)
+        .send(http::Method::POST, &url, &headers, Some(&[]))
+        .await
+        .map_err(|e| match e {
+            HttpsError::Request(e) => Error::quick_tunnel_request(e),
+            HttpsError::Response(message) => Error::quick_tunnel_response(message),
+            HttpsError::Timeout(message) => Error::quick_tunnel_api(message),
+        })?;
+    if status == 429 {
+        let message = String::from_utf8_lossy(&body);
+        return Err(Error::quick_tunnel_rate_limited(format!(
+            "service returned status {status}: {message}"
+        )));
+    }
     if status >= 300 {
         let message = String::from_utf8_lossy(&body);
         return Err(Error::quick_tunnel_api(format!(
@@ -127,6 +146,16 @@ pub(crate) fn parse_response(body: &[u8]) -> Result<QuickTunnel> {
     let data: QuickTunnelResponse =
         serde_json::from_slice(body).map_err(|e| Error::quick_tunnel_response(e.to_string()))?;
     if !data.success {
+        if let Some(error) = data
+            .errors
+            .iter()
+            .find(|e| RATE_LIMIT_CODES.contains(&e.code))
+        {
+            return Err(Error::quick_tunnel_rate_limited(format!(
+                "{}: {}",
+                error.code, error.message
+            )));
+        }
         let message = data
             .errors
             .first()
@@ -225,6 +254,19 @@ mod tests {
         assert!(error.to_string().contains("10000: nope"), "{error}");
     }
 
+    #[test]
+    fn rate_limit_codes_are_typed() {
+        let body = br#"{"success":false,"errors":[{"code":1015,"message":"rate limited"}]}"#;
+        let error = parse_response(body).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::Tunnel(crate::tunnel::Error::QuickTunnelRateLimited(_))
+            ),
+            "{error:?}"
+        );
+    }
+
     #[test]
     fn rejects_quick_tunnel_with_invalid_secret_base64() {
         let body = br#"{"tunnel_id":"6ea05ba1-9e0e-4f0d-9e9e-3d0f0f0f0f0f","name":"","hostname":"x.trycloudflare.com","account_tag":"a","secret":"not!base64"}"#;
@@ -32,6 +32,20 @@ fn send_check_tunnel() -> libcfd::Tunnel {
     })
 }
 
+/// An origin answering every request with an empty `200`.
+#[cfg(edge_conn)]
+fn ok_origin() -> libcfd::Origin {
+    libcfd::Origin::http(
+        |_request: libcfd::Request, respond: libcfd::HttpResponder| {
+            respond.send(libcfd::Response::new(
+                http::StatusCode::OK,
+                http::HeaderMap::new(),
+                libcfd::Body::empty(),
+            ));
+        },
+    )
+}
+
 /// Every public future must be `Send`; this is a compile-time guarantee the
 /// crate commits to. The futures are constructed but never polled, so no
 /// I/O happens.
@@ -40,6 +54,23 @@ fn send_check_tunnel() -> libcfd::Tunnel {
 fn public_futures_are_send() {
     fn assert_send<T: Send>(_value: T) {}
 
+    {
+        let mut request = libcfd::Request::new(
+            http::Method::GET,
+            http::Uri::from_static("/chat"),
+            http::HeaderMap::new(),
+            libcfd::Body::empty(),
+        );
+        request.headers.insert(
+            "sec-websocket-key",
+            http::HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="),
+        );
+        let upgrade = libcfd::WebSocketUpgrade::from_request(&request).unwrap();
+        let (_connection, socket) = upgrade.accept();
+        let message = libcfd::origin::websocket::Message::Text("ping".into());
+        assert_send(socket.send(message));
+        assert_send(socket.close(None));
+    }
     #[cfg(feature = "quick-tunnel")]
     {
         let options = libcfd::QuickTunnelOptions::default();
@@ -68,24 +99,23 @@ fn public_futures_are_send() {
     #[cfg(edge_conn)]
     {
         let tunnel = send_check_tunnel();
-        let origin = libcfd::Origin::http(
-            |_request: libcfd::Request, respond: libcfd::HttpResponder| {
-                respond.send(libcfd::Response::new(
-                    http::StatusCode::OK,
-                    http::HeaderMap::new(),
-                    libcfd::Body::empty(),
-                ));
-            },
-        );
+        let origin = ok_origin();
         let shutdown = async {};
         let connector = libcfd::EdgeConnector::new(libcfd::EdgeOptions::default());
-        assert_send(connector.run(tunnel, origin, shutdown));
+        assert_send(connector.run(tunnel, origin.clone(), shutdown));
+        assert_send(connector.run_with_credentials(send_check_tunnel(), origin.clone(), async {}));
+        #[cfg(feature = "quick-tunnel")]
+        {
+            let options = libcfd::QuickSupervisorOptions::default();
+            assert_send(connector.run_quick_supervised(None, origin, async {}, &options));
+        }
     }
 }
 
 /// The public error and RPC types must be `Send + Sync`.
 #[test]
 fn public_error_types_are_send_and_sync() {
+    assert_send_sync::<libcfd::OriginError>();
     assert_send_sync::<libcfd::Error>();
     assert_send_sync::<libcfd_rpc::RpcError>();
     assert_send_sync::<libcfd_rpc::tunnel::RegistrationFailure>();
@@ -139,6 +169,31 @@ fn default_configuration_json_is_valid_ingress() {
     assert!(value["ingress"].is_array());
 }
 
+/// The callback types of public option fields can be named outside the
+/// crate.
+#[cfg(edge_conn)]
+#[test]
+fn callback_option_types_are_public() {
+    let render: libcfd::ErrorPageRenderer =
+        std::sync::Arc::new(|_headers: &http::HeaderMap, _error: &libcfd::OriginError| None);
+    let options = libcfd::EdgeOptions {
+        error_page: Some(render),
+        ..libcfd::EdgeOptions::default()
+    };
+    assert!(options.error_page.is_some());
+
+    #[cfg(feature = "quick-tunnel")]
+    {
+        let on_new_tunnel: libcfd::NewTunnelCallback =
+            std::sync::Arc::new(|_tunnel: &libcfd::QuickTunnel| {});
+        let options = libcfd::QuickSupervisorOptions {
+            on_new_tunnel: Some(on_new_tunnel),
+            ..libcfd::QuickSupervisorOptions::default()
+        };
+        assert!(options.on_new_tunnel.is_some());
+    }
+}
+
 /// The default transport matches the enabled edge features.
 #[cfg(edge_conn)]
 #[test]
@@ -151,3 +206,100 @@ fn default_transport_matches_enabled_features() {
     #[cfg(all(not(quic_any), feature = "h2-edge"))]
     assert_eq!(options.transport, libcfd::Transport::H2);
 }
+
+/// A connector that has not run reports cloudflared's not-ready `/ready`.
+#[cfg(edge_conn)]
+#[test]
+fn idle_connector_is_not_ready() {
+    let connector = libcfd::EdgeConnector::new(libcfd::EdgeOptions::default());
+    let readiness = connector.readiness();
+    assert_eq!(readiness.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    let value: serde_json::Value = serde_json::from_str(&readiness.to_json()).unwrap();
+    assert_eq!(value["readyConnections"], 0);
+    assert_eq!(value["connectorId"], readiness.connector_id());
+    assert_eq!(readiness.connector_id().len(), 36);
+}
+
+/// A connector's diagnostics bundle has the cloudflared-style files even
+/// before it runs.
+#[cfg(edge_conn)]
+#[tokio::test]
+async fn idle_connector_bundles_diagnostics() {
+    let connector = libcfd::EdgeConnector::new(libcfd::EdgeOptions::default());
+    let bundle = connector.diagnostics().bundle().await;
+    for name in [
+        "system.txt",
+        "options.txt",
+        "connections.txt",
+        "readiness.json",
+    ] {
+        assert!(bundle.file(name).is_some(), "missing {name}");
+    }
+    assert!(
+        bundle
+            .file("system.txt")
+            .unwrap()
+            .contains(connector.readiness().connector_id())
+    );
+}
+
+/// A supervised quick tunnel keeps retrying creation while the service is
+/// unreachable and still stops cleanly on shutdown.
+#[cfg(all(feature = "quick-tunnel", edge_conn))]
+#[tokio::test]
+async fn supervised_quick_tunnel_stops_while_creation_fails() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let new_tunnels = Arc::new(AtomicUsize::new(0));
+    let options = libcfd::QuickSupervisorOptions {
+        quick_tunnel: libcfd::QuickTunnelOptions {
+            // Nothing listens on the discard port.
+            service_url: "https://127.0.0.1:9".into(),
+            http_timeout: Duration::from_secs(1),
+        },
+        backoff: Duration::from_millis(10),
+        on_new_tunnel: Some(Arc::new({
+            let new_tunnels = new_tunnels.clone();
+            move |_: &libcfd::QuickTunnel| {
+                new_tunnels.fetch_add(1, Ordering::SeqCst);
+            }
+        })),
+        ..libcfd::QuickSupervisorOptions::default()
+    };
+    let origin = ok_origin();
+    let connector = libcfd::EdgeConnector::new(libcfd::EdgeOptions::default());
+    let shutdown = tokio::time::sleep(Duration::from_millis(300));
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        connector.run_quick_supervised(None, origin, shutdown, &options),
+    )
+    .await
+    .expect("shutdown should end the supervised run")
+    .unwrap();
+    assert_eq!(new_tunnels.load(Ordering::SeqCst), 0);
+}
+
+/// A credential provider that fails before the first registration ends the
+/// run with its error.
+#[cfg(edge_conn)]
+#[tokio::test]
+async fn failing_credential_provider_ends_the_run() {
+    let credentials = libcfd::FnCredentials::new(|| async {
+        Err::<libcfd::Tunnel, _>("secret manager unavailable")
+    });
+    let origin = ok_origin();
+    let connector = libcfd::EdgeConnector::new(libcfd::EdgeOptions::default());
+    let error = connector
+        .run_with_credentials(credentials, origin, std::future::pending())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            error,
+            libcfd::Error::Tunnel(libcfd::tunnel::Error::CredentialProvider(_))
+        ),
+        "{error:?}"
+    );
+}
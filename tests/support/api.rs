@@ -0,0 +1,165 @@
+//! A stand-in Cloudflare API for the offline `TunnelApi` tests.
+//!
+//! Serves HTTPS on a loopback port with a certificate from the test CA in
+//! `tests/fixtures`, records every request, and answers with whatever the
+//! test's handler returns. Responses are chunked, as the real API's are.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// The CA that signed the stand-in's certificate.
+pub const CA_PEM: &[u8] = include_bytes!("../fixtures/api-ca.pem");
+const SERVER_PEM: &[u8] = include_bytes!("../fixtures/api-server.pem");
+const SERVER_KEY: &[u8] = include_bytes!("../fixtures/api-server.key");
+
+/// A request the stand-in received.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    /// The request method.
+    pub method: String,
+    /// The path, including the query.
+    pub path: String,
+    /// Headers in order of appearance.
+    pub headers: Vec<(String, String)>,
+    /// The request body.
+    pub body: Vec<u8>,
+}
+
+impl Recorded {
+    /// The first header called `name`, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body parsed as JSON.
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("request body should be JSON")
+    }
+}
+
+type Handler = dyn Fn(&Recorded) -> (u16, String) + Send + Sync;
+
+/// A running stand-in API.
+pub struct StandInApi {
+    /// The base URL to hand to `TunnelApiOptions`.
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl StandInApi {
+    /// Starts serving; `handler` maps each request to a status and a JSON
+    /// body.
+    pub async fn start(
+        handler: impl Fn(&Recorded) -> (u16, String) + Send + Sync + 'static,
+    ) -> Self {
+        let certs = CertificateDer::pem_slice_iter(SERVER_PEM)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("fixture certificate");
+        let key = PrivateKeyDer::from_pem_slice(SERVER_KEY).expect("fixture key");
+        let configuration = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .expect("server configuration");
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(configuration));
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let port = listener.local_addr().expect("local address").port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((tcp, _)) = listener.accept().await {
+                    let acceptor = acceptor.clone();
+                    let requests = requests.clone();
+                    let handler = handler.clone();
+                    tokio::spawn(async move {
+                        let Ok(mut stream) = acceptor.accept(tcp).await else {
+                            return;
+                        };
+                        let Some(request) = read_request(&mut stream).await else {
+                            return;
+                        };
+                        let (status, body) = handler(&request);
+                        requests.lock().unwrap().push(request);
+                        let response = format!(
+                            "HTTP/1.1 {status} Stand-In\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{body}\r\n0\r\n\r\n",
+                            body.len()
+                        );
+                        let _ = stream.write_all(response.as_bytes()).await;
+                        let _ = stream.shutdown().await;
+                    });
+                }
+            }
+        });
+        Self {
+            base_url: format!("https://127.0.0.1:{port}/client/v4"),
+            requests,
+        }
+    }
+
+    /// Options pointing a `TunnelApi` at this stand-in.
+    pub fn options(&self) -> libcfd::TunnelApiOptions {
+        libcfd::TunnelApiOptions {
+            base_url: self.base_url.clone(),
+            ca_cert_pem: Some(CA_PEM.to_vec()),
+            http_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Reads one request: the head, then a `Content-Length` body.
+async fn read_request(stream: &mut (impl tokio::io::AsyncRead + Unpin)) -> Option<Recorded> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8(buffer[..header_end].to_vec()).ok()?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = buffer[header_end + 4..].to_vec();
+    while body.len() < length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Some(Recorded {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
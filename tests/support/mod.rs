@@ -2,11 +2,16 @@
 //!
 //! The live-edge tests (`tests/live_quick.rs`, `tests/live_named.rs`) reuse
 //! these helpers: the HTTPS client, the tunnel state managers, the origin
-//! handlers, and the run/poll/shutdown scaffolding. Nothing here is exposed
-//! by the `libcfd` crate itself.
+//! handlers, and the run/poll/shutdown scaffolding. `tests/tunnel_api.rs`
+//! uses the stand-in Cloudflare API. Nothing here is exposed by the
+//! `libcfd` crate itself.
 
 #![allow(dead_code)]
 
+/// A stand-in Cloudflare API for the `TunnelApi` tests.
+#[cfg(feature = "tunnel-api")]
+pub mod api;
+
 /// Minimal HTTPS client used to verify public tunnel hostnames.
 #[cfg(feature = "quick-tunnel")]
 pub mod http;